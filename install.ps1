@@ -124,6 +124,10 @@ Write-Host "  th k               - Kubernetes login" -ForegroundColor $Yellow
 Write-Host "  th d               - Database login" -ForegroundColor $Yellow
 Write-Host "  th update          - Update to latest version" -ForegroundColor $Yellow
 Write-Host ""
+Write-Host "🔧 Setup:" -ForegroundColor $Blue
+Write-Host "Add to your PowerShell profile (notepad `$PROFILE):" -ForegroundColor $Blue
+Write-Host "th shell-init powershell | Out-String | Invoke-Expression" -ForegroundColor $Yellow
+Write-Host ""
 Write-Host "🚀 Quick start:" -ForegroundColor $Blue
 Write-Host "th" -ForegroundColor $Yellow
 Write-Host ""
@@ -116,27 +116,11 @@ echo -e "${BLUE}📝 Creating wrapper script at ${WRAPPER_SCRIPT}...${NC}"
 tee "$WRAPPER_SCRIPT" > /dev/null << 'EOF'
 #!/bin/bash
 
-# Wrapper script for th - sources credentials after execution
-function th() {
-    # Run the actual th binary
-    command th "$@"
-    local exit_code=$?
-    
-    # Source any credential files that were created
-    for cred_file in /tmp/yl_* /tmp/admin_*; do
-        if [[ -f "$cred_file" ]]; then
-            source "$cred_file"
-            break
-        fi
-    done
-    
-    return $exit_code
-}
-
-# If script is being sourced, just define the function
-# If script is being executed directly, run th with passed arguments  
-if [[ "${BASH_SOURCE[0]}" == "${0}" ]]; then
-    th "$@"
+# Kept for rc files that still source it - `eval "$(th shell-init zsh)"` does the same
+if [ -n "$ZSH_VERSION" ]; then
+    eval "$(command th shell-init zsh)"
+else
+    eval "$(command th shell-init bash)"
 fi
 EOF
 
@@ -153,12 +137,12 @@ echo -e "  ${YELLOW}th d${NC}               - Database login"
 echo -e "  ${YELLOW}th update${NC}          - Update to latest version"
 echo
 echo -e "${BLUE}🔧 Setup:${NC}"
-echo -e "Add to your shell profile (~/.zshrc or ~/.bash_profile):"
+echo -e "Add to your shell profile (~/.zshrc, ~/.bash_profile, or see th shell-init -h for fish):"
 echo -e "${YELLOW}export PATH=\"\$HOME/.local/bin:\$PATH\"${NC}"
-echo -e "${YELLOW}source \$HOME/.local/bin/th.sh${NC}"
+echo -e "${YELLOW}eval \"\$(th shell-init zsh)\"${NC}   (or bash)"
 echo
 echo -e "${BLUE}🚀 Quick start:${NC}"
-echo -e "${YELLOW}export PATH=\"\$HOME/.local/bin:\$PATH\" && source \$HOME/.local/bin/th.sh && th${NC}"
+echo -e "${YELLOW}export PATH=\"\$HOME/.local/bin:\$PATH\" && eval \"\$(th shell-init)\" && th${NC}"
 echo
 
 # Update PATH for current session
@@ -0,0 +1,127 @@
+use crate::config::edit::write_atomic;
+use crate::error::ThError;
+use anyhow::Result;
+use std::path::PathBuf;
+
+/// Marks a profile as written by `th aws sync-profiles`; it is replaced or removed on the next sync
+const MANAGED_MARKER: &str = "aws credential-process";
+
+/// A `[profile <name>]` section
+#[derive(Debug, Clone)]
+pub struct AwsProfile {
+    pub name: String,
+    pub settings: Vec<(String, String)>,
+}
+
+impl AwsProfile {
+    fn render(&self) -> String {
+        let mut section = format!("[profile {}]\n", self.name);
+        for (key, value) in &self.settings {
+            section.push_str(&format!("{} = {}\n", key, value));
+        }
+        section
+    }
+}
+
+/// What a sync changed, by profile name
+#[derive(Debug, Default)]
+pub struct SyncReport {
+    pub added: Vec<String>,
+    pub updated: Vec<String>,
+    pub removed: Vec<String>,
+    /// Profiles you wrote yourself under a name th wanted, left alone
+    pub skipped: Vec<String>,
+}
+
+/// `$AWS_CONFIG_FILE`, or `~/.aws/config`
+pub fn config_path() -> Result<PathBuf> {
+    if let Some(path) = std::env::var_os("AWS_CONFIG_FILE").filter(|p| !p.is_empty()) {
+        return Ok(PathBuf::from(path));
+    }
+    let home = dirs::home_dir()
+        .ok_or_else(|| ThError::Config("Could not determine home directory".to_string()))?;
+    Ok(home.join(".aws").join("config"))
+}
+
+/// Where `th aws credential-process` keeps a copy of the CA an app's endpoint proxy
+/// serves, for its profile's `ca_bundle`
+pub fn ca_bundle_path(app: &str) -> Result<PathBuf> {
+    Ok(config_path()?.with_file_name(format!("th-{}-ca.pem", app.replace('/', "+"))))
+}
+
+/// One `[...]` section with the lines under it; the lines before the first header have no name
+struct Section {
+    name: Option<String>,
+    lines: Vec<String>,
+}
+
+impl Section {
+    fn is_managed(&self) -> bool {
+        self.lines.iter().any(|line| line.trim_start().starts_with("credential_process") && line.contains(MANAGED_MARKER))
+    }
+}
+
+fn parse_sections(content: &str) -> Vec<Section> {
+    let mut sections = vec![Section { name: None, lines: Vec::new() }];
+    for line in content.lines() {
+        let trimmed = line.trim();
+        if let Some(header) = trimmed.strip_prefix('[').and_then(|h| h.strip_suffix(']')) {
+            // `[profile name]`, or `[default]` which takes no prefix
+            let name = header.trim().strip_prefix("profile ").unwrap_or(header.trim()).trim();
+            sections.push(Section { name: Some(name.to_string()), lines: Vec::new() });
+        }
+        sections.last_mut().unwrap().lines.push(line.to_string());
+    }
+    sections
+}
+
+/// Replace the profiles th wrote before with `profiles`, leaving every other section as it was
+pub fn sync_profiles(profiles: &[AwsProfile]) -> Result<SyncReport> {
+    let path = config_path()?;
+    let content = std::fs::read_to_string(&path).unwrap_or_default();
+    let mut report = SyncReport::default();
+    let mut written = Vec::new();
+    let mut out = String::new();
+
+    for section in parse_sections(&content) {
+        let wanted = section.name.as_ref().and_then(|name| profiles.iter().find(|p| &p.name == name));
+        match wanted {
+            Some(profile) if !section.is_managed() => {
+                report.skipped.push(profile.name.clone());
+                written.push(profile.name.clone());
+                for line in &section.lines {
+                    out.push_str(line);
+                    out.push('\n');
+                }
+            }
+            Some(profile) => {
+                let rendered = profile.render();
+                let existing: String = section.lines.iter().map(|l| format!("{}\n", l)).collect();
+                if existing.trim_end() != rendered.trim_end() {
+                    report.updated.push(profile.name.clone());
+                }
+                out.push_str(&rendered);
+                out.push('\n');
+                written.push(profile.name.clone());
+            }
+            None if section.is_managed() => report.removed.extend(section.name),
+            None => {
+                for line in &section.lines {
+                    out.push_str(line);
+                    out.push('\n');
+                }
+            }
+        }
+    }
+
+    for profile in profiles.iter().filter(|p| !written.contains(&p.name)) {
+        if !out.is_empty() && !out.ends_with("\n\n") {
+            out.push('\n');
+        }
+        out.push_str(&profile.render());
+        report.added.push(profile.name.clone());
+    }
+
+    write_atomic(&path, &format!("{}\n", out.trim_end_matches('\n')))?;
+    Ok(report)
+}
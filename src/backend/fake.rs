@@ -0,0 +1,325 @@
+use super::{ProxyRequest, TeleportBackend, FAKE_TSH_ENV};
+use crate::error::ThError;
+use crate::models::resources::{parse_list, qualify, KubeCluster};
+use crate::process::ProcessOutput;
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::sync::Mutex;
+
+/// Canned tsh responses, loaded from the JSON file named by `TH_FAKE_TSH`
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default)]
+pub struct FakeFixture {
+    /// Version reported by `tsh version`, e.g. `"11.3.0"` to script a too-old tsh
+    pub tsh_version: Option<String>,
+    /// `tsh status --format=json` output; absent means logged out
+    pub status: Option<Value>,
+    /// Status to report after a successful `tsh login`
+    pub login_status: Option<Value>,
+    /// `tsh clusters --format=json` items; defaults to just the root cluster
+    pub clusters: Vec<Value>,
+    /// `tsh apps ls --format=json` items; a `"cluster": "<leaf>"` field puts one in a leaf cluster
+    pub apps: Vec<Value>,
+    /// `tsh kube ls -f json` items
+    pub kube_clusters: Vec<Value>,
+    /// `tsh db ls --format=json` items
+    pub databases: Vec<Value>,
+    /// AWS roles offered per app by `tsh apps login <app>`
+    pub aws_roles: HashMap<String, Vec<String>>,
+    /// Context reported by `kubectl config current-context` before any `tsh kube login`
+    pub kube_context: Option<String>,
+    /// Kube clusters where `kubectl auth can-i` answers yes
+    pub kube_write_access: Vec<String>,
+    /// Variables printed by the fake `tsh proxy aws`
+    pub aws_credentials: BTreeMap<String, String>,
+    /// Request ID handed out by `tsh request create`
+    pub request_id: Option<String>,
+    /// Operation name -> error message, e.g. `"kube_login": "access denied"`
+    pub errors: HashMap<String, String>,
+}
+
+impl FakeFixture {
+    pub fn load(path: &str) -> Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&content)?)
+    }
+}
+
+#[derive(Debug, Default)]
+struct FakeState {
+    status: Option<Value>,
+    kube_cluster: Option<String>,
+}
+
+/// Scriptable stand-in for tsh so flows can run without a Teleport cluster
+pub struct FakeBackend {
+    fixture: FakeFixture,
+    state: Mutex<FakeState>,
+}
+
+impl FakeBackend {
+    pub fn new(fixture: FakeFixture) -> Self {
+        let state = FakeState {
+            status: fixture.status.clone(),
+            kube_cluster: None,
+        };
+        Self {
+            fixture,
+            state: Mutex::new(state),
+        }
+    }
+
+    pub fn from_file(path: &str) -> Result<Self> {
+        let fixture = FakeFixture::load(path)
+            .map_err(|e| ThError::InvalidInput(format!("Could not load fake tsh fixture {}: {}", path, e)))?;
+        Ok(Self::new(fixture))
+    }
+
+    /// Fail with the scripted error for `operation`, if there is one
+    fn check(&self, operation: &str) -> Result<()> {
+        match self.fixture.errors.get(operation) {
+            Some(message) => Err(ThError::Process(format!("Command 'tsh' failed: {}", message)).into()),
+            None => Ok(()),
+        }
+    }
+
+    fn current_status(&self) -> Option<Value> {
+        self.state.lock().unwrap().status.clone()
+    }
+}
+
+fn output(stdout: String, stderr: String, success: bool) -> ProcessOutput {
+    ProcessOutput { stdout, stderr, success }
+}
+
+/// Fixture items whose `cluster` field is `leaf` (absent means the root cluster)
+fn in_cluster(items: &[Value], leaf: Option<&str>) -> Value {
+    Value::Array(items.iter()
+        .filter(|item| item.get("cluster").and_then(|c| c.as_str()) == leaf)
+        .cloned()
+        .collect())
+}
+
+fn role_arn(role: &str) -> String {
+    format!("arn:aws:iam::123456789012:role/{}", role)
+}
+
+#[async_trait]
+impl TeleportBackend for FakeBackend {
+    async fn version(&self) -> Result<String> {
+        self.check("version")?;
+        let version = self.fixture.tsh_version.as_deref().unwrap_or("16.0.0");
+        Ok(format!("Teleport v{} git:fake", version))
+    }
+
+    async fn status(&self) -> Result<Value> {
+        self.check("status")?;
+        match self.current_status() {
+            Some(status) => Ok(status),
+            None => Err(ThError::Process("Command 'tsh' failed: Not logged in.".to_string()).into()),
+        }
+    }
+
+    async fn login(&self, _auth_type: &str, proxy: &str, _request_id: Option<&str>) -> Result<()> {
+        if self.check("login").is_err() {
+            return Err(ThError::AuthFailed("Teleport login failed".to_string()).into());
+        }
+
+        let mut status = self.fixture.login_status.clone()
+            .or_else(|| self.fixture.status.clone())
+            .unwrap_or_else(|| json!({
+                "active": {
+                    "profile_url": format!("https://{}", proxy),
+                    "username": "fake.user",
+                    "cluster": "fake",
+                    "roles": [],
+                }
+            }));
+        // Like tsh, logging in makes the proxy's profile the active one
+        if let Some(active) = status.get_mut("active").and_then(|a| a.as_object_mut()) {
+            active.insert("profile_url".to_string(), json!(format!("https://{}", proxy)));
+        }
+        self.state.lock().unwrap().status = Some(status);
+        Ok(())
+    }
+
+    async fn logout(&self) -> Result<()> {
+        self.check("logout")?;
+        self.state.lock().unwrap().status = None;
+        Ok(())
+    }
+
+    async fn list_clusters(&self) -> Result<Value> {
+        self.check("list_clusters")?;
+        if !self.fixture.clusters.is_empty() {
+            return Ok(Value::Array(self.fixture.clusters.clone()));
+        }
+        Ok(json!([{ "cluster_name": "fake", "status": "online", "cluster_type": "root", "selected": true }]))
+    }
+
+    async fn list_apps(&self, leaf: Option<&str>) -> Result<Value> {
+        self.check("list_apps")?;
+        Ok(in_cluster(&self.fixture.apps, leaf))
+    }
+
+    async fn list_kube_clusters(&self, leaf: Option<&str>) -> Result<Value> {
+        self.check("list_kube_clusters")?;
+        Ok(in_cluster(&self.fixture.kube_clusters, leaf))
+    }
+
+    async fn list_databases(&self, leaf: Option<&str>) -> Result<Value> {
+        self.check("list_databases")?;
+        Ok(in_cluster(&self.fixture.databases, leaf))
+    }
+
+    async fn app_login(&self, app: &str, aws_role: Option<&str>, _leaf: Option<&str>) -> Result<ProcessOutput> {
+        if let Err(e) = self.check("app_login") {
+            return Ok(output(String::new(), format!("ERROR: {}\n", e), false));
+        }
+
+        let roles = self.fixture.aws_roles.get(app).cloned().unwrap_or_default();
+        match aws_role {
+            Some(role) if roles.iter().any(|r| r == role) => {
+                Ok(output(format!("Logged into AWS app \"{}\".\n", app), String::new(), true))
+            }
+            Some(role) => Ok(output(
+                String::new(),
+                format!("ERROR: IAM role \"{}\" not found for app \"{}\"\n", role, app),
+                false,
+            )),
+            None if roles.len() == 1 => Ok(output(
+                format!("Logged into AWS app \"{}\".\n\nYour IAM role:\n  {}\n", app, role_arn(&roles[0])),
+                String::new(),
+                true,
+            )),
+            None => {
+                let mut table = String::from("Available AWS roles:\nRole Name Role ARN\n--------- --------\n");
+                for role in &roles {
+                    table.push_str(&format!("{} {}\n", role, role_arn(role)));
+                }
+                table.push_str("\nERROR: --aws-role flag is required\n");
+                Ok(output(String::new(), table, false))
+            }
+        }
+    }
+
+    async fn app_logout(&self, _app: Option<&str>, _leaf: Option<&str>) -> Result<()> {
+        self.check("app_logout")
+    }
+
+    async fn kube_login(&self, cluster: &str, leaf: Option<&str>) -> Result<()> {
+        self.check("kube_login")?;
+        let clusters: Vec<KubeCluster> = parse_list(in_cluster(&self.fixture.kube_clusters, leaf));
+        let known = clusters.iter().any(|c| c.name == cluster);
+        if !known {
+            return Err(ThError::Process(format!("Command 'tsh' failed: kubernetes cluster \"{}\" not found", cluster)).into());
+        }
+        self.state.lock().unwrap().kube_cluster = Some(cluster.to_string());
+        Ok(())
+    }
+
+    async fn kube_can_i(&self, _verb: &str, _resource: &str) -> Result<bool> {
+        self.check("kube_can_i")?;
+        let state = self.state.lock().unwrap();
+        Ok(state.kube_cluster.as_ref()
+            .map(|cluster| self.fixture.kube_write_access.contains(cluster))
+            .unwrap_or(false))
+    }
+
+    async fn kube_current_context(&self) -> Result<Option<String>> {
+        self.check("kube_current_context")?;
+        let state = self.state.lock().unwrap();
+        Ok(state.kube_cluster.clone().or_else(|| self.fixture.kube_context.clone()))
+    }
+
+    async fn db_login(&self, _db: &str, _db_user: Option<&str>, _db_name: Option<&str>, _leaf: Option<&str>) -> Result<()> {
+        self.check("db_login")
+    }
+
+    async fn db_connect(&self, db: &str, db_user: Option<&str>, db_name: Option<&str>, _leaf: Option<&str>) -> Result<()> {
+        self.check("db_connect")?;
+        println!(
+            "[fake tsh] connected to {} as {} (database {})",
+            db,
+            db_user.unwrap_or("-"),
+            db_name.unwrap_or("-"),
+        );
+        Ok(())
+    }
+
+    async fn request_create(&self, roles: &str, reason: &str, _max_duration: Option<&str>) -> Result<ProcessOutput> {
+        if let Err(e) = self.check("request_create") {
+            return Ok(output(String::new(), format!("ERROR: {}\n", e), false));
+        }
+
+        let request_id = self.fixture.request_id.clone()
+            .unwrap_or_else(|| "00000000-0000-0000-0000-000000000000".to_string());
+        Ok(output(
+            format!("Creating request...\nRequest ID: {}\nRoles:      {}\nReason:     {}\n", request_id, roles, reason),
+            String::new(),
+            true,
+        ))
+    }
+
+    fn proxy_command(&self, request: &ProxyRequest) -> Result<std::process::Command> {
+        self.check("proxy")?;
+
+        // The fake proxy is this binary re-run with the hidden `fake-proxy` command
+        let mut command = std::process::Command::new(std::env::current_exe()?);
+        command.arg("fake-proxy");
+        match request {
+            ProxyRequest::Aws { app, port, leaf, endpoint_url } => {
+                command.args(["aws", &qualify(leaf.as_deref(), app), "--port", &port.to_string()]);
+                if *endpoint_url {
+                    command.arg("--endpoint-url");
+                }
+            }
+            ProxyRequest::Db { db, port, .. } => {
+                command.args(["db", db, "--port", &port.to_string()]);
+            }
+        }
+        Ok(command)
+    }
+}
+
+/// Body of the hidden `th fake-proxy` command: print what tsh would, then hold a port open
+pub async fn run_fake_proxy(kind: &str, target: &str, port: Option<u16>, endpoint_url: bool) -> Result<()> {
+    let listener = tokio::net::TcpListener::bind(("127.0.0.1", port.unwrap_or(0))).await?;
+    let port = listener.local_addr()?.port();
+
+    let mut stdout = std::io::stdout();
+    match kind {
+        "aws" => {
+            let fixture = match std::env::var(FAKE_TSH_ENV) {
+                Ok(path) => FakeFixture::load(&path)?,
+                Err(_) => FakeFixture::default(),
+            };
+            let mut credentials = fixture.aws_credentials;
+            credentials.entry("AWS_ACCESS_KEY_ID".to_string()).or_insert_with(|| "FAKEACCESSKEY".to_string());
+            credentials.entry("AWS_SECRET_ACCESS_KEY".to_string()).or_insert_with(|| "fake-secret".to_string());
+            if endpoint_url {
+                writeln!(stdout, "Started AWS proxy which serves as an AWS endpoint URL at https://localhost:{}.\n", port)?;
+                writeln!(stdout, "In addition to the endpoint URL, use the following credentials to connect to the proxy for {}:", target)?;
+            } else {
+                credentials.insert("HTTPS_PROXY".to_string(), format!("http://127.0.0.1:{}", port));
+                writeln!(stdout, "Started AWS proxy on http://127.0.0.1:{}.\n", port)?;
+                writeln!(stdout, "Use the following credentials and HTTPS proxy setting to connect to the proxy for {}:", target)?;
+            }
+            for (key, value) in credentials {
+                writeln!(stdout, "  export {}={}", key, value)?;
+            }
+        }
+        _ => {
+            writeln!(stdout, "Started authenticated tunnel for the database \"{}\" on 127.0.0.1:{}.", target, port)?;
+        }
+    }
+    stdout.flush()?;
+
+    loop {
+        let _ = listener.accept().await?;
+    }
+}
@@ -0,0 +1,107 @@
+pub mod fake;
+pub mod tsh;
+
+use crate::config::Config;
+use crate::process::ProcessOutput;
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::Arc;
+
+pub use fake::FakeBackend;
+pub use tsh::TshBackend;
+
+/// Environment variable pointing at a fake tsh fixture file
+pub const FAKE_TSH_ENV: &str = "TH_FAKE_TSH";
+
+/// A long-running `tsh proxy` that a backend knows how to start.
+/// A `port` of 0 lets `proxies::start` assign the resource's stable port.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum ProxyRequest {
+    Aws {
+        app: String,
+        #[serde(default)]
+        port: u16,
+        /// Leaf cluster the app is in; `None` for the root cluster
+        #[serde(default)]
+        leaf: Option<String>,
+        /// Serve an AWS endpoint URL (`--endpoint-url`) rather than an HTTPS proxy, for
+        /// `~/.aws/config` profiles
+        #[serde(default)]
+        endpoint_url: bool,
+    },
+    Db {
+        db: String,
+        db_user: Option<String>,
+        db_name: Option<String>,
+        port: u16,
+        /// Leaf cluster the database is in; `None` for the root cluster
+        leaf: Option<String>,
+    },
+}
+
+/// Everything th needs from Teleport. `TshBackend` shells out to tsh, `FakeBackend` answers from a fixture.
+/// `leaf` routes a call to a leaf (trusted) cluster; `None` means the root cluster.
+#[async_trait]
+pub trait TeleportBackend: Send + Sync {
+    /// `tsh version`
+    async fn version(&self) -> Result<String>;
+
+    /// `tsh status --format=json`
+    async fn status(&self) -> Result<Value>;
+
+    /// `tsh login`, optionally assuming an approved access request
+    async fn login(&self, auth_type: &str, proxy: &str, request_id: Option<&str>) -> Result<()>;
+
+    /// `tsh logout`
+    async fn logout(&self) -> Result<()>;
+
+    /// `tsh clusters --format=json` - the root cluster and its leaves
+    async fn list_clusters(&self) -> Result<Value>;
+
+    /// `tsh apps ls --format=json`
+    async fn list_apps(&self, leaf: Option<&str>) -> Result<Value>;
+
+    /// `tsh kube ls -f json`
+    async fn list_kube_clusters(&self, leaf: Option<&str>) -> Result<Value>;
+
+    /// `tsh db ls --format=json`
+    async fn list_databases(&self, leaf: Option<&str>) -> Result<Value>;
+
+    /// `tsh apps login <app> [--aws-role <role>]`, returning the raw output
+    async fn app_login(&self, app: &str, aws_role: Option<&str>, leaf: Option<&str>) -> Result<ProcessOutput>;
+
+    /// `tsh apps logout [app]` - every app when `app` is `None`
+    async fn app_logout(&self, app: Option<&str>, leaf: Option<&str>) -> Result<()>;
+
+    /// `tsh kube login <cluster>`
+    async fn kube_login(&self, cluster: &str, leaf: Option<&str>) -> Result<()>;
+
+    /// `kubectl auth can-i <verb> <resource>` against the selected cluster
+    async fn kube_can_i(&self, verb: &str, resource: &str) -> Result<bool>;
+
+    /// `kubectl config current-context`; `None` when no context is selected
+    async fn kube_current_context(&self) -> Result<Option<String>>;
+
+    /// `tsh db login <db>`
+    async fn db_login(&self, db: &str, db_user: Option<&str>, db_name: Option<&str>, leaf: Option<&str>) -> Result<()>;
+
+    /// `tsh db connect <db>` (interactive)
+    async fn db_connect(&self, db: &str, db_user: Option<&str>, db_name: Option<&str>, leaf: Option<&str>) -> Result<()>;
+
+    /// `tsh request create`, returning the raw output
+    async fn request_create(&self, roles: &str, reason: &str, max_duration: Option<&str>) -> Result<ProcessOutput>;
+
+    /// Build the command for a `tsh proxy` so callers can choose where its output goes
+    fn proxy_command(&self, request: &ProxyRequest) -> Result<std::process::Command>;
+}
+
+/// Pick the backend for this run: the fake when `TH_FAKE_TSH` is set, real tsh otherwise
+pub fn from_env(config: &Config) -> Result<Arc<dyn TeleportBackend>> {
+    match std::env::var(FAKE_TSH_ENV) {
+        Ok(path) if !path.is_empty() => Ok(Arc::new(FakeBackend::from_file(&path)?)),
+        _ => Ok(Arc::new(TshBackend::new(config.clone()))),
+    }
+}
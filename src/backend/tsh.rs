@@ -0,0 +1,202 @@
+use super::{ProxyRequest, TeleportBackend};
+use crate::config::Config;
+use crate::error::ThError;
+use crate::process::{execute_command, execute_command_json, execute_command_silent, execute_command_with_output, ProcessOutput};
+use crate::tools::{self, Tool};
+use anyhow::Result;
+use async_trait::async_trait;
+use serde_json::Value;
+use std::process::Stdio;
+
+/// The real backend - every call is a `tsh` invocation
+pub struct TshBackend {
+    config: Config,
+}
+
+impl TshBackend {
+    pub fn new(config: Config) -> Self {
+        Self { config }
+    }
+
+    fn tsh(&self) -> &str {
+        tools::program(&self.config, Tool::Tsh)
+    }
+}
+
+/// Append `--cluster` when talking to a leaf cluster
+fn push_leaf_flag(args: &mut Vec<String>, leaf: Option<&str>) {
+    if let Some(leaf) = leaf {
+        args.push(format!("--cluster={}", leaf));
+    }
+}
+
+/// Run `tsh <args> [--cluster=<leaf>]` and parse its JSON output
+async fn tsh_json(tsh: &str, args: &[&str], leaf: Option<&str>) -> Result<Value> {
+    let mut args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+    push_leaf_flag(&mut args, leaf);
+    let args: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+    execute_command_json(tsh, &args).await
+}
+
+/// Append `--db-user`/`--db-name` flags when set
+fn push_db_flags(args: &mut Vec<String>, db_user: Option<&str>, db_name: Option<&str>) {
+    if let Some(user) = db_user {
+        args.push(format!("--db-user={}", user));
+    }
+    if let Some(name) = db_name {
+        args.push(format!("--db-name={}", name));
+    }
+}
+
+#[async_trait]
+impl TeleportBackend for TshBackend {
+    async fn version(&self) -> Result<String> {
+        execute_command(self.tsh(), &["version"]).await
+    }
+
+    async fn status(&self) -> Result<Value> {
+        execute_command_json(self.tsh(), &["status", "--format=json"]).await
+    }
+
+    async fn login(&self, auth_type: &str, proxy: &str, request_id: Option<&str>) -> Result<()> {
+        let mut args = vec![
+            "login".to_string(),
+            format!("--auth={}", auth_type),
+            format!("--proxy={}", proxy),
+        ];
+        if let Some(request_id) = request_id {
+            args.push(format!("--request-id={}", request_id));
+        }
+
+        // Browser-based SSO - keep tsh's own chatter off the screen
+        let status = tokio::process::Command::new(self.tsh())
+            .args(&args)
+            .stdin(Stdio::inherit())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .await?;
+
+        if !status.success() {
+            return Err(ThError::AuthFailed("Teleport login failed".to_string()).into());
+        }
+
+        Ok(())
+    }
+
+    async fn logout(&self) -> Result<()> {
+        execute_command_silent(self.tsh(), &["logout"]).await?;
+        Ok(())
+    }
+
+    async fn list_clusters(&self) -> Result<Value> {
+        execute_command_json(self.tsh(), &["clusters", "--format=json"]).await
+    }
+
+    async fn list_apps(&self, leaf: Option<&str>) -> Result<Value> {
+        tsh_json(self.tsh(), &["apps", "ls", "--format=json"], leaf).await
+    }
+
+    async fn list_kube_clusters(&self, leaf: Option<&str>) -> Result<Value> {
+        tsh_json(self.tsh(), &["kube", "ls", "-f", "json"], leaf).await
+    }
+
+    async fn list_databases(&self, leaf: Option<&str>) -> Result<Value> {
+        tsh_json(self.tsh(), &["db", "ls", "--format=json"], leaf).await
+    }
+
+    async fn app_login(&self, app: &str, aws_role: Option<&str>, leaf: Option<&str>) -> Result<ProcessOutput> {
+        let mut args = vec!["apps".to_string(), "login".to_string(), app.to_string()];
+        if let Some(role) = aws_role {
+            args.extend(["--aws-role".to_string(), role.to_string()]);
+        }
+        push_leaf_flag(&mut args, leaf);
+        let args: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+        execute_command_with_output(self.tsh(), &args).await
+    }
+
+    async fn app_logout(&self, app: Option<&str>, leaf: Option<&str>) -> Result<()> {
+        let mut args = vec!["apps".to_string(), "logout".to_string()];
+        args.extend(app.map(|app| app.to_string()));
+        push_leaf_flag(&mut args, leaf);
+        let args: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+        execute_command_silent(self.tsh(), &args).await?;
+        Ok(())
+    }
+
+    async fn kube_login(&self, cluster: &str, leaf: Option<&str>) -> Result<()> {
+        let mut args = vec!["kube".to_string(), "login".to_string(), cluster.to_string()];
+        push_leaf_flag(&mut args, leaf);
+        let args: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+        execute_command(self.tsh(), &args).await?;
+        Ok(())
+    }
+
+    async fn kube_can_i(&self, verb: &str, resource: &str) -> Result<bool> {
+        execute_command_silent(tools::program(&self.config, Tool::Kubectl), &["auth", "can-i", verb, resource]).await
+    }
+
+    async fn kube_current_context(&self) -> Result<Option<String>> {
+        let output = execute_command_with_output(tools::program(&self.config, Tool::Kubectl), &["config", "current-context"]).await?;
+        let context = output.stdout.trim();
+        Ok(Some(context.to_string()).filter(|_| output.success && !context.is_empty()))
+    }
+
+    async fn db_login(&self, db: &str, db_user: Option<&str>, db_name: Option<&str>, leaf: Option<&str>) -> Result<()> {
+        let mut args = vec!["db".to_string(), "login".to_string(), db.to_string()];
+        push_db_flags(&mut args, db_user, db_name);
+        push_leaf_flag(&mut args, leaf);
+        let args: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+        execute_command(self.tsh(), &args).await?;
+        Ok(())
+    }
+
+    async fn db_connect(&self, db: &str, db_user: Option<&str>, db_name: Option<&str>, leaf: Option<&str>) -> Result<()> {
+        let mut args = vec!["db".to_string(), "connect".to_string(), db.to_string()];
+        push_db_flags(&mut args, db_user, db_name);
+        push_leaf_flag(&mut args, leaf);
+
+        tokio::process::Command::new(self.tsh())
+            .args(&args)
+            .stdin(Stdio::inherit())
+            .stdout(Stdio::inherit())
+            .stderr(Stdio::inherit())
+            .status()
+            .await?;
+
+        Ok(())
+    }
+
+    async fn request_create(&self, roles: &str, reason: &str, max_duration: Option<&str>) -> Result<ProcessOutput> {
+        let mut args = vec!["request", "create", "--roles", roles];
+        if let Some(duration) = max_duration {
+            args.extend(["--max-duration", duration]);
+        }
+        args.extend(["--reason", reason]);
+        execute_command_with_output(self.tsh(), &args).await
+    }
+
+    fn proxy_command(&self, request: &ProxyRequest) -> Result<std::process::Command> {
+        let mut command = std::process::Command::new(self.tsh());
+        match request {
+            ProxyRequest::Aws { app, port, leaf, endpoint_url } => {
+                let mut args = vec!["proxy".to_string(), "aws".to_string(), "--app".to_string(), app.clone()];
+                push_leaf_flag(&mut args, leaf.as_deref());
+                args.push(format!("--port={}", port));
+                if *endpoint_url {
+                    args.push("--endpoint-url".to_string());
+                }
+                command.args(args);
+            }
+            ProxyRequest::Db { db, db_user, db_name, port, leaf } => {
+                let mut args = vec!["proxy".to_string(), "db".to_string(), db.clone()];
+                push_db_flags(&mut args, db_user.as_deref(), db_name.as_deref());
+                push_leaf_flag(&mut args, leaf.as_deref());
+                args.push(format!("--port={}", port));
+                args.push("--tunnel".to_string());
+                command.args(args);
+            }
+        }
+        Ok(command)
+    }
+}
@@ -11,6 +11,10 @@ pub struct Cli {
     #[arg(short = 'h', long = "help")]
     pub help: bool,
     
+    /// Teleport profile from `[profiles.<name>]` to use (also `TH_PROFILE`)
+    #[arg(long, global = true)]
+    pub profile: Option<String>,
+    
     #[command(subcommand)]
     pub command: Option<Commands>,
 }
@@ -41,6 +45,25 @@ pub enum Commands {
     #[command(alias = "l")]
     Logout(LogoutArgs),
     
+    /// Inspect th configuration
+    Config(ConfigArgs),
+    
+    /// Manage background tsh proxies
+    Proxy(ProxyArgs),
+    
+    /// Show the Teleport session, exported credentials and running proxies
+    #[command(alias = "s")]
+    Status(StatusArgs),
+    
+    /// Run one command with an environment's credentials
+    Exec(ExecArgs),
+    
+    /// Print the shell function that lets th set variables in your shell
+    ShellInit(ShellInitArgs),
+    
+    /// Print a short kube/AWS/expiry segment for your prompt, from th's local state only
+    Prompt(PromptArgs),
+    
     /// Show version information  
     #[command(short_flag = 'v')]
     Version,
@@ -67,6 +90,10 @@ pub enum Commands {
     /// Clear update cache (for testing)
     #[command(hide = true)]
     ClearUpdateCache,
+    
+    /// Stand-in for `tsh proxy` when running against a fake tsh fixture
+    #[command(hide = true)]
+    FakeProxy(FakeProxyArgs),
 }
 
 #[derive(Args)]
@@ -77,9 +104,14 @@ pub struct KubeArgs {
     
     /// Environment to connect to (dev, staging, prod, etc.)
     pub environment: Option<String>,
+    
+    /// Open a subshell logged in to the cluster instead of changing ~/.kube/config
+    #[arg(long)]
+    pub shell: bool,
 }
 
 #[derive(Args)]
+#[command(args_conflicts_with_subcommands = true)]
 pub struct AwsArgs {
     /// Show help information
     #[arg(short = 'h', long = "help")]
@@ -90,6 +122,70 @@ pub struct AwsArgs {
     
     /// Sudo flag - pass "s" to use sudo role (exactly like bash version)
     pub sudo_flag: Option<String>,
+    
+    /// Open a subshell with the credentials instead of exporting them
+    #[arg(long)]
+    pub shell: bool,
+    
+    #[command(subcommand)]
+    pub command: Option<AwsCommand>,
+}
+
+#[derive(Subcommand)]
+pub enum AwsCommand {
+    /// Print credentials as JSON for the AWS CLI's `credential_process`, starting the proxy if needed
+    CredentialProcess {
+        /// Environment from `[aws]`
+        #[arg(long)]
+        env: String,
+        
+        /// AWS role to use instead of the environment's default
+        #[arg(long)]
+        role: Option<String>,
+
+        /// Serve the account through an endpoint URL proxy, as the synced profiles do
+        #[arg(long)]
+        endpoint_url: bool,
+    },
+    
+    /// Print a session's credentials as commands for your shell, or as JSON
+    Env {
+        /// Environment from `[aws]`; the default session when left out
+        env: Option<String>,
+        
+        /// Syntax to print; detected from $SHELL when left out
+        #[arg(long, value_enum)]
+        shell: Option<crate::shell::Shell>,
+        
+        /// Print the commands that remove the credentials instead
+        #[arg(long)]
+        unset: bool,
+        
+        /// AWS role to use instead of the environment's default
+        #[arg(long)]
+        role: Option<String>,
+        
+        /// Use the sudo role
+        #[arg(short, long)]
+        sudo: bool,
+        
+        /// Only read a session that is already running: no login, no new proxy
+        #[arg(long)]
+        no_login: bool,
+    },
+    
+    /// Write a ~/.aws/config profile for every environment in `[aws]`
+    SyncProfiles,
+    
+    /// List the AWS accounts with a running session
+    #[command(alias = "sessions")]
+    LsSessions,
+    
+    /// End the session for one account, leaving the others running
+    Logout {
+        /// Environment from `[aws]`, or an account name
+        env: String,
+    },
 }
 
 #[derive(Args)]
@@ -114,6 +210,10 @@ pub struct LoginArgs {
     /// Show help information
     #[arg(short = 'h', long = "help")]
     pub help: bool,
+    
+    /// Log in again unless the certificate lasts at least this long, e.g. `2h` or `1h30m`
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+    pub ensure: Option<chrono::Duration>,
 }
 
 #[derive(Args)]
@@ -123,6 +223,187 @@ pub struct LogoutArgs {
     pub help: bool,
 }
 
+#[derive(Args)]
+pub struct ConfigArgs {
+    /// Show help information
+    #[arg(short = 'h', long = "help")]
+    pub help: bool,
+    
+    #[command(subcommand)]
+    pub command: Option<ConfigCommand>,
+}
+
+#[derive(Subcommand)]
+pub enum ConfigCommand {
+    /// Print the merged configuration
+    Show {
+        /// Annotate each value with the layer it came from
+        #[arg(long)]
+        origin: bool,
+    },
+    
+    /// Print one value, e.g. `teleport.proxy` or `aws.dev`
+    Get {
+        key: String,
+    },
+    
+    /// Set a value in your config file
+    Set {
+        key: String,
+        value: String,
+        
+        /// Write to the project's .th.toml instead
+        #[arg(long)]
+        project: bool,
+    },
+    
+    /// Remove a value from your config file
+    Unset {
+        key: String,
+        
+        /// Remove from the project's .th.toml instead
+        #[arg(long)]
+        project: bool,
+    },
+    
+    /// Open your config file in $EDITOR
+    Edit {
+        /// Edit the project's .th.toml instead
+        #[arg(long)]
+        project: bool,
+    },
+    
+    /// Check every config layer for mistakes
+    Validate,
+    
+    /// Show where config files are read from
+    Path,
+    
+    /// Rebuild the env → account/cluster/database maps from Teleport labels
+    Sync {
+        /// Show what would change without writing anything
+        #[arg(long)]
+        dry_run: bool,
+        
+        /// Write to the project's .th.toml instead
+        #[arg(long)]
+        project: bool,
+    },
+    
+    /// Back up and remove your config file, returning to defaults
+    Reset {
+        /// Don't ask for confirmation
+        #[arg(short, long)]
+        yes: bool,
+    },
+}
+
+#[derive(Args)]
+pub struct ProxyArgs {
+    /// Show help information
+    #[arg(short = 'h', long = "help")]
+    pub help: bool,
+    
+    #[command(subcommand)]
+    pub command: Option<ProxyCommand>,
+}
+
+#[derive(Subcommand)]
+pub enum ProxyCommand {
+    /// List running proxies
+    #[command(alias = "list")]
+    Ls,
+    
+    /// Stop a proxy by id, or every proxy with `all`
+    Stop {
+        id: String,
+    },
+    
+    /// Stop a proxy and start it again on the same port
+    Restart {
+        id: u32,
+    },
+    
+    /// Restart proxies that die and renew expired certificates until none are left
+    Watch {
+        /// Run in the background, logging to the runtime dir's logs/watchdog.log
+        #[arg(short, long)]
+        detach: bool,
+    },
+    
+    /// Print a proxy's output
+    Logs {
+        id: u32,
+        
+        /// Keep printing new output until interrupted
+        #[arg(short, long)]
+        follow: bool,
+    },
+}
+
+#[derive(Args)]
+pub struct StatusArgs {
+    /// Show help information
+    #[arg(short = 'h', long = "help")]
+    pub help: bool,
+    
+    /// Print as JSON
+    #[arg(long)]
+    pub json: bool,
+}
+
+#[derive(Args)]
+pub struct ExecArgs {
+    /// Show help information
+    #[arg(short = 'h', long = "help")]
+    pub help: bool,
+    
+    /// Environment to run in (dev, staging, prod, etc.)
+    pub environment: Option<String>,
+    
+    /// Also log in to the environment's Kubernetes cluster
+    #[arg(long)]
+    pub kube: bool,
+    
+    /// AWS role to use instead of the environment's default
+    #[arg(long)]
+    pub role: Option<String>,
+    
+    /// Use the sudo role
+    #[arg(short, long)]
+    pub sudo: bool,
+    
+    /// Command to run, after `--`
+    #[arg(last = true)]
+    pub command: Vec<String>,
+}
+
+#[derive(Args)]
+pub struct ShellInitArgs {
+    /// Show help information
+    #[arg(short = 'h', long = "help")]
+    pub help: bool,
+    
+    /// Shell to print the function for; detected from $SHELL when left out
+    #[arg(value_enum)]
+    pub shell: Option<crate::shell::Shell>,
+}
+
+#[derive(Args)]
+pub struct PromptArgs {
+    /// Show help information
+    #[arg(short = 'h', long = "help")]
+    pub help: bool,
+    
+    /// Template to use instead of `prompt.format`
+    #[arg(long)]
+    pub format: Option<String>,
+    
+    /// How to write colours: for a terminal, for a bash or zsh prompt, for tmux, or not at all
+    #[arg(long, value_enum, default_value = "ansi")]
+    pub color: crate::commands::prompt::Color,
+}
+
 #[derive(Args)]
 pub struct AnimateArgs {
     /// Show help information
@@ -131,4 +412,81 @@ pub struct AnimateArgs {
     
     /// Animation type (yl, th)
     pub animation: Option<String>,
-}
\ No newline at end of file
+}
+
+/// `90s`, `45m`, `2h`, `1h30m`, `1d`; a bare number is minutes
+pub fn parse_duration(value: &str) -> Result<chrono::Duration, String> {
+    let invalid = || format!("'{}' is not a duration - use e.g. 30m, 2h or 1h30m", value);
+    let value = value.trim();
+    // Digits only, so there are no negative durations
+    if !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit()) {
+        return value.parse().ok().and_then(chrono::Duration::try_minutes).ok_or_else(invalid);
+    }
+
+    let mut total = chrono::Duration::zero();
+    let mut number = String::new();
+    for c in value.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let n: i64 = number.parse().map_err(|_| invalid())?;
+        let part = match c {
+            's' => chrono::Duration::try_seconds(n),
+            'm' => chrono::Duration::try_minutes(n),
+            'h' => chrono::Duration::try_hours(n),
+            'd' => chrono::Duration::try_days(n),
+            _ => return Err(invalid()),
+        };
+        total = part.and_then(|part| total.checked_add(&part)).ok_or_else(invalid)?;
+        number.clear();
+    }
+    if !number.is_empty() || value.is_empty() {
+        return Err(invalid());
+    }
+    Ok(total)
+}
+
+#[derive(Args)]
+pub struct FakeProxyArgs {
+    /// Proxy kind (aws, db)
+    pub kind: String,
+    
+    /// App or database being proxied
+    pub target: String,
+    
+    /// Local port to listen on
+    #[arg(long)]
+    pub port: Option<u16>,
+
+    /// Act as an AWS endpoint URL rather than an HTTPS proxy
+    #[arg(long)]
+    pub endpoint_url: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn parse_duration_adds_up_units() {
+        assert_eq!(parse_duration("1h30m"), Ok(Duration::minutes(90)));
+        assert_eq!(parse_duration("1d2h"), Ok(Duration::hours(26)));
+        assert_eq!(parse_duration("90s"), Ok(Duration::seconds(90)));
+        assert_eq!(parse_duration(" 2h "), Ok(Duration::hours(2)));
+    }
+
+    #[test]
+    fn parse_duration_reads_a_bare_number_as_minutes() {
+        assert_eq!(parse_duration("45"), Ok(Duration::minutes(45)));
+        assert_eq!(parse_duration("0"), Ok(Duration::zero()));
+    }
+
+    #[test]
+    fn parse_duration_rejects_negative_and_malformed_values() {
+        for value in ["-5", "-5m", "", "  ", "h", "2x", "1h30", "m30", "99999999999999999999d"] {
+            assert!(parse_duration(value).is_err(), "{:?} was accepted", value);
+        }
+    }
+}
@@ -1,11 +1,20 @@
-use crate::cli::AwsArgs;
-use crate::config::Config;
-use crate::display::{clear_screen, create_header, create_menu, show_loading, print_success, print_error, print_info, display_code, create_note};
-use crate::shell::ShellIntegration;
-use crate::teleport::TeleportClient;
+use crate::aws_config::{self, AwsProfile};
+use crate::backend::ProxyRequest;
+use crate::cli::{AwsArgs, AwsCommand};
+use crate::config::{discovery, Config};
+use crate::display::{clear_screen, create_header, create_menu, show_loading, print_success, print_error, print_info, print_warning, display_code, create_note};
+use crate::error::ThError;
+use crate::models::resources::{parse_aws_roles_table, split_target};
+use crate::proxies::{self, ProxyEntry};
+use crate::readiness::OutputStream;
+use crate::shell::{self, Shell, ShellIntegration};
+use crate::subshell;
+use crate::teleport::{self, TeleportClient};
 use anyhow::Result;
 use colored::*;
+use serde::Serialize;
 use std::collections::HashMap;
+use std::path::PathBuf;
 use tokio::fs;
 use tokio::io::AsyncWriteExt;
 
@@ -16,17 +25,39 @@ pub async fn execute(args: AwsArgs, config: &Config) -> Result<()> {
         return Ok(());
     }
 
-    let client = TeleportClient::new(config.clone());
+    let client = TeleportClient::new(config.clone())?;
 
-    // Call th_login at start like bash version does
-    crate::display::th_login().await?;
+    match args.command {
+        Some(AwsCommand::CredentialProcess { env, role, endpoint_url }) => {
+            return credential_process(&client, &env, role, endpoint_url).await;
+        }
+        Some(AwsCommand::Env { env, shell, unset, role, sudo, no_login }) => {
+            let shell = shell.unwrap_or_else(Shell::detect);
+            if unset {
+                println!("{}", shell.unset_vars(SESSION_VARS));
+                return Ok(());
+            }
+            return print_env(&client, env, role, sudo, no_login, shell).await;
+        }
+        Some(AwsCommand::SyncProfiles) => return sync_profiles(config).await,
+        Some(AwsCommand::LsSessions) => return list_sessions(config),
+        Some(AwsCommand::Logout { env }) => return logout_session(&client, &env).await,
+        None => {}
+    }
 
-    // Logout from any existing AWS sessions
-    let _ = client.aws_logout().await;
+    if args.shell && args.environment.is_none() {
+        return Err(ThError::InvalidInput("--shell needs an environment, e.g. `th a dev --shell`".to_string()).into());
+    }
+
+    // Call th_login at start like bash version does
+    crate::display::th_login(&client).await?;
 
     // Direct login if environment specified
     if let Some(env) = args.environment {
         let use_sudo = args.sudo_flag.as_deref() == Some("s");
+        if args.shell {
+            return subshell(&client, &env, use_sudo).await;
+        }
         return quick_login(&client, config, &env, use_sudo).await;
     }
 
@@ -36,37 +67,44 @@ pub async fn execute(args: AwsArgs, config: &Config) -> Result<()> {
 }
 
 async fn quick_login(client: &TeleportClient, config: &Config, env: &str, use_sudo: bool) -> Result<()> {
-    if let Some(account_name) = config.get_aws_account(env) {
+    let account_name = match config.get_aws_account(env) {
+        Some(account_name) => Some(account_name.clone()),
+        None => discovery::find_env(client, env, |d| &d.aws).await,
+    };
+
+    if let Some(account_name) = account_name.as_deref() {
         clear_screen()?;
         create_header("AWS Login");
         
         // Handle role selection
-        let role = if use_sudo {
-            select_sudo_role(client, account_name, env).await?
-        } else {
-            select_regular_role(client, account_name, env).await?
-        };
+        let role = config.roles.aws_role(env, use_sudo);
         
         // Display exactly like bash version
         println!("Logging you into: \x1b[1;32m{}\x1b[0m as \x1b[1;32m{}\x1b[0m", account_name, role);
         
-        // Logout first, then login with role - exactly like bash version
-        let _ = client.aws_logout().await;
+        // Logout of this account first, then login with role; sessions for other accounts keep running
+        let _ = client.aws_logout(Some(account_name)).await;
         client.aws_login(account_name, &role).await?;
         
         println!("\n✅ Logged in successfully!");
         
+        if config.get_aws_account(env).is_none() {
+            print_info(&format!("Found '{}' in Teleport - run `th config sync` to save it", env));
+        }
         
         // Create proxy and source credentials - exactly like bash create_proxy function
-        create_proxy(account_name, &role).await?;
+        create_proxy(client, account_name, &role).await?;
         
         Ok(())
     } else {
         print_error(&format!("Environment '{}' not found in configuration", env));
         print_info("Available environments:");
-        for env in config.list_aws_envs() {
+        let mut envs = config.list_aws_envs();
+        envs.sort();
+        for env in envs {
             println!("  - {}", env);
         }
+        println!("Run `th config sync` if it was added in Teleport recently.");
         Ok(())
     }
 }
@@ -89,65 +127,44 @@ async fn interactive_login(client: &TeleportClient, config: &Config, use_sudo: b
     // Create menu items
     let menu_items: Vec<String> = apps
         .iter()
-        .map(|app| app.name.clone())
+        .map(|app| app.qualified_name())
         .collect();
 
     // Show interactive menu
     let selection = create_menu("Available Accounts", &menu_items).await?;
-    let selected_app = &apps[selection];
+    // `leaf/name` for apps in a leaf cluster, so logins are routed there
+    let selected_app = &apps[selection].qualified_name();
 
     clear_screen()?;
     create_header("AWS Login");
     
-    print_info(&format!("Connecting to AWS account: {}", selected_app.name));
+    print_info(&format!("Connecting to AWS account: {}", selected_app));
     
-    // Logout to force fresh AWS role output - exactly like bash
-    let _ = client.aws_logout().await;
+    // Logout of the account to force fresh AWS role output - exactly like bash
+    let _ = client.aws_logout(Some(selected_app)).await;
     
     // Run tsh apps login to capture AWS roles (will error but shows roles) - exactly like bash
-    let login_output = std::process::Command::new("tsh")
-        .args(["apps", "login", &selected_app.name])
-        .output();
-        
-    let output_text = match login_output {
-        Ok(output) => {
-            // Combine both stdout and stderr like the bash version does with 2>&1
-            let stdout = String::from_utf8_lossy(&output.stdout);
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            format!("{}{}", stdout, stderr)
-        },
-        Err(_) => return Err(anyhow::anyhow!("Failed to get AWS roles")),
-    };
+    let output_text = client.aws_login_discover_roles(selected_app).await
+        .map_err(|_| anyhow::anyhow!("Failed to get AWS roles"))?;
     
-    // Extract AWS roles section - exactly like bash awk command
-    let role_section = extract_roles_section(&output_text);
+    // Parse the AWS roles table - exactly like bash awk command
+    let roles_list: Vec<String> = parse_aws_roles_table(&output_text)
+        .into_iter()
+        .map(|role| role.name)
+        .collect();
 
     // Extract default role from ARN - exactly like bash
     let default_role = extract_default_role(&output_text);
     
-    if role_section.is_empty() {
+    if roles_list.is_empty() {
         // Handle case with only default role - exactly like bash aws_elevated_login
         if let Some(role) = default_role {
             // Always show elevated login prompt when there's only one role available
-            return aws_elevated_login(client, &selected_app.name, &role).await;
+            return aws_elevated_login(client, selected_app, &role).await;
         }
         return Err(anyhow::anyhow!("No AWS roles available"));
     }
     
-    // Parse roles list - skip first 2 lines (headers) exactly like bash
-    let roles_lines: Vec<&str> = role_section.lines().skip(2).collect();
-    let roles_list: Vec<String> = roles_lines
-        .iter()
-        .filter(|line| !line.trim().is_empty())
-        .filter(|line| !line.contains("---------")) // Skip separator lines
-        .map(|line| line.split_whitespace().next().unwrap_or(line).to_string())
-        .filter(|role| !role.is_empty())
-        .collect();
-    
-    if roles_list.is_empty() {
-        return Err(anyhow::anyhow!("No roles found in output"));
-    }
-    
     // Display role selection menu - exactly like bash
     clear_screen()?;
     create_header("Available Roles");
@@ -174,36 +191,14 @@ async fn interactive_login(client: &TeleportClient, config: &Config, use_sudo: b
     let selected_role = &roles_list[role_choice - 1];
     
     // Login with selected role - exactly like bash
-    println!("\nLogging you into \x1b[1;32m{}\x1b[0m as \x1b[1;32m{}\x1b[0m", selected_app.name, selected_role);
-    client.aws_login(&selected_app.name, selected_role).await?;
+    println!("\nLogging you into \x1b[1;32m{}\x1b[0m as \x1b[1;32m{}\x1b[0m", selected_app, selected_role);
+    client.aws_login(selected_app, selected_role).await?;
     println!("\n✅\x1b[1;32m Logged in successfully!\x1b[0m");
     
-    create_proxy(&selected_app.name, selected_role).await?;
+    create_proxy(client, selected_app, selected_role).await?;
     Ok(())
 }
 
-async fn select_regular_role(_client: &TeleportClient, _account: &str, env: &str) -> Result<String> {
-    // Map environment to role value - exactly like bash version
-    let role_value = match env {
-        "dev" => "dev",
-        "corepg" => "coreplayground", 
-        _ => env,
-    };
-    
-    Ok(role_value.to_string())
-}
-
-async fn select_sudo_role(_client: &TeleportClient, _account: &str, env: &str) -> Result<String> {
-    // Map environment to role value, then add sudo_ prefix - exactly like bash version
-    let role_value = match env {
-        "dev" => "dev",
-        "corepg" => "coreplayground",
-        _ => env,
-    };
-    
-    Ok(format!("sudo_{}", role_value))
-}
-
 async fn aws_elevated_login(client: &TeleportClient, app: &str, default_role: &str) -> Result<()> {
     use std::io::{self, Write};
     
@@ -230,19 +225,23 @@ async fn aws_elevated_login(client: &TeleportClient, app: &str, default_role: &s
                 io::stdin().read_line(&mut reason).unwrap();
                 let reason = reason.trim();
                 
-                // Determine role based on app name - exactly like bash
-                let role = if app == "yl-production" {
-                    "sudo_prod_role"
-                } else {
-                    "sudo_usprod_role"
+                // Determine role based on the app's environment
+                let config = client.config();
+                let mapping = config.aws_env_for_account(app)
+                    .map(|env| config.roles.aws(env))
+                    .unwrap_or_default();
+                let role = match mapping.elevation {
+                    Some(role) => role,
+                    None => {
+                        println!("\n\x1b[31mNo elevation role configured for {}\x1b[0m", app);
+                        return Ok(());
+                    }
                 };
                 
                 // Create privilege request
-                let output = std::process::Command::new("tsh")
-                    .args(["request", "create", "--roles", role, "--reason", reason])
-                    .output()?;
+                let output = client.request_create(&role, reason, mapping.max_duration.as_deref()).await?;
                     
-                let output_text = String::from_utf8_lossy(&output.stdout);
+                let output_text = output.stdout;
                 println!("{}", output_text); // Show output to user like bash tee /dev/tty
                 
                 // Extract request ID
@@ -255,10 +254,8 @@ async fn aws_elevated_login(client: &TeleportClient, app: &str, default_role: &s
                     
                     // Re-authenticate with request ID - exactly like bash
                     println!("\n\x1b[1mRe-Authenticating\x1b[0m\n");
-                    let _ = std::process::Command::new("tsh").args(["logout"]).output();
-                    let _ = std::process::Command::new("tsh")
-                        .args(["login", "--auth=ad", "--proxy=youlend.teleport.sh:443", &format!("--request-id={}", request_id)])
-                        .output();
+                    let _ = client.logout().await;
+                    let _ = client.login_with_request(Some(request_id)).await;
                         
                     println!("✅ Re-authentication complete. Please run the command again to use elevated permissions.");
                     return Ok(());
@@ -272,7 +269,7 @@ async fn aws_elevated_login(client: &TeleportClient, app: &str, default_role: &s
                 println!("\nLogging you into \x1b[1;32m{}\x1b[0m as \x1b[1;32m{}\x1b[0m", app, default_role);
                 client.aws_login(app, default_role).await?;
                 println!("\n✅\x1b[1;32m Logged in successfully!\x1b[0m");
-                create_proxy(app, default_role).await?;
+                create_proxy(client, app, default_role).await?;
                 return Ok(());
             },
             _ => {
@@ -285,60 +282,58 @@ async fn aws_elevated_login(client: &TeleportClient, app: &str, default_role: &s
 
 
 /// Create proxy & source credentials - exactly like bash create_proxy function
-pub async fn create_proxy(app: &str, role_name: &str) -> Result<()> {
-    use std::process::Stdio;
-    use tokio::process::Command;
-    use tokio::fs;
-    use regex::Regex;
-    
+pub async fn create_proxy(client: &TeleportClient, app: &str, role_name: &str) -> Result<()> {
     if app.is_empty() {
         return Err(anyhow::anyhow!("No active app found. Run 'tsh apps login <app>' first."));
     }
 
-    let log_file = format!("/tmp/tsh_proxy_{}.log", app);
+    println!("\nStarting AWS proxy for \x1b[1;32m{}\x1b[0m...", app);
 
-    // Clean up existing credential files - exactly like bash
-    println!("Cleaned up existing credential files.");
+    // Start tsh proxy aws in the background; its output goes to the registry's log
+    let entry = proxies::start(client, ProxyRequest::Aws { app: app.to_string(), port: 0, leaf: None, endpoint_url: false }, Some(role_name))?;
+    export_proxy_credentials(client.config(), &entry).await
+}
 
-    println!("\nStarting AWS proxy for \x1b[1;32m{}\x1b[0m...", app);
+/// Copy the credentials a running AWS proxy printed into the file the shell sources,
+/// make it the default session and hand it to this process and the calling shell
+pub async fn export_proxy_credentials(config: &Config, entry: &ProxyEntry) -> Result<()> {
+    use tokio::fs;
 
-    // Start tsh proxy aws and redirect output to log file - exactly like bash
-    use std::process::Stdio as StdStdio;
-    let log_file_for_redirect = log_file.clone();
-    let mut child = std::process::Command::new("tsh")
-        .args(&["proxy", "aws", "--app", app])
-        .stdout(std::fs::File::create(&log_file_for_redirect)?)
-        .stderr(StdStdio::null())
-        .spawn()?;
-
-    // Wait up to 10 seconds for credentials to appear - exactly like bash
-    let mut wait_time = 0;
-    while wait_time < 20 {
-        if let Ok(content) = fs::read_to_string(&log_file).await {
-            // Look for the exact pattern the bash version uses - with leading spaces
-            if content.contains("  export AWS_ACCESS_KEY_ID=") {
-                break;
-            }
-        }
-        tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
-        wait_time += 1;
-        
-        if wait_time >= 20 {
-            return Err(anyhow::anyhow!("Timed out waiting for AWS credentials."));
+    let app = entry.target();
+    let log_file = write_credentials_file(config, entry).await?;
+
+    // Set environment variables directly in current process AND add to shell profile
+    if let Ok(content) = fs::read_to_string(&log_file).await {
+        // Parse and set environment variables in current process
+        for (key, value) in parse_exports(&content) {
+            std::env::set_var(key, value);
         }
     }
 
-    // Filter to retain only export lines - exactly like bash
-    if let Ok(content) = fs::read_to_string(&log_file).await {
-        let export_regex = Regex::new(r"^[[:space:]]*export ").unwrap();
-        let export_lines: Vec<&str> = content.lines()
-            .filter(|line| export_regex.is_match(line))
-            .collect();
-            
-        let filtered_content = export_lines.join("\n");
-        fs::write(&log_file, &filtered_content).await?;
+    // New shells pick the credentials up through the rc file
+    if let Err(e) = set_default_session(config, Some(&log_file)) {
+        print_warning(&e.to_string());
     }
 
+    // And hand them to the shell we were run from, through the shell-init wrapper
+    let content = fs::read_to_string(&log_file).await?;
+    shell::request_env(&parse_exports(&content), SESSION_VARS)?;
+
+    println!("\nCredentials exported, and made global, for app: \x1b[1;32m{}\x1b[0m\n", app);
+    
+    Ok(())
+}
+
+/// Rewrite an account's credentials file from what its running proxy printed, leaving
+/// the default session and every shell alone - for the watchdog. Returns the file.
+pub async fn write_credentials_file(config: &Config, entry: &ProxyEntry) -> Result<String> {
+    let app = entry.target();
+    let role_name = entry.role.as_deref().unwrap_or_default();
+    let log_file = credentials_file(app);
+
+    let export_lines = wait_for_credentials(config, entry).await?;
+    fs::write(&log_file, export_lines.join("\n")).await?;
+
     // Add ACCOUNT and ROLE exports - exactly like bash
     let mut file = tokio::fs::OpenOptions::new()
         .append(true)
@@ -350,86 +345,436 @@ pub async fn create_proxy(app: &str, role_name: &str) -> Result<()> {
     file.write_all(format!("export ROLE={}\n", role_name).as_bytes()).await?;
 
     // Set region based on app name - exactly like bash
-    if app.starts_with("yl-us") {
-        file.write_all(b"export AWS_DEFAULT_REGION=us-east-2\n").await?;
-    } else {
-        file.write_all(b"export AWS_DEFAULT_REGION=eu-west-1\n").await?;
-    }
+    file.write_all(format!("export AWS_DEFAULT_REGION={}\n", region_for(app)).as_bytes()).await?;
 
-    // Set environment variables directly in current process AND add to shell profile
-    if let Ok(content) = fs::read_to_string(&log_file).await {
-        // Parse and set environment variables in current process
-        for line in content.lines() {
-            if line.trim().starts_with("export ") {
-                if let Some(export_part) = line.trim().strip_prefix("export ") {
-                    if let Some(eq_pos) = export_part.find('=') {
-                        let key = &export_part[..eq_pos];
-                        let value = &export_part[eq_pos + 1..].trim_matches('"');
-                        std::env::set_var(key, value);
-                    }
-                }
-            }
-        }
+    Ok(log_file)
+}
+
+/// Wait for a running AWS proxy to print its credentials and proxy address (its CA for
+/// an endpoint proxy), returning its `export` lines
+pub async fn wait_for_credentials(config: &Config, entry: &ProxyEntry) -> Result<Vec<String>> {
+    use regex::Regex;
+
+    let last = match entry.request {
+        ProxyRequest::Aws { endpoint_url: true, .. } => "export AWS_CA_BUNDLE=",
+        _ => "export HTTPS_PROXY=",
+    };
+    let mut output = OutputStream::new(&entry.log);
+    output.wait_until(
+        "Waiting for AWS credentials",
+        proxies::ready_timeout(config),
+        || entry.is_running(),
+        |lines| ["export AWS_ACCESS_KEY_ID=", last].iter()
+            .all(|export| lines.iter().any(|line| line.contains(export))),
+    ).await?;
+
+    // Filter to retain only export lines - exactly like bash
+    let export_regex = Regex::new(r"^[[:space:]]*export ").unwrap();
+    Ok(output.lines().iter()
+        .filter(|line| export_regex.is_match(line))
+        .map(|line| line.trim().to_string())
+        .collect())
+}
+
+/// Default region for an account: `yl-us*` accounts live in us-east-2
+pub fn region_for(app: &str) -> &'static str {
+    if split_target(app).1.starts_with("yl-us") { "us-east-2" } else { "eu-west-1" }
+}
+
+/// Credentials for one account, served by a proxy th keeps running for it
+pub struct Session {
+    /// App name, `leaf/app` for a leaf cluster's
+    pub account: String,
+    pub role: String,
+    /// What the proxy printed: keys, `HTTPS_PROXY` and `AWS_CA_BUNDLE`
+    pub exports: Vec<(String, String)>,
+    /// The proxy, when it was started for this session rather than already running
+    pub started: Option<ProxyEntry>,
+}
+
+impl Session {
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.exports.iter().find(|(k, _)| k == key).map(|(_, value)| value.as_str())
     }
 
-    // Add source line to shell profile - exactly like bash  
-    let shell = std::env::var("SHELL").unwrap_or_default();
-    let shell_name = std::path::Path::new(&shell)
-        .file_name()
-        .and_then(|s| s.to_str())
-        .unwrap_or("");
+    /// Everything a process needs to use the session: the proxy's variables plus
+    /// `ACCOUNT`, `ROLE` and the region, like the credentials file
+    pub fn env(&self) -> Vec<(String, String)> {
+        let mut env = self.exports.clone();
+        env.push(("ACCOUNT".to_string(), self.account.clone()));
+        env.push(("ROLE".to_string(), self.role.clone()));
+        env.push(("AWS_DEFAULT_REGION".to_string(), region_for(&self.account).to_string()));
+        env
+    }
+}
 
-    let shell_profile = match shell_name {
-        "zsh" => format!("{}/.zshrc", std::env::var("HOME").unwrap_or_default()),
-        "bash" => format!("{}/.bash_profile", std::env::var("HOME").unwrap_or_default()),
-        _ => format!("{}/.profile", std::env::var("HOME").unwrap_or_default()),
+/// `[aws]` account for `env`, looked up in Teleport when `discovery.auto` is on
+pub async fn resolve_account(client: &TeleportClient, env: &str) -> Result<String> {
+    let account = match client.config().get_aws_account(env) {
+        Some(account) => Some(account.clone()),
+        None => discovery::find_env(client, env, |d| &d.aws).await,
     };
+    account.ok_or_else(|| ThError::NotFound(format!("AWS environment '{}' - run `th config sync`", env)).into())
+}
 
-    // Remove existing tsh source lines and add new one - exactly like bash
-    if let Ok(content) = fs::read_to_string(&shell_profile).await {
-        let lines: Vec<&str> = content.lines()
-            .filter(|line| !line.starts_with("source /tmp/tsh"))
-            .collect();
-        let mut new_content = lines.join("\n");
-        new_content.push_str(&format!("\nsource {}\n", log_file));
-        fs::write(&shell_profile, new_content).await?;
+/// Reuse the proxy running for `account` as `role`, or log in and start one, and wait
+/// for its credentials. Unlike `create_proxy` nothing is written for the shell.
+/// `endpoint_url` picks the endpoint proxy the synced profiles use.
+pub async fn ensure_session(client: &TeleportClient, account: &str, role: &str, endpoint_url: bool) -> Result<Session> {
+    let config = client.config();
+    let request = ProxyRequest::Aws { app: account.to_string(), port: 0, leaf: None, endpoint_url };
+    let running = proxies::list(config)?.into_iter().any(|entry| {
+        matches!(&entry.request, ProxyRequest::Aws { app, endpoint_url: endpoint, .. } if app == account && *endpoint == endpoint_url)
+            && entry.role.as_deref() == Some(role)
+    });
+    if !running {
+        client.aws_login(account, role).await?;
     }
+    // Another th may have started one since, e.g. a parallel credential_process
+    let (entry, started) = proxies::ensure(client, request, Some(role))?;
+
+    let exports = parse_exports(&wait_for_credentials(config, &entry).await?.join("\n"));
+    Ok(Session {
+        account: account.to_string(),
+        role: role.to_string(),
+        exports,
+        started: Some(entry).filter(|_| started),
+    })
+}
 
-    println!("\nCredentials exported, and made global, for app: \x1b[1;32m{}\x1b[0m\n", app);
-    
+/// `th aws <env> --shell`: a subshell with the account's credentials, leaving this
+/// shell and the rc file alone. A proxy started for it stops when it exits.
+async fn subshell(client: &TeleportClient, env: &str, use_sudo: bool) -> Result<()> {
+    let config = client.config();
+    let account = resolve_account(client, env).await?;
+    let role = config.roles.aws_role(env, use_sudo);
+    let session = ensure_session(client, &account, &role, false).await?;
+
+    print_success(&format!("{} as {} - exit the shell to leave", session.account, session.role));
+    let status = subshell::run(config, env, session.env()).await;
+
+    if let Some(entry) = &session.started {
+        proxies::stop(config, entry)?;
+        print_info(&format!("Stopped the proxy for {}", session.account));
+    }
+    status.map(|_| ())
+}
+
+/// `th aws credential-process`: the AWS CLI reads this from stdout, so nothing else may be printed there
+async fn credential_process(client: &TeleportClient, env: &str, role: Option<String>, endpoint_url: bool) -> Result<()> {
+    let account = resolve_account(client, env).await?;
+    let role = role.unwrap_or_else(|| client.config().roles.aws_role(env, false));
+
+    // tsh login keeps its output off stdout, so a browser login is fine here
+    let status = client.login(client.renew_before()).await?;
+    let session = ensure_session(client, &account, &role, endpoint_url).await?;
+
+    let credential = |key: &str| session.get(key)
+        .ok_or_else(|| ThError::Proxy(format!("AWS proxy for {} did not print {}", session.account, key)));
+
+    // The profile's `ca_bundle` points at a copy, as tsh's path isn't known when it's written
+    if endpoint_url {
+        let ca = std::fs::read(credential("AWS_CA_BUNDLE")?)?;
+        let copy = aws_config::ca_bundle_path(&session.account)?;
+        if std::fs::read(&copy).ok().as_deref() != Some(ca.as_slice()) {
+            if let Some(parent) = copy.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(&copy, ca)?;
+        }
+    }
+
+    let mut output = serde_json::json!({
+        "Version": 1,
+        "AccessKeyId": credential("AWS_ACCESS_KEY_ID")?,
+        "SecretAccessKey": credential("AWS_SECRET_ACCESS_KEY")?,
+    });
+    if let Some(token) = session.get("AWS_SESSION_TOKEN") {
+        output["SessionToken"] = token.into();
+    }
+    // The proxy's credentials are only good while the Teleport certificate is
+    if let Some(expires) = status.expires {
+        output["Expiration"] = expires.to_rfc3339().into();
+    }
+    println!("{}", output);
     Ok(())
 }
 
-fn find_env_for_account(config: &Config, account_name: &str) -> String {
-    for (env, name) in &config.aws {
-        if name == account_name {
-            return env.clone();
+/// Everything `th aws env` sets, for `--unset`
+pub const SESSION_VARS: &[&str] = &[
+    "AWS_ACCESS_KEY_ID",
+    "AWS_SECRET_ACCESS_KEY",
+    "AWS_SESSION_TOKEN",
+    "AWS_CA_BUNDLE",
+    "HTTPS_PROXY",
+    "ACCOUNT",
+    "ROLE",
+    "AWS_DEFAULT_REGION",
+    "AWS_CREDENTIAL_EXPIRATION",
+];
+
+/// `th aws env`: a session's variables in `shell`'s syntax, with the certificate's
+/// expiry as `AWS_CREDENTIAL_EXPIRATION`. With `no_login` only a running session is
+/// read, so wrappers can run it after any command without starting anything.
+async fn print_env(client: &TeleportClient, env: Option<String>, role: Option<String>, sudo: bool, no_login: bool, shell: Shell) -> Result<()> {
+    let config = client.config();
+    let (account, role) = match env {
+        Some(env) => {
+            let role = role.unwrap_or_else(|| config.roles.aws_role(&env, sudo));
+            (resolve_account(client, &env).await?, role)
+        }
+        None => {
+            let entry = current_session(config)?
+                .ok_or_else(|| ThError::NotFound("AWS session - log in with `th a <env>`".to_string()))?;
+            let role = role.or_else(|| entry.role.clone())
+                .ok_or_else(|| ThError::NotFound(format!("role for the {} session - pass --role", entry.target())))?;
+            (entry.target().to_string(), role)
         }
+    };
+
+    let (session, expires) = if no_login {
+        let entry = sessions(config)?.into_iter()
+            .find(|entry| entry.target() == account && entry.role.as_deref() == Some(role.as_str()))
+            .ok_or_else(|| ThError::NotFound(format!("running AWS session for {} as {}", account, role)))?;
+        let exports = parse_exports(&wait_for_credentials(config, &entry).await?.join("\n"));
+        (Session { account, role, exports, started: None }, teleport::cached_expiry(config))
+    } else {
+        let status = client.login(client.renew_before()).await?;
+        (ensure_session(client, &account, &role, false).await?, status.expires)
+    };
+    let mut vars = session.env();
+    if let Some(expires) = expires {
+        vars.push(("AWS_CREDENTIAL_EXPIRATION".to_string(), expires.to_rfc3339()));
     }
-    "unknown".to_string()
+    println!("{}", shell.set_vars(&vars));
+    Ok(())
 }
 
-// Extract roles section from tsh output - exactly like bash awk command
-fn extract_roles_section(output: &str) -> String {
-    let lines: Vec<&str> = output.lines().collect();
-    let mut in_roles_section = false;
-    let mut roles_section = Vec::new();
-    
-    for line in lines {
-        if line.contains("Available AWS roles:") {
-            in_roles_section = true;
-            roles_section.push(line); // Include the header line
+/// `th aws sync-profiles`: a `credential_process` profile per `[aws]` environment. Each
+/// role's profile sends requests to its own endpoint proxy, on a port kept for it.
+async fn sync_profiles(config: &Config) -> Result<()> {
+    let program = std::env::current_exe()?.display().to_string();
+    let profile_flag = config.profile.as_ref().map(|name| format!(" --profile {}", name)).unwrap_or_default();
+
+    let mut envs = config.list_aws_envs();
+    envs.sort();
+    let mut profiles = Vec::new();
+    for env in envs {
+        let account = &config.aws[env];
+        let (_, app) = split_target(account);
+        // Several envs can share an account
+        if profiles.iter().any(|p: &AwsProfile| p.name == app) {
             continue;
         }
-        if line.contains("ERROR: --aws-role flag is required") {
-            break;
+        let command = format!("{}{} aws credential-process --env {} --endpoint-url", program, profile_flag, env);
+        let ca_bundle = aws_config::ca_bundle_path(account)?.display().to_string();
+        let profile = |name: String, role: &str, command: String| -> Result<AwsProfile> {
+            let request = ProxyRequest::Aws { app: account.clone(), port: 0, leaf: None, endpoint_url: true };
+            let port = proxies::reserve_port(config, &proxies::port_key(&request, Some(role)))?;
+            Ok(AwsProfile {
+                name,
+                settings: vec![
+                    ("credential_process".to_string(), command),
+                    ("endpoint_url".to_string(), format!("https://localhost:{}", port)),
+                    ("ca_bundle".to_string(), ca_bundle.clone()),
+                    ("region".to_string(), region_for(app).to_string()),
+                ],
+            })
+        };
+
+        let role = config.roles.aws_role(env, false);
+        profiles.push(profile(app.to_string(), &role, command.clone())?);
+        let sudo = config.roles.aws_role(env, true);
+        if sudo != role {
+            profiles.push(profile(format!("{}-sudo", app), &sudo, format!("{} --role {}", command, sudo))?);
         }
-        if in_roles_section && !line.contains("ERROR:") {
-            roles_section.push(line);
+    }
+
+    let report = aws_config::sync_profiles(&profiles)?;
+    let path = aws_config::config_path()?;
+    for name in &report.added {
+        println!("  {} {}", "+".green(), name);
+    }
+    for name in &report.updated {
+        println!("  {} {}", "~".yellow(), name);
+    }
+    for name in &report.removed {
+        println!("  {} {}", "-".red(), name);
+    }
+    for name in &report.skipped {
+        print_info(&format!("Left your own [profile {}] alone", name));
+    }
+    print_success(&format!("{} AWS profiles in {}", profiles.len() - report.skipped.len(), path.display()));
+    println!("Use one with {}", display_code("AWS_PROFILE=<account> aws sts get-caller-identity"));
+    Ok(())
+}
+
+/// Point new shells at a credentials file, or at none, through th's block in the rc file
+fn set_default_session(config: &Config, credentials: Option<&str>) -> Result<()> {
+    let shell = ShellIntegration::new()?;
+    match credentials {
+        Some(file) => shell.source_aws_credentials(config, file),
+        None => shell.cleanup_profile(config),
+    }
+}
+
+/// Account whose credentials new shells source
+fn default_session() -> Option<String> {
+    let block = ShellIntegration::new().ok()?.managed_block()?;
+    block.lines()
+        .find_map(|line| line.strip_prefix("source /tmp/tsh_proxy_")?.strip_suffix(".log"))
+        .map(|app| app.replace('+', "/"))
+}
+
+/// Running AWS proxies - one per account th is logged in to. The synced profiles'
+/// endpoint proxies aren't sessions a shell can use.
+fn sessions(config: &Config) -> Result<Vec<ProxyEntry>> {
+    Ok(proxies::list(config)?
+        .into_iter()
+        .filter(|entry| matches!(entry.request, ProxyRequest::Aws { endpoint_url: false, .. }))
+        .collect())
+}
+
+/// The session new shells get, or else the newest one
+fn current_session(config: &Config) -> Result<Option<ProxyEntry>> {
+    let mut sessions = sessions(config)?;
+    let default = default_session();
+    if let Some(entry) = sessions.iter().find(|entry| default.as_deref() == Some(entry.target())) {
+        return Ok(Some(entry.clone()));
+    }
+    sessions.sort_by_key(|entry| entry.started_at);
+    Ok(sessions.pop())
+}
+
+/// `[aws]` environment for an app, `leaf/app` for a leaf cluster's
+fn env_for_app<'a>(config: &'a Config, app: &str) -> Option<&'a str> {
+    config.aws.iter()
+        .find(|(_, account)| *account == app)
+        .map(|(env, _)| env.as_str())
+}
+
+/// `th aws ls-sessions`
+fn list_sessions(config: &Config) -> Result<()> {
+    let sessions = sessions(config)?;
+    if sessions.is_empty() {
+        print_info("No AWS sessions - log in with `th a <env>`");
+        return Ok(());
+    }
+
+    let default = default_session();
+    println!("{}", format!("  {:<12} {:<28} {:<24} {:<7} {}", "ENV", "ACCOUNT", "ROLE", "PORT", "UPTIME").bold());
+    for entry in &sessions {
+        let marker = if default.as_deref() == Some(entry.target()) { "*" } else { " " };
+        println!(
+            "{} {:<12} {:<28} {:<24} {:<7} {}",
+            marker,
+            env_for_app(config, entry.target()).unwrap_or("-"),
+            entry.target(),
+            entry.role.as_deref().unwrap_or("-"),
+            entry.port().map(|p| p.to_string()).unwrap_or_else(|| "-".to_string()),
+            if entry.is_running() { crate::commands::proxy::uptime(entry) } else { "down".to_string() },
+        );
+    }
+    println!("\n{}", "* new shells use this one".dimmed());
+    println!("Use another in this shell with {}", display_code(&format!("source {}", credentials_file("<account>"))));
+    Ok(())
+}
+
+/// `th aws logout <env>`: end one account's session and leave the others running
+async fn logout_session(client: &TeleportClient, env: &str) -> Result<()> {
+    let config = client.config();
+    // An account name works as well as an env
+    let account = config.get_aws_account(env).cloned().unwrap_or_else(|| env.to_string());
+    let app = account.as_str();
+
+    let (ended, remaining): (Vec<ProxyEntry>, Vec<ProxyEntry>) = sessions(config)?
+        .into_iter()
+        .partition(|entry| entry.target() == app);
+    let file = credentials_file(app);
+    if ended.is_empty() && !std::path::Path::new(&file).exists() {
+        return Err(ThError::NotFound(format!("AWS session for '{}' - see `th aws ls-sessions`", env)).into());
+    }
+
+    for entry in &ended {
+        proxies::stop(config, entry)?;
+    }
+    let _ = client.aws_logout(Some(&account)).await;
+    let _ = fs::remove_file(&file).await;
+
+    // New shells move on to the most recent session still running
+    if default_session().as_deref() == Some(app) {
+        let next = remaining.iter()
+            .max_by_key(|entry| entry.started_at)
+            .map(|entry| credentials_file(entry.target()))
+            .filter(|next| std::path::Path::new(next).exists());
+        if let Err(e) = set_default_session(config, next.as_deref()) {
+            print_warning(&e.to_string());
         }
     }
-    
-    roles_section.join("\n")
+
+    if std::env::var("ACCOUNT").as_deref() == Ok(app) {
+        shell::request_env(&[], SESSION_VARS)?;
+    }
+
+    print_success(&format!("Logged out of {}", app));
+    Ok(())
+}
+
+/// File the shell sources for an app's credentials; th.sh reads it too. A leaf
+/// cluster's `leaf/app` is written `leaf+app`.
+pub fn credentials_file(app: &str) -> String {
+    format!("/tmp/tsh_proxy_{}.log", app.replace('/', "+"))
+}
+
+/// `(KEY, value)` for every `export KEY=value` line
+fn parse_exports(content: &str) -> Vec<(String, String)> {
+    content.lines()
+        .filter_map(|line| line.trim().strip_prefix("export "))
+        .filter_map(|export| export.split_once('='))
+        .map(|(key, value)| (key.to_string(), value.trim_matches('"').to_string()))
+        .collect()
+}
+
+/// An AWS app and role whose credentials are exported
+#[derive(Debug, Serialize)]
+pub struct ExportedSession {
+    pub account: String,
+    pub role: String,
+    pub region: Option<String>,
+    /// Exported in the shell th was run from, rather than only waiting in the credentials file
+    pub in_shell: bool,
+    pub credentials_file: Option<PathBuf>,
+}
+
+/// The `ACCOUNT`/`ROLE` this shell has exported, or else the newest credentials
+/// file - the one new shells source
+pub fn exported_session() -> Option<ExportedSession> {
+    if let Some(account) = std::env::var("ACCOUNT").ok().filter(|account| !account.is_empty()) {
+        let file = PathBuf::from(credentials_file(&account));
+        return Some(ExportedSession {
+            role: std::env::var("ROLE").unwrap_or_default(),
+            region: std::env::var("AWS_DEFAULT_REGION").ok(),
+            in_shell: true,
+            credentials_file: Some(file).filter(|file| file.is_file()),
+            account,
+        });
+    }
+
+    let newest = std::fs::read_dir("/tmp").ok()?
+        .flatten()
+        .filter(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            name.starts_with("tsh_proxy_") && name.ends_with(".log")
+        })
+        .max_by_key(|entry| entry.metadata().and_then(|m| m.modified()).ok())?;
+    let exports: HashMap<String, String> = parse_exports(&std::fs::read_to_string(newest.path()).ok()?).into_iter().collect();
+    Some(ExportedSession {
+        account: exports.get("ACCOUNT")?.clone(),
+        role: exports.get("ROLE").cloned().unwrap_or_default(),
+        region: exports.get("AWS_DEFAULT_REGION").cloned(),
+        in_shell: false,
+        credentials_file: Some(newest.path()),
+    })
 }
 
 // Extract default role from ARN - exactly like bash grep -o command
@@ -463,8 +808,21 @@ fn show_help() {
     println!(" ╚═ {}                : Open interactive login.", "th a".bold());
     println!(" ╚═ {}  : Quick log-in, Where {} = dev, staging, etc..", "th a <account> <s>".bold(), "<account>".bold());
     println!("                          and {} is an optional arg which logs you in with", "<s>".bold());
-    println!("                          the account's sudo role\n");
+    println!("                          the account's sudo role");
+    println!(" ╚═ {} : Open a subshell with the account's credentials and", "th a <account> --shell".bold());
+    println!("                          {} in its prompt; its proxy stops when you exit", "(th:<account>)".bold());
+    println!(" ╚═ {}       : List the accounts you are logged in to - several", "th a ls-sessions".bold());
+    println!("                          can be at once, each with its own proxy");
+    println!(" ╚═ {}     : End one account's session", "th a logout <env>".bold());
+    println!(" ╚═ {}  : Print a session's credentials for your shell ({},", "th a env [env] [-s]".bold(), "--shell".bold());
+    println!("                          bash, zsh, fish, powershell or json); {} prints", "--unset".bold());
+    println!("                          the commands that remove them, {} only reads", "--no-login".bold());
+    println!("                          a running session");
+    println!(" ╚═ {}     : Write an ~/.aws/config profile per account", "th a sync-profiles".bold());
+    println!(" ╚═ {}", "th a credential-process --env <env> [--role <role>]".bold());
+    println!("                        : Print credentials for the profiles' {}\n", "credential_process".bold());
     println!("Examples:");
     println!(" ╚═ {}            : logs you into {} as {}", display_code("th a dev"), "yl-development".green(), "dev".underline().green());
     println!(" ╚═ {}          : logs you into {} as {}", display_code("th a dev s"), "yl-development".green(), "sudo_dev".underline().green());
+    println!(" ╚═ {} : loads the default session into fish", display_code("th a env --shell fish | source"));
 }
\ No newline at end of file
@@ -0,0 +1,313 @@
+use crate::cli::{ConfigArgs, ConfigCommand};
+use crate::config::edit::{self, target_path};
+use crate::config::layers::{self, flatten, lookup, LayeredConfig};
+use crate::config::migrate;
+use crate::config::validate::{self, Issue, Severity};
+use crate::config::discovery::{Change, Discovered};
+use crate::config::Config;
+use crate::display::{clear_screen, create_header, print_success, print_warning, show_loading};
+use crate::error::ThError;
+use crate::teleport::TeleportClient;
+use anyhow::Result;
+use colored::*;
+use std::io::{self, Write};
+use toml::Value;
+
+pub async fn execute(args: ConfigArgs) -> Result<()> {
+    // Show help if requested
+    if args.help {
+        show_help();
+        return Ok(());
+    }
+
+    match args.command {
+        Some(ConfigCommand::Show { origin }) => show(origin),
+        Some(ConfigCommand::Get { key }) => get(&key),
+        Some(ConfigCommand::Set { key, value, project }) => set(&key, &value, project),
+        Some(ConfigCommand::Unset { key, project }) => unset(&key, project),
+        Some(ConfigCommand::Edit { project }) => edit(project),
+        Some(ConfigCommand::Validate) => validate(),
+        Some(ConfigCommand::Path) => path(),
+        Some(ConfigCommand::Sync { dry_run, project }) => sync(dry_run, project).await,
+        Some(ConfigCommand::Reset { yes }) => reset(yes),
+        None => {
+            show_help();
+            Ok(())
+        }
+    }
+}
+
+fn show(origin: bool) -> Result<()> {
+    let layered = LayeredConfig::discover()?;
+    let merged = layered.merged();
+
+    if !origin {
+        print!("{}", toml::to_string_pretty(&merged)?);
+        return Ok(());
+    }
+
+    println!("{}", "Layers (lowest precedence first):".bold());
+    for layer in &layered.layers {
+        println!("  • {}", layer.describe());
+    }
+    println!();
+
+    for (key, value) in flatten(&merged) {
+        let source = layered.origin(&key)
+            .map(|layer| layer.describe())
+            .unwrap_or_default();
+        println!("{} = {}  {}", key, value, format!("# {}", source).dimmed());
+    }
+
+    Ok(())
+}
+
+fn get(key: &str) -> Result<()> {
+    let merged = LayeredConfig::discover()?.merged();
+    match lookup(&merged, key) {
+        // Plain strings are printed unquoted so `$(th config get ...)` works
+        Some(Value::String(s)) => println!("{}", s),
+        Some(Value::Table(table)) => print!("{}", toml::to_string_pretty(table)?),
+        Some(value) => println!("{}", value),
+        None => return Err(ThError::NotFound(format!("Config key '{}' is not set", key)).into()),
+    }
+    Ok(())
+}
+
+fn set(key: &str, value: &str, project: bool) -> Result<()> {
+    let path = target_path(project)?;
+    edit::set_key(&path, key, value)?;
+    print_success(&format!("Set {} in {}", key.bold(), path.display()));
+    Ok(())
+}
+
+fn unset(key: &str, project: bool) -> Result<()> {
+    let path = target_path(project)?;
+    if edit::unset_key(&path, key)? {
+        print_success(&format!("Removed {} from {}", key.bold(), path.display()));
+    } else {
+        print_warning(&format!("{} is not set in {}", key, path.display()));
+    }
+    Ok(())
+}
+
+fn edit(project: bool) -> Result<()> {
+    let path = target_path(project)?;
+    if !path.exists() {
+        edit::write_atomic(&path, &format!(
+            "# th configuration - only the keys you set here override the defaults.\n# Run `th config show --origin` to see every value and where it comes from.\nversion = {}\n",
+            migrate::CURRENT_VERSION
+        ))?;
+    }
+
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    let mut editor_args = shellwords::split(&editor)
+        .map_err(|_| ThError::InvalidInput(format!("Could not parse editor command: {}", editor)))?;
+    if editor_args.is_empty() {
+        editor_args.push("vi".to_string());
+    }
+
+    loop {
+        let status = std::process::Command::new(&editor_args[0])
+            .args(&editor_args[1..])
+            .arg(&path)
+            .status()?;
+        if !status.success() {
+            return Err(ThError::Process(format!("Editor '{}' exited with {}", editor, status)).into());
+        }
+
+        let issues = validate::validate()?;
+        print_issues(&issues);
+        if !issues.iter().any(|issue| issue.severity == Severity::Error) {
+            return Ok(());
+        }
+
+        print!("\nRe-open the editor to fix these? (y/n): ");
+        io::stdout().flush()?;
+        let mut answer = String::new();
+        io::stdin().read_line(&mut answer)?;
+        if !answer.trim().to_lowercase().starts_with('y') {
+            return Err(ThError::Config(format!("{} has errors", path.display())).into());
+        }
+    }
+}
+
+fn validate() -> Result<()> {
+    let issues = validate::validate()?;
+    print_issues(&issues);
+
+    let errors = issues.iter().filter(|issue| issue.severity == Severity::Error).count();
+    if errors > 0 {
+        return Err(ThError::Config(format!("{} error(s) found", errors)).into());
+    }
+    Ok(())
+}
+
+fn print_issues(issues: &[Issue]) {
+    if issues.is_empty() {
+        print_success("Configuration is valid");
+        return;
+    }
+
+    for issue in issues {
+        let label = match issue.severity {
+            Severity::Error => "error".red().bold(),
+            Severity::Warning => "warning".yellow().bold(),
+        };
+        match &issue.location {
+            Some(location) => println!("{}: {}: {}", label, location, issue.message),
+            None => println!("{}: {}", label, issue.message),
+        }
+    }
+}
+
+fn path() -> Result<()> {
+    for (kind, path) in layers::layer_files()? {
+        let state = if path.is_file() { "" } else { " (not found)" };
+        println!("{:<8} {}{}", kind.to_string(), path.display(), state.dimmed());
+    }
+    Ok(())
+}
+
+async fn sync(dry_run: bool, project: bool) -> Result<()> {
+    let config = Config::load().await?;
+    let client = TeleportClient::new(config.clone())?;
+    crate::display::th_login(&client).await?;
+
+    let discovered = show_loading("Reading Teleport labels...", Discovered::fetch(&client)).await?;
+
+    // Entries already in the file may point at resources without the label, so they stay
+    let path = target_path(project)?;
+    let written: toml::Table = std::fs::read_to_string(&path).ok()
+        .and_then(|content| content.parse().ok())
+        .unwrap_or_default();
+    let hand_written = |section: &str, env: &str| lookup(&written, &format!("{}.{}", section, env)).is_some();
+
+    let mut sections = Vec::new();
+    for (section, changes) in discovered.diff(&config) {
+        let map = discovered.section(section);
+        // A section with no labelled resources is left alone rather than emptied
+        if map.is_empty() {
+            print_warning(&format!(
+                "No {} resources carry the '{}' label - keeping [{}] as is",
+                section, config.discovery.env_label, section
+            ));
+            continue;
+        }
+
+        println!("\n{}", format!("[{}]", section).bold());
+        if changes.is_empty() {
+            println!("  {}", "no changes".dimmed());
+        }
+        for change in &changes {
+            match change {
+                Change::Added { env, name } => println!("  {} {} = {}", "+".green(), env, name),
+                Change::Changed { env, from, to } => println!("  {} {} = {} → {}", "~".yellow(), env, from, to),
+                Change::Removed { env, name } if hand_written(section, env) => {
+                    println!("  {} {} = {} {}", "=".dimmed(), env, name, "(not labelled in Teleport - kept)".dimmed())
+                }
+                Change::Removed { env, name } => println!("  {} {} = {}", "-".red(), env, name),
+            }
+        }
+        sections.push((section, map.clone()));
+    }
+
+    if dry_run || sections.is_empty() {
+        return Ok(());
+    }
+
+    edit::update_document(&path, |doc| {
+        for (section, map) in &sections {
+            if !doc.contains_key(section) {
+                doc.insert(section, toml_edit::Item::Table(toml_edit::Table::new()));
+            }
+            let Some(table) = doc[section].as_table_like_mut() else {
+                continue;
+            };
+            // In place, so comments and the order of existing entries survive
+            for (env, name) in map {
+                match table.get_mut(env).and_then(|item| item.as_value_mut()) {
+                    Some(value) if value.as_str() == Some(name.as_str()) => {}
+                    Some(value) => {
+                        let decor = value.decor().clone();
+                        *value = name.as_str().into();
+                        *value.decor_mut() = decor;
+                    }
+                    None => {
+                        table.insert(env, toml_edit::value(name.as_str()));
+                    }
+                }
+            }
+        }
+
+        if !doc.contains_key("discovery") {
+            doc.insert("discovery", toml_edit::Item::Table(toml_edit::Table::new()));
+        }
+        let discovery = &mut doc["discovery"];
+        discovery["synced_at"] = toml_edit::value(chrono::Utc::now().to_rfc3339());
+        // Only these sections stop using the built-in maps
+        let mut synced: Vec<String> = discovery.get("synced_sections")
+            .and_then(|item| item.as_array())
+            .map(|array| array.iter().filter_map(|v| v.as_str()).map(|s| s.to_string()).collect())
+            .unwrap_or_default();
+        for (section, _) in &sections {
+            if !synced.iter().any(|s| s == section) {
+                synced.push(section.to_string());
+            }
+        }
+        discovery["synced_sections"] = toml_edit::value(synced.iter().collect::<toml_edit::Array>());
+    })?;
+
+    println!();
+    print_success(&format!("Synced environments into {}", path.display()));
+    Ok(())
+}
+
+fn reset(yes: bool) -> Result<()> {
+    let path = Config::get_config_path()?;
+    if !path.exists() {
+        print_warning(&format!("{} does not exist - already using defaults", path.display()));
+        return Ok(());
+    }
+
+    if !yes {
+        print!("Reset {} to defaults? A backup will be kept. (y/n): ", path.display());
+        io::stdout().flush()?;
+        let mut answer = String::new();
+        io::stdin().read_line(&mut answer)?;
+        if !answer.trim().to_lowercase().starts_with('y') {
+            println!("Reset cancelled.");
+            return Ok(());
+        }
+    }
+
+    let backup = edit::backup(&path)?;
+    std::fs::remove_file(&path)?;
+    print_success(&format!("Removed {} (backup at {})", path.display(), backup.display()));
+    Ok(())
+}
+
+fn show_help() {
+    clear_screen().unwrap();
+    create_header("th config");
+    println!("Inspect and change th configuration.\n");
+    println!("Configuration is merged from these layers, later ones winning:");
+    println!("  • Built-in defaults");
+    println!("  • System file     : /etc/th/config.toml (or $TH_SYSTEM_CONFIG)");
+    println!("  • User file       : ~/.config/th/config.toml");
+    println!("  • Project file    : nearest .th.toml from the current directory up");
+    println!("  • Environment     : TH_<SECTION>__<KEY>, e.g. TH_TELEPORT__PROXY\n");
+    println!("Usage: {}", "th config <command>".bold());
+    println!(" ╚═ {}          : Print the merged configuration", "th config show".bold());
+    println!(" ╚═ {} : Show which layer each value came from", "th config show --origin".bold());
+    println!(" ╚═ {}     : Print one value, e.g. teleport.proxy", "th config get <key>".bold());
+    println!(" ╚═ {} : Set a value (--project for .th.toml)", "th config set <key> <v>".bold());
+    println!(" ╚═ {}   : Remove a value (--project for .th.toml)", "th config unset <key>".bold());
+    println!(" ╚═ {}          : Open your config file in $EDITOR", "th config edit".bold());
+    println!(" ╚═ {}      : Check every layer for mistakes", "th config validate".bold());
+    println!(" ╚═ {}          : Show where config files are read from", "th config path".bold());
+    println!(" ╚═ {}          : Rebuild env maps from Teleport labels (--dry-run)", "th config sync".bold());
+    println!(" ╚═ {}         : Back up and remove your config file", "th config reset".bold());
+}
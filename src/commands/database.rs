@@ -1,7 +1,10 @@
+use crate::backend::ProxyRequest;
 use crate::cli::DatabaseArgs;
 use crate::config::Config;
 use crate::display::{clear_screen, create_header, create_menu, load_content, print_success, print_error, print_info, display_code};
+use crate::proxies;
 use crate::teleport::TeleportClient;
+use crate::tools::{self, Tool};
 use anyhow::Result;
 use colored::*;
 use serde_json::Value;
@@ -13,13 +16,15 @@ pub async fn execute(args: DatabaseArgs, config: &Config) -> Result<()> {
         return Ok(());
     }
 
-    let client = TeleportClient::new(config.clone());
+    let client = TeleportClient::new(config.clone())?;
 
     // Call th_login at start like bash version does
-    crate::display::th_login().await?;
+    crate::display::th_login(&client).await?;
 
     // Direct connection if target specified
     if let Some(target) = args.target {
+        // `th d <env>` uses the [databases] map; anything else is a database name
+        let target = config.databases.get(&target).cloned().unwrap_or(target);
         return direct_connect(&client, &target).await;
     }
 
@@ -43,7 +48,7 @@ async fn direct_connect(client: &TeleportClient, target: &str) -> Result<()> {
     print_success(&format!("Successfully connected to {}", target));
     
     // Get connection details and show options
-    show_connection_options(client, target).await
+    show_connection_options(target).await
 }
 
 async fn db_login(client: &TeleportClient) -> Result<()> {
@@ -130,7 +135,7 @@ async fn handle_rds_selection(client: &TeleportClient) -> Result<()> {
     
     // Handle elevated access case
     if !selected_db.accessible {
-        db_elevated_login(client, "sudo_teleport_rds_read_role", &selected_db.name).await?;
+        db_elevated_login(client, "rds", &selected_db.name).await?;
         return Ok(());
     }
     
@@ -190,7 +195,7 @@ async fn handle_mongodb_selection(client: &TeleportClient) -> Result<()> {
     
     // If user doesn't have atlas access, trigger elevated login
     if !has_atlas_access {
-        db_elevated_login(client, "atlas-read-only", selected_db).await?;
+        db_elevated_login(client, "mongodb", selected_db).await?;
         return Ok(());
     }
     
@@ -200,9 +205,18 @@ async fn handle_mongodb_selection(client: &TeleportClient) -> Result<()> {
     mongo_connect(client, selected_db).await
 }
 
-async fn db_elevated_login(_client: &TeleportClient, role: &str, db_name: &str) -> Result<()> {
+async fn db_elevated_login(client: &TeleportClient, db_class: &str, db_name: &str) -> Result<()> {
     use std::io::{self, Write};
     
+    let roles = client.config().roles.db(db_class);
+    let role = match roles.elevation {
+        Some(ref role) => role.clone(),
+        None => {
+            println!("\n\x1b[31mNo elevation role configured for {} databases\x1b[0m", db_class);
+            return Ok(());
+        }
+    };
+    
     let display_name = if db_name.is_empty() {
         "Mongo databases"
     } else {
@@ -232,17 +246,10 @@ async fn db_elevated_login(_client: &TeleportClient, role: &str, db_name: &str)
                 println!();
                 
                 // Execute tsh request create command
-                let request_output = std::process::Command::new("tsh")
-                    .args([
-                        "request", "create", 
-                        "--roles", role,
-                        "--max-duration", "6h",
-                        "--reason", reason
-                    ])
-                    .output()?;
+                let request_output = client.request_create(&role, reason, roles.max_duration.as_deref()).await?;
                 
-                let output_text = String::from_utf8_lossy(&request_output.stdout);
-                let error_text = String::from_utf8_lossy(&request_output.stderr);
+                let output_text = request_output.stdout;
+                let error_text = request_output.stderr;
                 
                 // Print output to user (like bash tee /dev/tty)
                 print!("{}{}", output_text, error_text);
@@ -297,15 +304,15 @@ async fn rds_connect(client: &TeleportClient, rds: &str) -> Result<()> {
     match option.trim() {
         "1" => {
             println!("\nConnecting via \x1b[1;32mPSQL\x1b[0m...");
-            check_psql().await?;
+            check_psql(client.config()).await?;
             let database = list_postgres_databases(client, rds).await?;
-            let db_user = check_admin(client).await?;
+            let db_user = check_admin(client, rds).await?;
             connect_db(client, rds, &database, &db_user).await
         },
         "2" => {
             println!("\nConnecting via \x1b[1;32mDBeaver\x1b[0m...");
             let database = list_postgres_databases(client, rds).await?;
-            let db_user = check_admin(client).await?;
+            let db_user = check_admin(client, rds).await?;
             open_dbeaver(client, rds, &database, &db_user).await
         },
         _ => {
@@ -315,15 +322,11 @@ async fn rds_connect(client: &TeleportClient, rds: &str) -> Result<()> {
     }
 }
 
-async fn check_psql() -> Result<()> {
+async fn check_psql(config: &Config) -> Result<()> {
     use std::io::{self, Write};
     
     // Check if psql command exists
-    let psql_check = std::process::Command::new("which")
-        .arg("psql")
-        .output();
-    
-    if psql_check.map(|output| output.status.success()).unwrap_or(false) {
+    if tools::resolve(config, Tool::Psql).is_ok() {
         return Ok(());
     }
     
@@ -365,50 +368,43 @@ async fn check_psql() -> Result<()> {
     Ok(())
 }
 
-async fn list_postgres_databases(_client: &TeleportClient, rds: &str) -> Result<String> {
+async fn list_postgres_databases(client: &TeleportClient, rds: &str) -> Result<String> {
     use std::io::{self, Write};
     
-    // Find available port
-    let port = crate::display::find_available_port();
-    
-    // Start proxy tunnel
-    let mut child = std::process::Command::new("tsh")
-        .args(["proxy", "db", rds, "--db-user=tf_teleport_rds_read_user", "--db-name=postgres", &format!("--port={}", port), "--tunnel"])
-        .stdout(std::process::Stdio::null())
-        .stderr(std::process::Stdio::null())
-        .spawn()?;
-    
-    // Wait for proxy to be ready (up to 10 seconds)
-    let mut proxy_ready = false;
-    for _ in 0..10 {
-        let nc_check = std::process::Command::new("nc")
-            .args(["-z", "localhost", &port.to_string()])
-            .output();
-            
-        if nc_check.map(|output| output.status.success()).unwrap_or(false) {
-            proxy_ready = true;
-            break;
-        }
-        tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
-    }
-    
-    if !proxy_ready {
+    let read_user = client.config().roles.db("rds")
+        .user_for(rds, false)
+        .unwrap_or_default()
+        .to_string();
+    
+    // Start proxy tunnel - registered so it can be cleaned up if th dies before stopping it
+    let proxy = proxies::start(client, ProxyRequest::Db {
+        db: rds.to_string(),
+        db_user: Some(read_user.clone()),
+        db_name: Some("postgres".to_string()),
+        port: 0,
+        leaf: None,
+    }, None)?;
+    let port = proxy.port().unwrap_or_default();
+    
+    // Wait for proxy to be ready (proxy.ready_timeout_seconds)
+    if let Err(e) = proxies::wait_ready(client.config(), &proxy).await {
         println!("\n\x1b[31m❌ Failed to establish tunnel to database.\x1b[0m");
-        let _ = child.kill();
-        return Err(anyhow::anyhow!("Failed to establish tunnel"));
+        let _ = proxies::stop(client.config(), &proxy);
+        return Err(e);
     }
     
     clear_screen()?;
     create_header("Available Databases");
     
     // Fetch databases
+    let psql = tools::program(client.config(), Tool::Psql).to_string();
     let db_list = load_content(
         "Fetching databases...",
-        async move { fetch_postgres_databases(port).await }
+        async move { fetch_postgres_databases(&psql, port, &read_user).await }
     ).await?;
     
     // Kill proxy
-    let _ = child.kill();
+    let _ = proxies::stop(client.config(), &proxy);
     
     if db_list.is_empty() {
         println!("\x1b[31m❌ No databases found or connection failed.\x1b[0m");
@@ -442,9 +438,9 @@ async fn list_postgres_databases(_client: &TeleportClient, rds: &str) -> Result<
     Ok(db_list[selected_index - 1].clone())
 }
 
-async fn fetch_postgres_databases(port: u16) -> Result<Vec<String>> {
-    let output = std::process::Command::new("psql")
-        .arg(&format!("postgres://tf_teleport_rds_read_user@localhost:{}/postgres", port))
+async fn fetch_postgres_databases(psql: &str, port: u16, db_user: &str) -> Result<Vec<String>> {
+    let output = std::process::Command::new(psql)
+        .arg(&format!("postgres://{}@localhost:{}/postgres", db_user, port))
         .args(["-t", "-A", "-c", "SELECT datname FROM pg_database WHERE datistemplate = false;"])
         .output()?;
     
@@ -461,32 +457,30 @@ async fn fetch_postgres_databases(port: u16) -> Result<Vec<String>> {
     Ok(db_list)
 }
 
-async fn check_admin(client: &TeleportClient) -> Result<String> {
+async fn check_admin(client: &TeleportClient, rds: &str) -> Result<String> {
     use std::io::{self, Write};
     
-    // Check if user has admin role
-    let status_output = std::process::Command::new("tsh")
-        .args(["status"])
-        .output()?;
+    let roles = client.config().roles.db("rds");
+    let mut admin = false;
     
-    let status_text = String::from_utf8_lossy(&status_output.stdout);
+    // Check if user has admin role
+    let status = client.get_status().await.unwrap_or_default();
     
-    if status_text.contains("sudo_teleport_rds_write_role") {
+    if roles.admin_role.as_deref().is_some_and(|role| status.has_role(role)) {
         print!("\nConnecting as admin? (y/n): ");
         io::stdout().flush().unwrap();
         
-        let mut admin = String::new();
-        io::stdin().read_line(&mut admin).unwrap();
-        
-        if admin.trim().to_lowercase().starts_with('y') {
-            return Ok("tf_sudo_teleport_rds_user".to_string());
-        }
+        let mut answer = String::new();
+        io::stdin().read_line(&mut answer).unwrap();
+        admin = answer.trim().to_lowercase().starts_with('y');
     }
     
-    Ok("tf_teleport_rds_read_user".to_string())
+    roles.user_for(rds, admin)
+        .map(|user| user.to_string())
+        .ok_or_else(|| anyhow::anyhow!("No database user configured for RDS"))
 }
 
-async fn connect_db(_client: &TeleportClient, rds: &str, database: &str, db_user: &str) -> Result<()> {
+async fn connect_db(client: &TeleportClient, rds: &str, database: &str, db_user: &str) -> Result<()> {
     use std::io::Write;
     
     println!("\n\x1b[1mConnecting to \x1b[1;32m{}\x1b[0m in \x1b[1;32m{}\x1b[0m as \x1b[1;32m{}\x1b[0m...", database, rds, db_user);
@@ -501,28 +495,26 @@ async fn connect_db(_client: &TeleportClient, rds: &str, database: &str, db_user
     clear_screen()?;
     
     // Execute tsh db connect
-    std::process::Command::new("tsh")
-        .args(["db", "connect", rds, &format!("--db-user={}", db_user), &format!("--db-name={}", database)])
-        .status()?;
-    
-    Ok(())
+    client.db_connect(rds, Some(db_user), Some(database)).await
 }
 
-async fn open_dbeaver(_client: &TeleportClient, rds: &str, database: &str, db_user: &str) -> Result<()> {
+async fn open_dbeaver(client: &TeleportClient, rds: &str, database: &str, db_user: &str) -> Result<()> {
     use std::io::Write;
     
-    let port = crate::display::find_available_port();
-    
     println!("\n\x1b[1mConnecting to \x1b[1;32m{}\x1b[0m in \x1b[1;32m{}\x1b[0m as \x1b[1;32m{}\x1b[0m...\n", database, rds, db_user);
     
     tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
     
-    // Start proxy in background
-    std::process::Command::new("tsh")
-        .args(["proxy", "db", rds, &format!("--db-name={}", database), &format!("--port={}", port), "--tunnel", &format!("--db-user={}", db_user)])
-        .stdout(std::process::Stdio::null())
-        .stderr(std::process::Stdio::null())
-        .spawn()?;
+    // Start proxy in background, on the same port as last time so saved connections keep working
+    let proxy = proxies::start(client, ProxyRequest::Db {
+        db: rds.to_string(),
+        db_user: Some(db_user.to_string()),
+        db_name: Some(database.to_string()),
+        port: 0,
+        leaf: None,
+    }, None)?;
+    let port = proxy.port().unwrap_or_default();
+    proxies::wait_ready(client.config(), &proxy).await?;
     
     clear_screen()?;
     create_header("DBeaver");
@@ -556,16 +548,13 @@ async fn open_dbeaver(_client: &TeleportClient, rds: &str, database: &str, db_us
     Ok(())
 }
 
-async fn mongo_connect(_client: &TeleportClient, db_name: &str) -> Result<()> {
+async fn mongo_connect(client: &TeleportClient, db_name: &str) -> Result<()> {
     use std::io::{self, Write};
     
     // Determine db_user based on database name
-    let db_user = match db_name {
-        "mongodb-YLUSProd-Cluster-1" => "teleport-usprod",
-        "mongodb-YLProd-Cluster-1" => "teleport-prod", 
-        "mongodb-YLSandbox-Cluster-1" => "teleport-sandbox",
-        _ => "teleport-default", // fallback
-    };
+    let roles = client.config().roles.db("mongodb");
+    let db_user = roles.user_for(db_name, false)
+        .ok_or_else(|| anyhow::anyhow!("No database user configured for {}", db_name))?;
     
     clear_screen()?;
     create_header("MongoDB");
@@ -583,11 +572,7 @@ async fn mongo_connect(_client: &TeleportClient, db_name: &str) -> Result<()> {
         match option.trim() {
             "1" => {
                 // Check if mongosh is available
-                let mongosh_check = std::process::Command::new("command")
-                    .args(["-v", "mongosh"])
-                    .output();
-                
-                if mongosh_check.map(|output| output.status.success()).unwrap_or(false) {
+                if tools::resolve(client.config(), Tool::Mongosh).is_ok() {
                     // MongoDB client found, connect
                     println!("\n\x1b[1mConnecting to \x1b[1;32m{}\x1b[0m...", db_name);
                     
@@ -600,9 +585,7 @@ async fn mongo_connect(_client: &TeleportClient, db_name: &str) -> Result<()> {
                     clear_screen()?;
                     
                     // Execute tsh db connect
-                    std::process::Command::new("tsh")
-                        .args(["db", "connect", db_name, &format!("--db-user={}", db_user), "--db-name=admin"])
-                        .status()?;
+                    client.db_connect(db_name, Some(db_user), Some("admin")).await?;
                         
                     return Ok(());
                 } else {
@@ -628,9 +611,7 @@ async fn mongo_connect(_client: &TeleportClient, db_name: &str) -> Result<()> {
                                     println!("\n\x1b[1mConnecting to \x1b[1;32m{}\x1b[0m...", db_name);
                                     println!();
                                     
-                                    std::process::Command::new("tsh")
-                                        .args(["db", "connect", db_name])
-                                        .status()?;
+                                    client.db_connect(db_name, None, None).await?;
                                 } else {
                                     println!("\n❌ Failed to install MongoDB client");
                                 }
@@ -656,19 +637,21 @@ async fn mongo_connect(_client: &TeleportClient, db_name: &str) -> Result<()> {
                 println!("Logging into: \x1b[1;32m{}\x1b[0m as \x1b[1;32m{}\x1b[0m", db_name, db_user);
                 
                 // Login to database
-                let _login_result = std::process::Command::new("tsh")
-                    .args(["db", "login", db_name, &format!("--db-user={}", db_user), "--db-name=admin"])
-                    .output()?;
+                let _login_result = client.db_login_as(db_name, db_user, "admin").await;
                 
                 println!("\n✅ \x1b[1;32mLogged in successfully!\x1b[0m");
                 
                 // Create proxy
                 println!("\nCreating proxy for \x1b[1;32m{}\x1b[0m...", db_name);
-                let mongo_port = crate::display::find_available_port();
-                
-                std::process::Command::new("tsh")
-                    .args(["proxy", "db", "--tunnel", &format!("--port={}", mongo_port), db_name])
-                    .spawn()?;
+                let proxy = proxies::start(client, ProxyRequest::Db {
+                    db: db_name.to_string(),
+                    db_user: None,
+                    db_name: None,
+                    port: 0,
+                    leaf: None,
+                }, None)?;
+                let mongo_port = proxy.port().unwrap_or_default();
+                proxies::wait_ready(client.config(), &proxy).await?;
                 
                 // Open MongoDB Compass
                 println!("\nOpening MongoDB compass...");
@@ -691,19 +674,12 @@ async fn mongo_connect(_client: &TeleportClient, db_name: &str) -> Result<()> {
     }
 }
 
-async fn show_connection_options(client: &TeleportClient, db_name: &str) -> Result<()> {
+async fn show_connection_options(db_name: &str) -> Result<()> {
     print_info("Connection options:");
     
-    // Try to get proxy information
-    match client.get_db_proxy(db_name).await {
-        Ok(proxy_info) => {
-            println!("\n1. {} Connect via proxy:", "🔗".bright_blue());
-            print_info(&format!("Proxy details: {}", proxy_info));
-        }
-        Err(_) => {
-            print_info("Proxy information not available");
-        }
-    }
+    // Show how to start a local proxy
+    println!("\n1. {} Connect via proxy:", "🔗".bright_blue());
+    print_info(&format!("Run {} to open a local tunnel", display_code(&format!("tsh proxy db {} --tunnel", db_name))));
     
     // Show direct connection commands
     println!("\n2. {} Direct connection commands:", "💻".bright_blue());
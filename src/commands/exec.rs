@@ -0,0 +1,96 @@
+use crate::cli::ExecArgs;
+use crate::commands::{aws, kube};
+use crate::config::Config;
+use crate::display::{clear_screen, create_header, display_code};
+use crate::error::ThError;
+use crate::process;
+use crate::proxies;
+use crate::shell;
+use crate::teleport::TeleportClient;
+use anyhow::Result;
+use colored::*;
+use std::process::Stdio;
+
+pub async fn execute(args: ExecArgs, config: &Config) -> Result<()> {
+    // Show help if requested
+    if args.help {
+        show_help();
+        return Ok(());
+    }
+
+    let (Some(env), Some((program, program_args))) = (args.environment, args.command.split_first()) else {
+        return Err(ThError::InvalidInput("Usage: th exec <env> [--kube] -- <command>".to_string()).into());
+    };
+
+    let client = TeleportClient::new(config.clone())?;
+    client.login(client.renew_before()).await?;
+
+    let mut vars = Vec::new();
+    let mut started = None;
+
+    // AWS credentials whenever the env has an account; with --kube they are optional
+    let account = match config.get_aws_account(&env) {
+        Some(account) => Some(account.clone()),
+        None if args.kube => None,
+        None => Some(aws::resolve_account(&client, &env).await?),
+    };
+    if let Some(account) = account {
+        let role = args.role.clone().unwrap_or_else(|| config.roles.aws_role(&env, args.sudo));
+        let session = aws::ensure_session(&client, &account, &role, false).await?;
+        vars.extend(session.env());
+        started = session.started;
+    }
+
+    let kubeconfig = if args.kube {
+        let cluster = kube::resolve_cluster(&client, &env).await?;
+        let (dir, path) = kube::login_isolated(&client, &cluster).await?;
+        vars.push(("KUBECONFIG".to_string(), path.display().to_string()));
+        Some(dir)
+    } else {
+        None
+    };
+
+    let mut command = tokio::process::Command::new(program);
+    // Only this env's credentials: none of the calling shell's, and a th inside the
+    // command must not reach that shell through its shell-init side channel
+    for key in aws::SESSION_VARS.iter().chain(&[shell::ENV_FILE_VAR, shell::ENV_SHELL_VAR]) {
+        command.env_remove(key);
+    }
+    let child = command
+        .args(program_args)
+        .envs(vars)
+        .stdin(Stdio::inherit())
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .map_err(|e| ThError::Process(format!("Could not run '{}': {}", program, e)));
+    let status = match child {
+        Ok(mut child) => process::wait_in_foreground(&mut child).await,
+        Err(e) => Err(e.into()),
+    };
+
+    // Like a subshell, leave nothing running that was started for the command
+    if let Some(entry) = &started {
+        proxies::stop(config, entry)?;
+    }
+    drop(kubeconfig);
+    std::process::exit(process::exit_code(status?));
+}
+
+fn show_help() {
+    clear_screen().unwrap();
+    create_header("th exec");
+    println!("Run one command with an environment's credentials, leaving your shell,");
+    println!("shell profile and ~/.kube/config as they are.\n");
+    println!("Usage: {}", "th exec <env> [options] -- <command>".bold());
+    println!(" ╚═ {}  : AWS credentials, proxy and region for the env's", "th exec <env> -- <command>".bold());
+    println!("                                  {} account", "[aws]".bold());
+    println!(" ╚═ {}                        : Also log in to the env's cluster, in a KUBECONFIG", "--kube".bold());
+    println!("                                  only the command sees");
+    println!(" ╚═ {}                   : Use the sudo role", "-s, --sudo".bold());
+    println!(" ╚═ {}                 : Use this AWS role instead", "--role <role>".bold());
+    println!("\nth exits with the command's exit code and prints nothing to stdout.");
+    println!("\nExamples:");
+    println!(" ╚═ {}", display_code("th exec staging -- aws s3 ls"));
+    println!(" ╚═ {}", display_code("th exec prod --kube -- kubectl get pods"));
+}
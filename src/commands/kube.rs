@@ -1,9 +1,14 @@
 use crate::cli::KubeArgs;
-use crate::config::Config;
+use crate::config::{discovery, Config};
 use crate::display::{clear_screen, create_header, create_menu, show_loading, print_success, print_error, print_info, display_code};
+use crate::error::ThError;
+use crate::proxies;
+use crate::subshell;
 use crate::teleport::TeleportClient;
 use anyhow::Result;
 use colored::*;
+use std::path::PathBuf;
+use tempfile::TempDir;
 
 pub async fn execute(args: KubeArgs, config: &Config) -> Result<()> {
     // Show help if requested
@@ -12,10 +17,16 @@ pub async fn execute(args: KubeArgs, config: &Config) -> Result<()> {
         return Ok(());
     }
 
-    let client = TeleportClient::new(config.clone());
+    let client = TeleportClient::new(config.clone())?;
 
-    // Ensure logged in to Teleport
-    show_loading("Checking Teleport login...", client.login()).await?;
+    // Ensure logged in to Teleport, renewing a certificate about to expire
+    crate::display::th_login(&client).await?;
+
+    if args.shell {
+        let env = args.environment
+            .ok_or_else(|| ThError::InvalidInput("--shell needs an environment, e.g. `th k dev --shell`".to_string()))?;
+        return subshell(&client, &env).await;
+    }
 
     // Direct login if environment specified
     if let Some(env) = args.environment {
@@ -26,21 +37,65 @@ pub async fn execute(args: KubeArgs, config: &Config) -> Result<()> {
     interactive_login(&client, config).await
 }
 
+/// `[kube]` cluster for `env`, looked up in Teleport when `discovery.auto` is on
+pub async fn resolve_cluster(client: &TeleportClient, env: &str) -> Result<String> {
+    let cluster = match client.config().get_kube_cluster(env) {
+        Some(cluster) => Some(cluster.clone()),
+        None => discovery::find_env(client, env, |d| &d.kube).await,
+    };
+    cluster.ok_or_else(|| ThError::NotFound(format!("Kubernetes environment '{}' - run `th config sync`", env)).into())
+}
+
+/// Log in to `cluster` in a kubeconfig of its own under the runtime dir, leaving
+/// ~/.kube/config alone. The kubeconfig goes when the returned directory is dropped.
+pub async fn login_isolated(client: &TeleportClient, cluster: &str) -> Result<(TempDir, PathBuf)> {
+    let dir = tempfile::Builder::new().prefix("kube-").tempdir_in(proxies::ensure_runtime_dir(client.config())?)?;
+    let path = dir.path().join("config");
+    // tsh writes to whatever KUBECONFIG says
+    std::env::set_var("KUBECONFIG", &path);
+    client.kube_login(cluster).await?;
+    Ok((dir, path))
+}
+
+/// `th kube <env> --shell`: a subshell whose KUBECONFIG holds only this cluster
+async fn subshell(client: &TeleportClient, env: &str) -> Result<()> {
+    let cluster = resolve_cluster(client, env).await?;
+    let (dir, path) = login_isolated(client, &cluster).await?;
+
+    print_success(&format!("{} - exit the shell to leave", cluster));
+    let status = subshell::run(client.config(), env, vec![("KUBECONFIG".to_string(), path.display().to_string())]).await;
+    drop(dir);
+    status.map(|_| ())
+}
+
 async fn quick_login(client: &TeleportClient, config: &Config, env: &str) -> Result<()> {
-    if let Some(cluster_name) = config.get_kube_cluster(env) {
+    let cluster_name = match config.get_kube_cluster(env) {
+        Some(cluster_name) => Some(cluster_name.clone()),
+        None => discovery::find_env(client, env, |d| &d.kube).await,
+    };
+
+    if let Some(cluster_name) = cluster_name {
         clear_screen()?;
         create_header("Kube Login");
         
         println!("Logging you into: \x1b[1;32m{}\x1b[0m", cluster_name);
         
-        client.kube_login(cluster_name).await?;
+        client.kube_login(&cluster_name).await?;
         
         println!("\n✅ Logged in successfully!\n");
         
+        if config.get_kube_cluster(env).is_none() {
+            print_info(&format!("Found '{}' in Teleport - run `th config sync` to save it", env));
+        }
+        
         Ok(())
     } else {
+        let mut envs = config.list_kube_envs();
+        envs.sort();
+        let envs: Vec<&str> = envs.into_iter().map(|e| e.as_str()).collect();
         println!("\n\x1b[31mUnknown environment: {}\x1b[0m", env);
-        println!("Available environments: dev, sandbox, staging, usstaging, admin, prod, usprod, corepgblue, corepggreen");
+        println!("Available environments: {}", envs.join(", "));
+        println!("Run `th config sync` if it was added in Teleport recently.");
         Ok(())
     }
 }
@@ -60,8 +115,15 @@ async fn interactive_login(client: &TeleportClient, _config: &Config) -> Result<
         return Ok(());
     }
 
-    // Display clusters with bash-style formatting
+    // Display clusters with bash-style formatting, grouped by Teleport cluster when there are leaves
+    let has_leaves = clusters.iter().any(|cluster| cluster.cluster.is_some());
+    let mut group: Option<Option<&str>> = None;
     for (i, cluster) in clusters.iter().enumerate() {
+        if has_leaves && group != Some(cluster.cluster.as_deref()) {
+            group = Some(cluster.cluster.as_deref());
+            let heading = cluster.cluster.as_deref().unwrap_or(client.config().teleport.proxy.split(':').next().unwrap_or("root"));
+            println!("{}\x1b[1m{}\x1b[0m", if i == 0 { "" } else { "\n" }, heading);
+        }
         if cluster.accessible && !cluster.name.contains("prod") {
             // n/a case - normal display
             println!("{:2}. {}", i + 1, cluster.name);
@@ -99,13 +161,13 @@ async fn interactive_login(client: &TeleportClient, _config: &Config) -> Result<
     
     // Handle elevated access case for prod clusters
     if !selected_cluster.accessible && selected_cluster.name.contains("prod") {
-        kube_elevated_login(client, &selected_cluster.name).await?;
+        kube_elevated_login(client, &selected_cluster.qualified_name()).await?;
         return Ok(());
     }
     
     // Normal login
-    println!("\n\x1b[1mLogging you into:\x1b[0m \x1b[1;32m{}\x1b[0m", selected_cluster.name);
-    client.kube_login(&selected_cluster.name).await?;
+    println!("\n\x1b[1mLogging you into:\x1b[0m \x1b[1;32m{}\x1b[0m", selected_cluster.qualified_name());
+    client.kube_login(&selected_cluster.qualified_name()).await?;
     println!("\n✅ \x1b[1mLogged in successfully!\x1b[0m\n");
 
     Ok(())
@@ -137,20 +199,21 @@ async fn kube_elevated_login(client: &TeleportClient, cluster: &str) -> Result<(
                 io::stdin().read_line(&mut reason).unwrap();
                 let reason = reason.trim();
                 
-                let role = match cluster {
-                    "live-prod-eks-blue" => "sudo_prod_eks_cluster",
-                    "live-usprod-eks-blue" => "sudo_usprod_eks_cluster", 
-                    _ => {
-                        println!("\nCluster doesn't exist");
+                let config = client.config();
+                let mapping = config.kube_env_for_cluster(cluster)
+                    .map(|env| config.roles.kube(env))
+                    .unwrap_or_default();
+                let role = match mapping.elevation {
+                    Some(role) => role,
+                    None => {
+                        println!("\nNo elevation role configured for {}", cluster);
                         return Ok(());
                     }
                 };
                 
-                let output = std::process::Command::new("tsh")
-                    .args(["request", "create", "--roles", role, "--reason", reason])
-                    .output()?;
+                let output = client.request_create(&role, reason, mapping.max_duration.as_deref()).await?;
                     
-                let output_text = String::from_utf8_lossy(&output.stdout);
+                let output_text = output.stdout;
                 println!("{}", output_text);
                 
                 // Extract request ID
@@ -179,7 +242,9 @@ fn show_help() {
     println!("Login to our Kubernetes clusters.\n");
     println!("Usage: {} | {}", "th kube [options]".bold(), "k".bold());
     println!(" ╚═ {}                 : Open interactive login.", "th k".bold());
-    println!(" ╚═ {}       : Quick kube log-in, Where {} = dev, staging, etc..\n", "th k <account>".bold(), "<account>".bold());
+    println!(" ╚═ {}       : Quick kube log-in, Where {} = dev, staging, etc..", "th k <account>".bold(), "<account>".bold());
+    println!(" ╚═ {} : Open a subshell logged in to the cluster, leaving", "th k <account> --shell".bold());
+    println!("                          ~/.kube/config alone\n");
     println!("Examples:");
     println!(" ╚═ {}             : logs you into {}.", display_code("th k dev"), "aslive-dev-eks-blue".green());
 }
\ No newline at end of file
@@ -1,17 +1,28 @@
 use crate::cli::LoginArgs;
 use crate::config::Config;
-use crate::display;
+use crate::display::{self, format_duration, print_success};
 use anyhow::Result;
+use colored::*;
 
-pub async fn execute(args: LoginArgs, _config: &Config) -> Result<()> {
+pub async fn execute(args: LoginArgs, config: &Config) -> Result<()> {
     // Show help if requested
     if args.help {
         show_help();
         return Ok(());
     }
 
+    let client = crate::teleport::TeleportClient::new(config.clone())?;
+
+    // Non-interactive, for scripts that are about to run for a while
+    if let Some(min) = args.ensure {
+        let status = client.login(min).await?;
+        let left = status.remaining().map(|left| format_duration(left.num_seconds())).unwrap_or_else(|| "an unknown time".to_string());
+        print_success(&format!("Teleport certificate valid for another {}", left));
+        return Ok(());
+    }
+
     // Use the exact bash th_login function
-    display::th_login().await
+    display::th_login(&client).await
 }
 
 fn show_help() {
@@ -26,5 +37,10 @@ fn show_help() {
     println!("  • Kubernetes clusters via 'th kube'");
     println!("  • AWS accounts via 'th aws'");
     println!("  • Database connections via 'th database'");
-    println!("  • Administrative tools via 'th terra'");
-}
\ No newline at end of file
+    println!("  • Administrative tools via 'th terra'\n");
+    println!("Usage: {}", "th login [options]".bold());
+    println!(" ╚═ {}  : Log in again unless the certificate lasts at least", "th li --ensure <duration>".bold());
+    println!("                               {} longer, e.g. {} or {}", "<duration>".bold(), "2h".bold(), "1h30m".bold());
+    println!("\nA certificate with less than {} left counts as expiring: th", "teleport.renew_before_minutes".bold());
+    println!("warns and offers to log in again ({} does it without asking).", "teleport.auto_renew".bold());
+}
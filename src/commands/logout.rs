@@ -1,9 +1,10 @@
 use crate::cli::LogoutArgs;
 use crate::config::Config;
 use crate::display;
+use crate::tools::{self, Tool};
 use anyhow::Result;
 
-pub async fn execute(args: LogoutArgs, _config: &Config) -> Result<()> {
+pub async fn execute(args: LogoutArgs, config: &Config) -> Result<()> {
     // Show help if requested
     if args.help {
         show_help();
@@ -11,19 +12,22 @@ pub async fn execute(args: LogoutArgs, _config: &Config) -> Result<()> {
     }
 
     // Use the exact bash th_kill function
-    display::th_kill().await
+    let client = crate::teleport::TeleportClient::new(config.clone())?;
+    display::th_kill(&client).await
 }
 
-async fn cleanup_kubectl_contexts() -> Result<()> {
+async fn cleanup_kubectl_contexts(config: &Config) -> Result<()> {
+    let kubectl = tools::program(config, Tool::Kubectl);
+    
     // Get current kubectl contexts
-    let output = crate::process::execute_command("kubectl", &["config", "get-contexts", "-o", "name"]).await;
+    let output = crate::process::execute_command(kubectl, &["config", "get-contexts", "-o", "name"]).await;
     
     if let Ok(contexts) = output {
         for context in contexts.lines() {
             // Remove contexts that look like Teleport contexts
             if context.contains("teleport") || context.contains("tsh-") {
                 let _ = crate::process::execute_command_silent(
-                    "kubectl",
+                    kubectl,
                     &["config", "delete-context", context]
                 ).await;
             }
@@ -1,12 +1,18 @@
 pub mod aws;
 pub mod animate;
 pub mod changelog;
+pub mod config;
 pub mod database;
 pub mod docs;
+pub mod exec;
 pub mod kube;
 pub mod login;
 pub mod logout;
+pub mod prompt;
+pub mod proxy;
 pub mod quickstart;
+pub mod shell_init;
+pub mod status;
 pub mod terraform;
 pub mod update;
 pub mod version;
\ No newline at end of file
@@ -0,0 +1,178 @@
+use crate::cli::PromptArgs;
+use crate::config::Config;
+use crate::display::{clear_screen, create_header, display_code, format_duration};
+use crate::models::resources::split_target;
+use crate::subshell::ENV_MARKER;
+use crate::teleport;
+use anyhow::Result;
+use chrono::Utc;
+use colored::*;
+use std::path::PathBuf;
+
+/// How `th prompt` writes colours
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Color {
+    Never,
+    Ansi,
+    /// Escapes wrapped in `\001`/`\002` so readline measures the prompt right
+    Bash,
+    /// `%F{red}`, for `PROMPT` with `PROMPT_SUBST`
+    Zsh,
+    /// `#[fg=red]`, for tmux status lines
+    Tmux,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Style {
+    Plain,
+    /// Production, or an expired certificate
+    Alert,
+    /// A certificate about to expire
+    Warning,
+}
+
+impl Color {
+    fn paint(self, text: &str, style: Style) -> String {
+        let text = self.escape(text);
+        let (ansi, zsh, tmux) = match style {
+            Style::Plain => return text,
+            Style::Alert => ("1;31", "%B%F{red}", "fg=red,bold"),
+            Style::Warning => ("33", "%F{yellow}", "fg=yellow"),
+        };
+        match self {
+            Color::Never => text,
+            Color::Ansi => format!("\x1b[{}m{}\x1b[0m", ansi, text),
+            Color::Bash => format!("\x01\x1b[{}m\x02{}\x01\x1b[0m\x02", ansi, text),
+            Color::Zsh => format!("{}{}%f%b", zsh, text),
+            Color::Tmux => format!("#[{}]{}#[default]", tmux, text),
+        }
+    }
+
+    /// Characters the prompt language would otherwise interpret
+    fn escape(self, text: &str) -> String {
+        match self {
+            Color::Zsh => text.replace('%', "%%"),
+            Color::Tmux => text.replace('#', "##"),
+            _ => text.to_string(),
+        }
+    }
+}
+
+pub async fn execute(args: PromptArgs) -> Result<()> {
+    // Show help if requested
+    if args.help {
+        show_help();
+        return Ok(());
+    }
+
+    // A broken config shouldn't put an error in every prompt
+    let Ok(config) = Config::load().await else {
+        return Ok(());
+    };
+    let format = args.format.unwrap_or_else(|| config.prompt.format.clone());
+    println!("{}", render(&config, &format, args.color));
+    Ok(())
+}
+
+/// Fill in `format` from the environment, the kubeconfig and the cached certificate
+/// expiry. Nothing here runs tsh or kubectl: this runs with every prompt.
+fn render(config: &Config, format: &str, color: Color) -> String {
+    let prod = |names: &[&str]| {
+        let pattern = config.prompt.prod_match.as_str();
+        if !pattern.is_empty() && names.iter().any(|name| name.contains(pattern)) { Style::Alert } else { Style::Plain }
+    };
+    let var = |key: &str| std::env::var(key).ok().filter(|value| !value.is_empty());
+
+    let context = kube_context();
+    let kube = context.as_deref().map(|context| match kube_env(config, context) {
+        Some((env, cluster)) => color.paint(&format!("⎈ {}", env), prod(&[env, cluster])),
+        None => color.paint(&format!("⎈ {}", context), prod(&[context])),
+    });
+
+    let (account, role) = (var("ACCOUNT"), var("ROLE"));
+    let aws = account.as_deref().map(|account| {
+        let env = aws_env(config, account).unwrap_or_default();
+        let text = match &role {
+            Some(role) => format!("☁ {}:{}", account, role),
+            None => format!("☁ {}", account),
+        };
+        color.paint(&text, prod(&[env, account]))
+    });
+
+    let expiry = teleport::cached_expiry(config).map(|expires| {
+        let left = (expires - Utc::now()).num_seconds();
+        if left <= 0 {
+            color.paint("⏳expired", Style::Alert)
+        } else if left < config.teleport.renew_before_minutes as i64 * 60 {
+            color.paint(&format!("⏳{}", format_duration(left)), Style::Warning)
+        } else {
+            color.paint(&format!("⏳{}", format_duration(left)), Style::Plain)
+        }
+    });
+
+    let env = var(ENV_MARKER).map(|env| color.paint(&env, prod(&[&env])));
+
+    let segments = [
+        ("{kube}", kube),
+        ("{aws}", aws),
+        ("{expiry}", expiry),
+        ("{env}", env),
+        ("{account}", account.map(|account| color.escape(&account))),
+        ("{role}", role.map(|role| color.escape(&role))),
+        ("{context}", context.map(|context| color.escape(&context))),
+    ];
+    let filled = segments.iter().fold(format.to_string(), |out, (placeholder, value)| {
+        out.replace(placeholder, value.as_deref().unwrap_or(""))
+    });
+    // Missing segments leave their separators behind
+    filled.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// `current-context` from the first kubeconfig that sets one, read directly rather than
+/// through kubectl
+fn kube_context() -> Option<String> {
+    let paths: Vec<PathBuf> = match std::env::var_os("KUBECONFIG").filter(|value| !value.is_empty()) {
+        Some(list) => std::env::split_paths(&list).collect(),
+        None => vec![dirs::home_dir()?.join(".kube").join("config")],
+    };
+    paths.iter().find_map(|path| {
+        let content = std::fs::read_to_string(path).ok()?;
+        content.lines()
+            .find_map(|line| line.strip_prefix("current-context:"))
+            .map(|context| context.trim().trim_matches(|c| c == '"' || c == '\'').to_string())
+            .filter(|context| !context.is_empty())
+    })
+}
+
+/// `[kube]` env and cluster for a context; tsh names contexts `<teleport cluster>-<kube cluster>`.
+/// The shortest env wins when several share a cluster.
+fn kube_env<'a>(config: &'a Config, context: &str) -> Option<(&'a str, &'a str)> {
+    config.kube.iter()
+        .map(|(env, target)| (env.as_str(), split_target(target).1))
+        .filter(|(_, cluster)| context == *cluster || context.ends_with(&format!("-{}", cluster)))
+        .min_by_key(|(env, _)| (env.len(), *env))
+}
+
+/// `[aws]` env for an account, the shortest when several share it
+fn aws_env<'a>(config: &'a Config, account: &str) -> Option<&'a str> {
+    config.aws.iter()
+        .filter(|(_, target)| *target == account)
+        .map(|(env, _)| env.as_str())
+        .min_by_key(|env| (env.len(), *env))
+}
+
+fn show_help() {
+    clear_screen().unwrap();
+    create_header("th prompt");
+    println!("Print a short segment for your prompt, e.g. {}.", "⎈ staging ☁ yl-production:sudo_prod ⏳42m".bold());
+    println!("It only reads local files, so it is fast enough to run with every prompt.\n");
+    println!("Usage: {}", "th prompt [options]".bold());
+    println!(" ╚═ {}  : Template; {}, {}, {}, {},", "--format <template>".bold(), "{kube}".bold(), "{aws}".bold(), "{expiry}".bold(), "{env}".bold());
+    println!("                          {}, {} and {} (default {})", "{account}".bold(), "{role}".bold(), "{context}".bold(), "prompt.format".bold());
+    println!(" ╚═ {}      : {}, {} (default), {}, {} or {}", "--color <how>".bold(), "never".bold(), "ansi".bold(), "bash".bold(), "zsh".bold(), "tmux".bold());
+    println!("\nSegments for environments matching {} are red.", "prompt.prod_match".bold());
+    println!("\nExamples:");
+    println!(" ╚═ {}", display_code(r#"PS1='$(th prompt --color bash) '"$PS1""#));
+    println!(" ╚═ {}", display_code(r#"setopt PROMPT_SUBST; PROMPT='$(th prompt --color zsh) '$PROMPT"#));
+    println!(" ╚═ {}", display_code("set -g status-right '#(th prompt --color tmux)'"));
+}
@@ -0,0 +1,155 @@
+use crate::backend::ProxyRequest;
+use crate::cli::{ProxyArgs, ProxyCommand};
+use crate::commands::aws;
+use crate::config::Config;
+use crate::display::{clear_screen, create_header, print_info, print_success, display_code, format_duration};
+use crate::error::ThError;
+use crate::proxies::{self, ProxyEntry};
+use crate::teleport::TeleportClient;
+use crate::watchdog;
+use anyhow::Result;
+use chrono::Utc;
+use colored::*;
+use std::io::{Read, Seek, SeekFrom, Write};
+
+pub async fn execute(args: ProxyArgs, config: &Config) -> Result<()> {
+    // Show help if requested
+    if args.help {
+        show_help();
+        return Ok(());
+    }
+
+    match args.command {
+        Some(ProxyCommand::Ls) | None => list(config),
+        Some(ProxyCommand::Stop { id }) => stop(config, &id),
+        Some(ProxyCommand::Restart { id }) => restart(config, id).await,
+        Some(ProxyCommand::Watch { detach }) => watch(config, detach).await,
+        Some(ProxyCommand::Logs { id, follow }) => logs(config, id, follow).await,
+    }
+}
+
+fn list(config: &Config) -> Result<()> {
+    let entries = proxies::list(config)?;
+    if entries.is_empty() {
+        print_info("No proxies running");
+        return Ok(());
+    }
+
+    println!("{}", format!("{:<4} {:<5} {:<36} {:<7} {:<32} {:<8} {:<8} {}", "ID", "KIND", "TARGET", "PORT", "USER", "UPTIME", "RESTARTS", "PID").bold());
+    for entry in &entries {
+        // Only listed while down when the watchdog is about to restart it
+        let uptime = if entry.is_running() { uptime(entry) } else { "down".to_string() };
+        println!(
+            "{:<4} {:<5} {:<36} {:<7} {:<32} {:<8} {:<8} {}",
+            entry.id,
+            entry.kind(),
+            entry.target(),
+            entry.port().map(|p| p.to_string()).unwrap_or_else(|| "-".to_string()),
+            entry.user().unwrap_or("-"),
+            uptime,
+            entry.restarts,
+            entry.pid
+        );
+    }
+    if let Some(pid) = watchdog::running_pid(config) {
+        println!("\n{}", format!("Watchdog running (PID {})", pid).dimmed());
+    }
+    Ok(())
+}
+
+fn stop(config: &Config, id: &str) -> Result<()> {
+    let entries = if id == "all" {
+        proxies::list(config)?
+    } else {
+        let id = id.parse::<u32>()
+            .map_err(|_| ThError::InvalidInput(format!("'{}' is not a proxy id - use a number from `th proxy ls` or `all`", id)))?;
+        vec![proxies::find(config, id)?]
+    };
+
+    if entries.is_empty() {
+        print_info("No proxies running");
+    }
+    for entry in &entries {
+        proxies::stop(config, entry)?;
+        print_success(&format!("Stopped proxy {} ({} {})", entry.id, entry.kind(), entry.target()));
+    }
+    Ok(())
+}
+
+async fn restart(config: &Config, id: u32) -> Result<()> {
+    let client = TeleportClient::new(config.clone())?;
+    let entry = proxies::restart(&client, id)?;
+
+    // A new AWS proxy comes with new credentials
+    match entry.request {
+        ProxyRequest::Aws { endpoint_url: false, .. } => aws::export_proxy_credentials(config, &entry).await?,
+        ProxyRequest::Aws { .. } | ProxyRequest::Db { .. } => proxies::wait_ready(config, &entry).await?,
+    }
+
+    let port = entry.port().map(|p| format!(" on port {}", p)).unwrap_or_default();
+    print_success(&format!("Restarted proxy {} ({} {}){}", entry.id, entry.kind(), entry.target(), port));
+    Ok(())
+}
+
+async fn watch(config: &Config, detach: bool) -> Result<()> {
+    if detach {
+        watchdog::spawn_detached(config)?;
+        print_success(&format!("Watchdog started - logging to {}", proxies::runtime_dir(config).join("logs").join("watchdog.log").display()));
+        return Ok(());
+    }
+    let client = TeleportClient::new(config.clone())?;
+    watchdog::run(&client).await
+}
+
+async fn logs(config: &Config, id: u32, follow: bool) -> Result<()> {
+    let entry = proxies::find(config, id)?;
+    let mut file = std::fs::File::open(&entry.log)?;
+    let mut stdout = std::io::stdout();
+
+    let mut content = String::new();
+    file.read_to_string(&mut content)?;
+    print!("{}", content);
+    stdout.flush()?;
+
+    if !follow {
+        return Ok(());
+    }
+
+    // Poll for new output like `tail -f`, stopping when the proxy exits
+    let mut position = file.seek(SeekFrom::End(0))?;
+    while entry.is_running() {
+        tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+        let length = file.metadata()?.len();
+        if length < position {
+            position = 0;
+        }
+        if length > position {
+            file.seek(SeekFrom::Start(position))?;
+            let mut chunk = String::new();
+            file.read_to_string(&mut chunk)?;
+            print!("{}", chunk);
+            stdout.flush()?;
+            position = length;
+        }
+    }
+    Ok(())
+}
+
+pub fn uptime(entry: &ProxyEntry) -> String {
+    format_duration((Utc::now() - entry.started_at).num_seconds())
+}
+
+fn show_help() {
+    clear_screen().unwrap();
+    create_header("th proxy");
+    println!("Manage the tsh proxies th starts in the background for AWS and databases.\n");
+    println!("Usage: {}", "th proxy <command>".bold());
+    println!(" ╚═ {}              : List running proxies with their ports", "th proxy ls".bold());
+    println!(" ╚═ {}       : Stop one proxy, or every proxy with {}", "th proxy stop <id>".bold(), "all".bold());
+    println!(" ╚═ {}    : Restart a proxy on the same port", "th proxy restart <id>".bold());
+    println!(" ╚═ {}       : Print a proxy's output ({} to follow)", "th proxy logs <id>".bold(), "-f".bold());
+    println!(" ╚═ {}           : Restart dead proxies and renew expired certs", "th proxy watch".bold());
+    println!("                          ({} to run in the background)", "-d".bold());
+    println!("\nExamples:");
+    println!(" ╚═ {}      : stops every proxy th started", display_code("th proxy stop all"));
+}
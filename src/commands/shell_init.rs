@@ -0,0 +1,88 @@
+use crate::cli::ShellInitArgs;
+use crate::display::{clear_screen, create_header, display_code};
+use crate::error::ThError;
+use crate::shell::{Shell, ENV_FILE_VAR, ENV_SHELL_VAR};
+use anyhow::Result;
+use colored::*;
+
+pub async fn execute(args: ShellInitArgs) -> Result<()> {
+    // Show help if requested
+    if args.help {
+        show_help();
+        return Ok(());
+    }
+
+    let shell = args.shell.unwrap_or_else(Shell::detect);
+    let program = std::env::current_exe()?.display().to_string();
+    println!("{}", wrapper(shell, &program)?);
+    Ok(())
+}
+
+/// A `th` function that runs `program` with a fresh env file and evaluates whatever
+/// the command wrote to it, so any command can change the calling shell's variables
+fn wrapper(shell: Shell, program: &str) -> Result<String> {
+    let (env_file, env_shell) = (ENV_FILE_VAR, ENV_SHELL_VAR);
+    let name = format!("{:?}", shell).to_lowercase();
+    let script = match shell {
+        Shell::Bash | Shell::Zsh => format!(
+            r#"th() {{
+    local th_env_file th_status
+    th_env_file=$(mktemp "${{TMPDIR:-/tmp}}/th-env.XXXXXX") || return
+    {env_file}="$th_env_file" {env_shell}={name} command '{program}' "$@"
+    th_status=$?
+    [ -s "$th_env_file" ] && . "$th_env_file"
+    rm -f "$th_env_file"
+    return $th_status
+}}"#,
+            program = program.replace('\'', r"'\''"),
+        ),
+        Shell::Fish => format!(
+            r#"function th
+    set -l th_env_file (mktemp)
+    or return
+    env {env_file}=$th_env_file {env_shell}={name} '{program}' $argv
+    set -l th_status $status
+    test -s $th_env_file; and source $th_env_file
+    rm -f $th_env_file
+    return $th_status
+end"#,
+            program = program.replace('\\', r"\\").replace('\'', r"\'"),
+        ),
+        Shell::Powershell => format!(
+            r#"function th {{
+    $thEnvFile = [System.IO.Path]::GetTempFileName()
+    $Env:{env_file} = $thEnvFile
+    $Env:{env_shell} = '{name}'
+    try {{
+        & '{program}' @args
+    }} finally {{
+        Remove-Item Env:{env_file}, Env:{env_shell} -ErrorAction SilentlyContinue
+    }}
+    $thStatus = $LASTEXITCODE
+    $thEnv = Get-Content -Raw $thEnvFile
+    if ($thEnv) {{ Invoke-Expression $thEnv }}
+    Remove-Item $thEnvFile -ErrorAction SilentlyContinue
+    $global:LASTEXITCODE = $thStatus
+}}"#,
+            program = program.replace('\'', "''"),
+        ),
+        Shell::Cmd | Shell::Json => {
+            return Err(ThError::InvalidInput("th shell-init supports bash, zsh, fish and powershell - cmd.exe uses th.bat".to_string()).into());
+        }
+    };
+    Ok(script)
+}
+
+fn show_help() {
+    clear_screen().unwrap();
+    create_header("th shell-init");
+    println!("Print the shell function that lets th change your shell's environment,");
+    println!("e.g. export AWS credentials after {} or clear them after {}.\n", "th a".bold(), "th l".bold());
+    println!("Usage: {}", "th shell-init [bash|zsh|fish|powershell]".bold());
+    println!(" ╚═ Detected from {} when left out\n", "$SHELL".bold());
+    println!("Add one of these to your shell's rc file:");
+    println!(" ╚═ {}", display_code(r#"eval "$(th shell-init zsh)""#));
+    println!(" ╚═ {}", display_code(r#"eval "$(th shell-init bash)""#));
+    println!(" ╚═ {}", display_code("th shell-init fish | source"));
+    println!(" ╚═ {}", display_code("th shell-init powershell | Out-String | Invoke-Expression"));
+}
@@ -0,0 +1,167 @@
+use crate::backend::ProxyRequest;
+use crate::cli::StatusArgs;
+use crate::commands::aws::{self, ExportedSession};
+use crate::commands::proxy;
+use crate::config::Config;
+use crate::display::{clear_screen, create_header, display_code, format_duration};
+use crate::proxies::{self, ProxyEntry};
+use crate::teleport::TeleportClient;
+use anyhow::Result;
+use chrono::{DateTime, Local, Utc};
+use colored::*;
+use serde::Serialize;
+
+/// Everything th has set up, as printed by `th status --json`
+#[derive(Debug, Serialize)]
+struct SessionStatus {
+    teleport: TeleportSession,
+    aws: Option<ExportedSession>,
+    kube_context: Option<String>,
+    proxies: Vec<ProxyStatus>,
+}
+
+#[derive(Debug, Serialize)]
+struct TeleportSession {
+    logged_in: bool,
+    proxy: String,
+    profile: Option<String>,
+    user: Option<String>,
+    cluster: Option<String>,
+    expires: Option<DateTime<Utc>>,
+    /// Less than `teleport.renew_before_minutes` left
+    expiring: bool,
+    roles: Vec<String>,
+    active_requests: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct ProxyStatus {
+    #[serde(flatten)]
+    entry: ProxyEntry,
+    running: bool,
+}
+
+pub async fn execute(args: StatusArgs, config: &Config) -> Result<()> {
+    // Show help if requested
+    if args.help {
+        show_help();
+        return Ok(());
+    }
+
+    let client = TeleportClient::new(config.clone())?;
+    let status = collect(&client).await?;
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&status)?);
+    } else {
+        print_status(&status);
+    }
+    Ok(())
+}
+
+async fn collect(client: &TeleportClient) -> Result<SessionStatus> {
+    let config = client.config();
+    let tsh = client.get_status().await?;
+    let expiring = tsh.logged_in && !tsh.valid_for(client.renew_before());
+
+    Ok(SessionStatus {
+        teleport: TeleportSession {
+            logged_in: tsh.logged_in,
+            proxy: config.teleport.proxy.clone(),
+            profile: config.profile.clone(),
+            user: tsh.user,
+            cluster: tsh.cluster,
+            expires: tsh.expires,
+            expiring,
+            roles: tsh.roles,
+            active_requests: tsh.active_requests,
+        },
+        aws: aws::exported_session(),
+        kube_context: client.kube_current_context().await,
+        proxies: proxies::list(config)?
+            .into_iter()
+            .map(|entry| ProxyStatus { running: entry.is_running(), entry })
+            .collect(),
+    })
+}
+
+fn print_status(status: &SessionStatus) {
+    let teleport = &status.teleport;
+
+    println!("{}", "Teleport".bold());
+    let profile = teleport.profile.as_ref().map(|p| format!(", profile {}", p)).unwrap_or_default();
+    match (&teleport.user, teleport.logged_in) {
+        (Some(user), true) => println!(" ╚═ Logged in as {} ({}{})", user.green(), teleport.proxy, profile),
+        _ => println!(" ╚═ {} to {}{} - run {}", "Not logged in".red(), teleport.proxy, profile, display_code("th login")),
+    }
+    if let Some(expires) = teleport.expires {
+        let left = (expires - Utc::now()).num_seconds();
+        let until = expires.with_timezone(&Local).format("%Y-%m-%d %H:%M");
+        if teleport.expiring && left > 0 {
+            println!(" ╚═ Certificate valid until {} ({} - run {})", until, format!("{} left", format_duration(left)).yellow(), display_code("th login"));
+        } else if left > 0 {
+            println!(" ╚═ Certificate valid until {} ({} left)", until, format_duration(left));
+        } else {
+            println!(" ╚═ Certificate {} at {}", "expired".red(), until);
+        }
+    }
+    if teleport.logged_in {
+        println!(" ╚═ Roles: {}", list_or_none(&teleport.roles));
+        println!(" ╚═ Access requests: {}", list_or_none(&teleport.active_requests));
+    }
+
+    println!("\n{}", "AWS".bold());
+    match &status.aws {
+        Some(session) => {
+            let region = session.region.as_ref().map(|r| format!(" ({})", r)).unwrap_or_default();
+            println!(" ╚═ {} as {}{}", session.account.green(), session.role.green(), region);
+            let proxy = status.proxies.iter().find(|p| {
+                matches!(&p.entry.request, ProxyRequest::Aws { app, .. } if *app == session.account)
+            });
+            match proxy.and_then(|p| p.entry.port().filter(|_| p.running)) {
+                Some(port) => println!(" ╚═ Proxy on port {}", port),
+                None => println!(" ╚═ {}", "Proxy not running - credentials will not work".yellow()),
+            }
+            if !session.in_shell {
+                if let Some(file) = &session.credentials_file {
+                    println!(" ╚═ {}", format!("Not exported in this shell - open a new one or `source {}`", file.display()).dimmed());
+                }
+            }
+        }
+        None => println!(" ╚═ No credentials exported"),
+    }
+
+    println!("\n{}", "Kubernetes".bold());
+    match &status.kube_context {
+        Some(context) => println!(" ╚═ Context: {}", context.green()),
+        None => println!(" ╚═ No context selected"),
+    }
+
+    println!("\n{}", "Proxies".bold());
+    if status.proxies.is_empty() {
+        println!(" ╚═ None running");
+    }
+    for proxy in &status.proxies {
+        let entry = &proxy.entry;
+        let port = entry.port().map(|p| format!("port {}", p)).unwrap_or_else(|| "no port".to_string());
+        let state = if proxy.running { format!("up {}", proxy::uptime(entry)) } else { "down".red().to_string() };
+        println!(" ╚═ {:<3} {:<4} {:<36} {:<11} {}", entry.id, entry.kind(), entry.target(), port, state);
+    }
+}
+
+fn list_or_none(items: &[String]) -> String {
+    if items.is_empty() { "none".dimmed().to_string() } else { items.join(", ") }
+}
+
+fn show_help() {
+    clear_screen().unwrap();
+    create_header("th status");
+    println!("Show what th has set up in one place.\n");
+    println!("Usage: {}", "th status [options]".bold());
+    println!(" ╚═ {}         : Teleport login, certificate expiry, roles and access", "th status".bold());
+    println!("                          requests, exported AWS account and role, kube");
+    println!("                          context and running proxies");
+    println!(" ╚═ {}  : The same as JSON, for scripts", "th status --json".bold());
+    println!("\nExamples:");
+    println!(" ╚═ {} : prints the certificate expiry", display_code("th status --json | jq .teleport.expires"));
+}
@@ -12,24 +12,25 @@ pub async fn execute(args: TerraArgs, config: &Config) -> Result<()> {
         return Ok(());
     }
 
-    let client = TeleportClient::new(config.clone());
+    let client = TeleportClient::new(config.clone())?;
 
     // Call th_login at start like bash version does
-    crate::display::th_login().await?;
+    crate::display::th_login(&client).await?;
     
     clear_screen()?;
     create_header("Terragrunt Login");
     
-    // Logout from existing AWS sessions
-    let _ = client.aws_logout().await;
+    let terraform = &config.roles.terraform;
     
-    println!("\x1b[1mLogging into \x1b[1;32myl-admin\x1b[0m \x1b[1mas\x1b[0m \x1b[1;32msudo_admin\x1b[0m");
+    // Logout from an existing session for the account; other accounts stay logged in
+    let _ = client.aws_logout(Some(&terraform.account)).await;
+    println!("\x1b[1mLogging into \x1b[1;32m{}\x1b[0m \x1b[1mas\x1b[0m \x1b[1;32m{}\x1b[0m", terraform.account, terraform.role);
     
-    // Login to yl-admin with sudo_admin role - silently like bash version
-    client.aws_login("yl-admin", "sudo_admin").await?;
+    // Login to the terraform account with its admin role - silently like bash version
+    client.aws_login(&terraform.account, &terraform.role).await?;
     
     // Create proxy like bash version - now using public create_proxy function
-    crate::commands::aws::create_proxy("yl-admin", "sudo_admin").await?;
+    crate::commands::aws::create_proxy(&client, &terraform.account, &terraform.role).await?;
 
     Ok(())
 }
@@ -0,0 +1,154 @@
+use super::{Config, DiscoveryConfig};
+use crate::teleport::TeleportClient;
+use anyhow::Result;
+use std::collections::{BTreeMap, HashMap};
+
+/// Env → resource maps built from Teleport labels
+#[derive(Debug, Clone, Default)]
+pub struct Discovered {
+    pub kube: BTreeMap<String, String>,
+    pub aws: BTreeMap<String, String>,
+    pub databases: BTreeMap<String, String>,
+}
+
+/// One change `th config sync` would make to a map
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    Added { env: String, name: String },
+    Changed { env: String, from: String, to: String },
+    Removed { env: String, name: String },
+}
+
+impl Discovered {
+    /// Ask Teleport for every app, kube cluster and database and map them by label
+    pub async fn fetch(client: &TeleportClient) -> Result<Self> {
+        let config = client.config();
+        let apps = client.list_aws_apps().await?;
+        let clusters = client.list_kube_resources().await?;
+        let databases = client.list_databases().await?;
+
+        Ok(Self {
+            aws: env_map(
+                &config.discovery,
+                apps.iter().map(|app| (app.qualified_name(), &app.metadata.labels)),
+                &config.aws,
+            ),
+            kube: env_map(
+                &config.discovery,
+                clusters.iter().map(|cluster| (cluster.qualified_name(), &cluster.labels)),
+                &config.kube,
+            ),
+            databases: env_map(
+                &config.discovery,
+                databases.iter().map(|db| (db.qualified_name(), &db.metadata.labels)),
+                &config.databases,
+            ),
+        })
+    }
+
+    /// The map for one of `layers::ENV_SECTIONS`
+    pub fn section(&self, section: &str) -> &BTreeMap<String, String> {
+        match section {
+            "kube" => &self.kube,
+            "aws" => &self.aws,
+            _ => &self.databases,
+        }
+    }
+
+    /// `(section, changes)` against what `config` currently uses
+    pub fn diff(&self, config: &Config) -> Vec<(&'static str, Vec<Change>)> {
+        vec![
+            ("kube", diff_map(&config.kube, &self.kube)),
+            ("aws", diff_map(&config.aws, &self.aws)),
+            ("databases", diff_map(&config.databases, &self.databases)),
+        ]
+    }
+}
+
+/// Env name for a resource's labels: `env=staging, region=us` → `usstaging`.
+/// Resources in the home region (or without a region) keep the bare env name.
+pub fn env_name(discovery: &DiscoveryConfig, labels: &BTreeMap<String, String>) -> Option<String> {
+    let env = labels.get(&discovery.env_label)?.trim().to_lowercase();
+    if env.is_empty() {
+        return None;
+    }
+
+    let region = labels.get(&discovery.region_label)
+        .map(|r| r.trim().to_lowercase())
+        .unwrap_or_default();
+    if region.is_empty() || region == discovery.home_region.to_lowercase() || env.starts_with(&region) {
+        Some(env)
+    } else {
+        Some(format!("{}{}", region, env))
+    }
+}
+
+/// Map env names to resource names (`leaf/name` for leaf cluster resources). When several resources share an env (blue/green
+/// EKS pairs), each gets `<env><suffix>` from the last `-` segment of its name, and
+/// the bare env keeps pointing where it did before - or at the first by name.
+fn env_map<'a>(
+    discovery: &DiscoveryConfig,
+    resources: impl Iterator<Item = (String, &'a BTreeMap<String, String>)>,
+    current: &HashMap<String, String>,
+) -> BTreeMap<String, String> {
+    let mut by_env: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for (name, labels) in resources {
+        if let Some(env) = env_name(discovery, labels) {
+            by_env.entry(env).or_default().push(name);
+        }
+    }
+
+    let mut map = BTreeMap::new();
+    for (env, mut names) in by_env {
+        names.sort();
+        names.dedup();
+        if let [name] = names.as_slice() {
+            map.insert(env, name.to_string());
+            continue;
+        }
+
+        let bare = current.get(&env)
+            .filter(|name| names.contains(name))
+            .cloned()
+            .unwrap_or_else(|| names[0].to_string());
+        for name in &names {
+            let suffix = name.rsplit(['-', '/']).next().unwrap_or(name).to_lowercase();
+            map.insert(format!("{}{}", env, suffix), name.to_string());
+        }
+        map.insert(env, bare);
+    }
+    map
+}
+
+fn diff_map(current: &HashMap<String, String>, discovered: &BTreeMap<String, String>) -> Vec<Change> {
+    let mut changes = Vec::new();
+    for (env, name) in discovered {
+        match current.get(env) {
+            None => changes.push(Change::Added { env: env.clone(), name: name.clone() }),
+            Some(old) if old != name => changes.push(Change::Changed { env: env.clone(), from: old.clone(), to: name.clone() }),
+            Some(_) => {}
+        }
+    }
+
+    let mut removed: Vec<_> = current.iter()
+        .filter(|(env, _)| !discovered.contains_key(*env))
+        .collect();
+    removed.sort();
+    for (env, name) in removed {
+        changes.push(Change::Removed { env: env.clone(), name: name.clone() });
+    }
+    changes
+}
+
+/// On-demand discovery for an env missing from the config, when `discovery.auto` is on
+pub async fn find_env(
+    client: &TeleportClient,
+    env: &str,
+    section: fn(&Discovered) -> &BTreeMap<String, String>,
+) -> Option<String> {
+    if !client.config().discovery.auto {
+        return None;
+    }
+    let discovered = Discovered::fetch(client).await.ok()?;
+    section(&discovered).get(env).cloned()
+}
@@ -0,0 +1,140 @@
+use super::layers::{find_project_config, parse_value, PROJECT_CONFIG_FILE};
+use super::migrate::stamp_version;
+use super::validate::{is_known_key, type_error};
+use super::Config;
+use crate::error::ThError;
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+use toml_edit::{DocumentMut, Item, Table, TableLike};
+
+/// File written by `th config set|unset|edit`
+pub fn target_path(project: bool) -> Result<PathBuf> {
+    if !project {
+        return Config::get_config_path();
+    }
+    let cwd = std::env::current_dir()?;
+    Ok(find_project_config(&cwd).unwrap_or_else(|| cwd.join(PROJECT_CONFIG_FILE)))
+}
+
+/// Set `key` to `raw` in the file at `path`, keeping its comments and layout
+pub fn set_key(path: &Path, key: &str, raw: &str) -> Result<()> {
+    if !is_known_key(key) {
+        return Err(ThError::InvalidInput(format!("Unknown config key '{}'", key)).into());
+    }
+    let value = parse_value(raw);
+    if let Some(message) = type_error(key, value.clone()) {
+        return Err(ThError::InvalidInput(format!("{}: {}", key, message)).into());
+    }
+
+    let mut doc = read_document(path)?;
+    let segments: Vec<&str> = key.split('.').collect();
+    let (last, parents) = segments.split_last()
+        .ok_or_else(|| ThError::InvalidInput("Empty config key".to_string()))?;
+
+    let mut table: &mut dyn TableLike = doc.as_table_mut();
+    for segment in parents {
+        if !table.contains_key(segment) {
+            let mut child = Table::new();
+            child.set_implicit(true);
+            table.insert(segment, Item::Table(child));
+        }
+        table = table.get_mut(segment)
+            .and_then(|item| item.as_table_like_mut())
+            .ok_or_else(|| ThError::InvalidInput(format!("'{}' in {} is not a table", segment, key)))?;
+    }
+    table.insert(last, to_item(&value)?);
+
+    write_atomic(path, &doc.to_string())
+}
+
+/// Remove `key` from the file at `path`; returns whether it was there
+pub fn unset_key(path: &Path, key: &str) -> Result<bool> {
+    if !path.is_file() {
+        return Ok(false);
+    }
+    let mut doc = read_document(path)?;
+    let segments: Vec<&str> = key.split('.').collect();
+    let removed = remove_path(doc.as_table_mut(), &segments);
+    if removed {
+        write_atomic(path, &doc.to_string())?;
+    }
+    Ok(removed)
+}
+
+/// Read the file at `path`, let `change` edit it, and write it back
+pub fn update_document(path: &Path, change: impl FnOnce(&mut DocumentMut)) -> Result<()> {
+    let mut doc = read_document(path)?;
+    change(&mut doc);
+    write_atomic(path, &doc.to_string())
+}
+
+/// Remove a key path, dropping tables it leaves empty
+pub fn remove_path(table: &mut dyn TableLike, path: &[&str]) -> bool {
+    match path {
+        [] => false,
+        [last] => table.remove(last).is_some(),
+        [first, rest @ ..] => {
+            let (removed, now_empty) = match table.get_mut(first).and_then(|item| item.as_table_like_mut()) {
+                Some(child) => {
+                    let removed = remove_path(child, rest);
+                    (removed, child.is_empty())
+                }
+                None => (false, false),
+            };
+            if removed && now_empty {
+                table.remove(first);
+            }
+            removed
+        }
+    }
+}
+
+fn read_document(path: &Path) -> Result<DocumentMut> {
+    if !path.exists() {
+        let mut doc = DocumentMut::new();
+        stamp_version(&mut doc);
+        return Ok(doc);
+    }
+    let content = std::fs::read_to_string(path)?;
+    content.parse::<DocumentMut>()
+        .map_err(|e| ThError::Config(format!("{}: {}", path.display(), e)).into())
+}
+
+fn to_item(value: &toml::Value) -> Result<Item> {
+    let doc = format!("value = {}", value).parse::<DocumentMut>()
+        .map_err(|e| ThError::InvalidInput(e.to_string()))?;
+    Ok(doc.as_table().get("value").cloned().unwrap_or(Item::None))
+}
+
+/// Write via a temp file and rename so a crash never leaves a half-written file. Each
+/// writer gets its own temp file, so concurrent th processes can't clobber each other's.
+pub fn write_atomic(path: &Path, content: &str) -> Result<()> {
+    static WRITES: AtomicU32 = AtomicU32::new(0);
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let name = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+    let tmp = path.with_file_name(format!(
+        ".{}.{}-{}.tmp",
+        name,
+        std::process::id(),
+        WRITES.fetch_add(1, Ordering::Relaxed)
+    ));
+    std::fs::write(&tmp, content)?;
+    if let Err(e) = std::fs::rename(&tmp, path) {
+        let _ = std::fs::remove_file(&tmp);
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+/// Copy `path` to `<path>.bak`, returning the backup's path
+pub fn backup(path: &Path) -> Result<PathBuf> {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".bak");
+    let backup = PathBuf::from(name);
+    std::fs::copy(path, &backup)?;
+    Ok(backup)
+}
@@ -0,0 +1,461 @@
+use super::{migrate, Config};
+use crate::error::ThError;
+use anyhow::Result;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use toml::{Table, Value};
+use toml_edit::DocumentMut;
+
+/// Team-wide config shipped by the platform team
+pub const SYSTEM_CONFIG_PATH: &str = "/etc/th/config.toml";
+/// Overrides `SYSTEM_CONFIG_PATH`
+pub const SYSTEM_CONFIG_ENV: &str = "TH_SYSTEM_CONFIG";
+/// Per-repo config, found by walking up from the cwd
+pub const PROJECT_CONFIG_FILE: &str = ".th.toml";
+/// `TH_TELEPORT__PROXY=...` sets `teleport.proxy`
+pub const ENV_PREFIX: &str = "TH_";
+pub const ENV_SEPARATOR: &str = "__";
+/// Environment → resource maps, filled in by `th config sync`
+pub const ENV_SECTIONS: &[&str] = &["kube", "aws", "databases"];
+/// Selects a `[profiles.<name>]` table; `--profile` sets it too
+pub const PROFILE_ENV: &str = "TH_PROFILE";
+
+/// Where a configuration value came from, lowest precedence first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LayerKind {
+    Default,
+    System,
+    User,
+    Project,
+    Profile,
+    Env,
+}
+
+impl fmt::Display for LayerKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            LayerKind::Default => "default",
+            LayerKind::System => "system",
+            LayerKind::User => "user",
+            LayerKind::Project => "project",
+            LayerKind::Profile => "profile",
+            LayerKind::Env => "env",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// One source of configuration values
+#[derive(Debug, Clone)]
+pub struct ConfigLayer {
+    pub kind: LayerKind,
+    pub path: Option<PathBuf>,
+    pub values: Table,
+}
+
+impl ConfigLayer {
+    /// e.g. `user (~/.config/th/config.toml)` or `profile (staging)`
+    pub fn describe(&self) -> String {
+        match (&self.path, self.values.get("profile")) {
+            (Some(path), _) => format!("{} ({})", self.kind, path.display()),
+            (None, Some(Value::String(name))) if self.kind == LayerKind::Profile => format!("{} ({})", self.kind, name),
+            _ => self.kind.to_string(),
+        }
+    }
+
+    /// Whether this layer sets `key` (a dotted path like `teleport.proxy`)
+    pub fn contains(&self, key: &str) -> bool {
+        lookup(&self.values, key).is_some()
+    }
+}
+
+/// Every layer that contributed to the running configuration
+#[derive(Debug, Clone)]
+pub struct LayeredConfig {
+    pub layers: Vec<ConfigLayer>,
+}
+
+impl LayeredConfig {
+    /// Read every layer that exists: built-in defaults, system, user, project, then env vars
+    pub fn discover() -> Result<Self> {
+        let mut layers = vec![default_layer()?];
+
+        for (kind, path) in layer_files()? {
+            if path.is_file() {
+                layers.push(ConfigLayer {
+                    kind,
+                    values: read_table(&path, kind)?,
+                    path: Some(path),
+                });
+            }
+        }
+
+        layers.extend(env_layer());
+
+        let mut layered = Self { layers };
+        layered.drop_synced_defaults();
+        layered.apply_profile()?;
+        Ok(layered)
+    }
+
+    /// Profile picked by `TH_PROFILE`/`--profile`, or the `profile` key in a config file
+    pub fn selected_profile(&self) -> Option<String> {
+        std::env::var(PROFILE_ENV).ok()
+            .filter(|name| !name.is_empty())
+            .or_else(|| lookup(&self.merged(), "profile").and_then(|v| v.as_str()).map(|s| s.to_string()))
+    }
+
+    /// Names of every `[profiles.<name>]` table
+    pub fn profile_names(&self) -> Vec<String> {
+        match lookup(&self.merged(), "profiles") {
+            Some(Value::Table(profiles)) => profiles.keys().cloned().collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Add the selected profile as a layer above the files. Env maps it defines
+    /// replace the ones below rather than merging, as they belong to another cluster.
+    pub fn apply_profile(&mut self) -> Result<()> {
+        let Some(name) = self.selected_profile() else {
+            return Ok(());
+        };
+
+        let mut values = match lookup(&self.merged(), &format!("profiles.{}", name)) {
+            Some(Value::Table(values)) => values.clone(),
+            _ => {
+                let available = self.profile_names();
+                let available = if available.is_empty() { "none defined".to_string() } else { available.join(", ") };
+                return Err(ThError::Config(format!("Unknown profile '{}' (available: {})", name, available)).into());
+            }
+        };
+
+        for section in ENV_SECTIONS {
+            if values.contains_key(*section) {
+                for layer in &mut self.layers {
+                    layer.values.remove(*section);
+                }
+            }
+        }
+        values.insert("profile".to_string(), Value::String(name));
+
+        let position = self.layers.iter()
+            .position(|layer| layer.kind == LayerKind::Env)
+            .unwrap_or(self.layers.len());
+        self.layers.insert(position, ConfigLayer { kind: LayerKind::Profile, path: None, values });
+        Ok(())
+    }
+
+    /// Once `th config sync` has written an env map, the built-in one would only bring
+    /// back environments Teleport no longer has. Sections sync skipped keep theirs.
+    pub fn drop_synced_defaults(&mut self) {
+        let mut synced: Vec<String> = Vec::new();
+        for layer in self.layers.iter().filter(|layer| layer.kind != LayerKind::Default) {
+            match lookup(&layer.values, "discovery.synced_sections") {
+                Some(Value::Array(sections)) => {
+                    synced.extend(sections.iter().filter_map(|s| s.as_str()).map(|s| s.to_string()));
+                }
+                // Synced before sections were recorded: the maps that sync wrote
+                _ if layer.contains("discovery.synced_at") => {
+                    synced.extend(ENV_SECTIONS.iter().filter(|s| layer.values.contains_key(**s)).map(|s| s.to_string()));
+                }
+                _ => {}
+            }
+        }
+        for layer in self.layers.iter_mut().filter(|layer| layer.kind == LayerKind::Default) {
+            for section in &synced {
+                layer.values.remove(section);
+            }
+        }
+    }
+
+    /// All layers merged, later layers winning
+    pub fn merged(&self) -> Table {
+        let mut merged = Table::new();
+        for layer in &self.layers {
+            merge(&mut merged, &layer.values);
+        }
+        merged
+    }
+
+    pub fn resolve(&self) -> Result<Config> {
+        Value::Table(self.merged())
+            .try_into()
+            .map_err(|e| ThError::Config(format!(
+                "Invalid configuration: {} (run `th config validate` for details)",
+                e.to_string().trim().replace('\n', " ")
+            )).into())
+    }
+
+    /// Highest-precedence layer that sets `key`
+    pub fn origin(&self, key: &str) -> Option<&ConfigLayer> {
+        self.layers.iter().rev().find(|layer| layer.contains(key))
+    }
+}
+
+pub fn default_layer() -> Result<ConfigLayer> {
+    Ok(ConfigLayer {
+        kind: LayerKind::Default,
+        path: None,
+        values: Table::try_from(Config::default())?,
+    })
+}
+
+pub fn env_layer() -> Option<ConfigLayer> {
+    let values = env_table(std::env::vars());
+    if values.is_empty() {
+        return None;
+    }
+    Some(ConfigLayer {
+        kind: LayerKind::Env,
+        path: None,
+        values,
+    })
+}
+
+/// Candidate files in precedence order; they may not exist
+pub fn layer_files() -> Result<Vec<(LayerKind, PathBuf)>> {
+    let mut files = vec![
+        (LayerKind::System, system_config_path()),
+        (LayerKind::User, Config::get_config_path()?),
+    ];
+    if let Some(project) = std::env::current_dir().ok().and_then(|cwd| find_project_config(&cwd)) {
+        files.push((LayerKind::Project, project));
+    }
+    Ok(files)
+}
+
+pub fn system_config_path() -> PathBuf {
+    std::env::var(SYSTEM_CONFIG_ENV)
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from(SYSTEM_CONFIG_PATH))
+}
+
+/// Nearest `.th.toml` in `start` or any of its parents
+pub fn find_project_config(start: &Path) -> Option<PathBuf> {
+    start.ancestors()
+        .map(|dir| dir.join(PROJECT_CONFIG_FILE))
+        .find(|path| path.is_file())
+}
+
+pub fn read_table(path: &Path, kind: LayerKind) -> Result<Table> {
+    let content = std::fs::read_to_string(path)?;
+    parse_table(&content, kind)
+        .map_err(|e| ThError::Config(format!("{}: {}", path.display(), e)).into())
+}
+
+/// Parse a config file, upgrading it in memory if it uses an older schema
+pub fn parse_table(content: &str, kind: LayerKind) -> Result<Table, String> {
+    let mut doc = content.parse::<DocumentMut>().map_err(|e| e.to_string())?;
+    migrate::migrate(&mut doc, kind);
+    toml::from_str(&doc.to_string()).map_err(|e: toml::de::Error| e.to_string())
+}
+
+/// Build a table from `TH_SECTION__KEY=value` variables; plain `TH_*` variables are not config.
+/// Section and field names are case-insensitive, keys of maps (`TH_PROXY__PORTS__MyApp`) are kept as written.
+fn env_table(vars: impl Iterator<Item = (String, String)>) -> Table {
+    let mut table = Table::new();
+    for (name, raw) in vars {
+        let key = match name.strip_prefix(ENV_PREFIX) {
+            Some(key) if key.contains(ENV_SEPARATOR) => key,
+            _ => continue,
+        };
+        let segments: Vec<&str> = key.split(ENV_SEPARATOR).collect();
+        if segments.iter().any(|segment| segment.is_empty()) {
+            continue;
+        }
+        let mut path: Vec<String> = Vec::new();
+        for segment in segments {
+            let parents: Vec<&str> = path.iter().map(|s| s.as_str()).collect();
+            let segment = if is_map(&parents) { segment.to_string() } else { segment.to_lowercase() };
+            path.push(segment);
+        }
+        let path: Vec<&str> = path.iter().map(|s| s.as_str()).collect();
+        insert(&mut table, &path, parse_value(&raw));
+    }
+    table
+}
+
+/// Whether the table at `path` is a map, whose keys are names rather than fields
+fn is_map(path: &[&str]) -> bool {
+    match path {
+        [section] => ENV_SECTIONS.contains(section) || *section == "profiles",
+        ["proxy", "ports"] | ["roles", "aws" | "kube" | "db"] | ["roles", "db", _, "users"] => true,
+        ["profiles", _, rest @ ..] => is_map(rest),
+        _ => false,
+    }
+}
+
+/// Read a command-line or env value as a TOML literal (`30`, `true`, `["a"]`), falling back to a plain string
+pub fn parse_value(raw: &str) -> Value {
+    toml::from_str::<Table>(&format!("value = {}", raw))
+        .ok()
+        .and_then(|mut t| t.remove("value"))
+        .unwrap_or_else(|| Value::String(raw.to_string()))
+}
+
+/// Set `value` at a key path, creating tables on the way
+pub fn insert(table: &mut Table, path: &[&str], value: Value) {
+    match path {
+        [] => {}
+        [last] => {
+            table.insert(last.to_string(), value);
+        }
+        [first, rest @ ..] => {
+            let entry = table.entry(first.to_string()).or_insert_with(|| Value::Table(Table::new()));
+            if !entry.is_table() {
+                *entry = Value::Table(Table::new());
+            }
+            if let Value::Table(child) = entry {
+                insert(child, rest, value);
+            }
+        }
+    }
+}
+
+/// Deep-merge `overlay` into `base`: tables merge key by key, anything else is replaced
+pub fn merge(base: &mut Table, overlay: &Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(key), value) {
+            (Some(Value::Table(existing)), Value::Table(incoming)) => merge(existing, incoming),
+            _ => {
+                base.insert(key.clone(), value.clone());
+            }
+        }
+    }
+}
+
+/// Value at a dotted key path
+pub fn lookup<'a>(table: &'a Table, key: &str) -> Option<&'a Value> {
+    let mut segments = key.split('.');
+    let mut current = table.get(segments.next()?)?;
+    for segment in segments {
+        current = current.as_table()?.get(segment)?;
+    }
+    Some(current)
+}
+
+/// Every leaf as `(dotted.key, value)`, in table order
+pub fn flatten(table: &Table) -> Vec<(String, Value)> {
+    fn walk(prefix: &str, table: &Table, out: &mut Vec<(String, Value)>) {
+        for (key, value) in table {
+            let path = if prefix.is_empty() { key.clone() } else { format!("{}.{}", prefix, key) };
+            match value {
+                Value::Table(child) if !child.is_empty() => walk(&path, child, out),
+                _ => out.push((path, value.clone())),
+            }
+        }
+    }
+
+    let mut out = Vec::new();
+    walk("", table, &mut out);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layer(kind: LayerKind, content: &str) -> ConfigLayer {
+        ConfigLayer { kind, path: None, values: toml::from_str(content).unwrap() }
+    }
+
+    fn layered() -> LayeredConfig {
+        LayeredConfig {
+            layers: vec![
+                layer(LayerKind::Default, "[teleport]\nproxy = \"default:443\"\ntimeout_seconds = 15\nauth_type = \"ad\"\n[aws]\ndev = \"yl-development\"\n"),
+                layer(LayerKind::System, "[teleport]\nproxy = \"system:443\"\ntimeout_seconds = 30\n"),
+                layer(LayerKind::User, "[teleport]\nproxy = \"user:443\"\n[aws]\nqa = \"yl-qa\"\n"),
+                layer(LayerKind::Project, "[teleport]\nproxy = \"project:443\"\n"),
+                layer(LayerKind::Profile, "[teleport]\nproxy = \"profile:443\"\n"),
+                layer(LayerKind::Env, "[teleport]\nproxy = \"env:443\"\n"),
+            ],
+        }
+    }
+
+    #[test]
+    fn merged_lets_later_layers_win_key_by_key() {
+        let merged = layered().merged();
+        assert_eq!(lookup(&merged, "teleport.proxy").unwrap().as_str(), Some("env:443"));
+        assert_eq!(lookup(&merged, "teleport.timeout_seconds").unwrap().as_integer(), Some(30));
+        assert_eq!(lookup(&merged, "teleport.auth_type").unwrap().as_str(), Some("ad"));
+        // Tables merge rather than replace each other
+        assert_eq!(lookup(&merged, "aws.dev").unwrap().as_str(), Some("yl-development"));
+        assert_eq!(lookup(&merged, "aws.qa").unwrap().as_str(), Some("yl-qa"));
+    }
+
+    #[test]
+    fn merged_follows_layer_precedence() {
+        let mut config = layered();
+        for expected in ["env", "profile", "project", "user", "system", "default"] {
+            let merged = config.merged();
+            assert_eq!(lookup(&merged, "teleport.proxy").unwrap().as_str(), Some(format!("{}:443", expected).as_str()));
+            config.layers.pop();
+        }
+    }
+
+    #[test]
+    fn origin_names_the_winning_layer() {
+        let config = layered();
+        assert_eq!(config.origin("teleport.proxy").unwrap().kind, LayerKind::Env);
+        assert_eq!(config.origin("teleport.timeout_seconds").unwrap().kind, LayerKind::System);
+        assert_eq!(config.origin("aws.qa").unwrap().kind, LayerKind::User);
+        assert_eq!(config.origin("teleport.auth_type").unwrap().kind, LayerKind::Default);
+        assert!(config.origin("teleport.missing").is_none());
+    }
+
+    fn vars(pairs: &[(&str, &str)]) -> Table {
+        env_table(pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())))
+    }
+
+    #[test]
+    fn env_table_parses_values_as_toml() {
+        let table = vars(&[
+            ("TH_TELEPORT__TIMEOUT_SECONDS", "30"),
+            ("TH_PROXY__WATCH", "true"),
+            ("TH_TELEPORT__PROXY", "staging.teleport.sh:443"),
+            ("TH_PROMPT__FORMAT", "{kube} {aws}"),
+        ]);
+        assert_eq!(lookup(&table, "teleport.timeout_seconds"), Some(&Value::Integer(30)));
+        assert_eq!(lookup(&table, "proxy.watch"), Some(&Value::Boolean(true)));
+        assert_eq!(lookup(&table, "teleport.proxy").unwrap().as_str(), Some("staging.teleport.sh:443"));
+        assert_eq!(lookup(&table, "prompt.format").unwrap().as_str(), Some("{kube} {aws}"));
+    }
+
+    #[test]
+    fn env_table_nests_on_double_underscores() {
+        let table = vars(&[("TH_ROLES__AWS__prod__SUDO", "sudo_prod"), ("TH_DISCOVERY__ENV_LABEL", "stage")]);
+        assert_eq!(lookup(&table, "roles.aws.prod.sudo").unwrap().as_str(), Some("sudo_prod"));
+        assert_eq!(lookup(&table, "discovery.env_label").unwrap().as_str(), Some("stage"));
+    }
+
+    #[test]
+    fn env_table_skips_empty_segments_and_plain_th_vars() {
+        let table = vars(&[
+            ("TH_PROFILE", "staging"),
+            ("TH_ENV_FILE", "/tmp/x"),
+            ("TH_TELEPORT____PROXY", "x"),
+            ("TH___PROXY", "x"),
+            ("TH_TELEPORT__", "x"),
+            ("OTHER__KEY", "x"),
+        ]);
+        assert!(table.is_empty(), "{:?}", table);
+    }
+
+    #[test]
+    fn env_table_keeps_map_keys_as_written() {
+        let table = vars(&[
+            ("TH_PROXY__PORTS__MyApp", "45001"),
+            ("TH_AWS__UsStaging", "yl-usstaging"),
+            ("TH_ROLES__KUBE__Prod__ELEVATION", "sudo_prod_eks"),
+            ("TH_ROLES__DB__RDS__USERS__MyDb", "reader"),
+            ("TH_PROFILES__Staging__TELEPORT__PROXY", "staging:443"),
+            ("TH_PROFILES__Staging__KUBE__Dev", "dev-eks"),
+        ]);
+        assert_eq!(lookup(&table, "proxy.ports.MyApp"), Some(&Value::Integer(45001)));
+        assert_eq!(lookup(&table, "aws.UsStaging").unwrap().as_str(), Some("yl-usstaging"));
+        assert_eq!(lookup(&table, "roles.kube.Prod.elevation").unwrap().as_str(), Some("sudo_prod_eks"));
+        assert_eq!(lookup(&table, "roles.db.RDS.users.MyDb").unwrap().as_str(), Some("reader"));
+        assert_eq!(lookup(&table, "profiles.Staging.teleport.proxy").unwrap().as_str(), Some("staging:443"));
+        assert_eq!(lookup(&table, "profiles.Staging.kube.Dev").unwrap().as_str(), Some("dev-eks"));
+    }
+}
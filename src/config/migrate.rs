@@ -0,0 +1,310 @@
+use super::edit::{backup, remove_path, write_atomic};
+use super::layers::{flatten, insert, LayerKind};
+use super::Config;
+use crate::error::ThError;
+use anyhow::Result;
+use colored::*;
+use std::path::{Path, PathBuf};
+use toml::{Table, Value};
+use toml_edit::{DocumentMut, Item, TableLike};
+
+/// Schema version written by this build of th
+pub const CURRENT_VERSION: u32 = 1;
+
+/// One step in the migration chain, upgrading a file from `from` to `from + 1`
+struct Migration {
+    from: u32,
+    apply: fn(&mut DocumentMut) -> Vec<String>,
+}
+
+/// Every migration, oldest first - add new ones to the end and bump `CURRENT_VERSION`
+const MIGRATIONS: &[Migration] = &[
+    Migration { from: 0, apply: drop_v0_defaults },
+];
+
+/// What `migrate` did to a file
+#[derive(Debug, Clone)]
+pub struct MigrationReport {
+    pub from: u32,
+    pub to: u32,
+    pub changes: Vec<String>,
+}
+
+/// Schema version of a file. Only the user file predates versioning -
+/// system and project files without one are treated as version 1.
+pub fn file_version(doc: &DocumentMut, kind: LayerKind) -> u32 {
+    match doc.get("version").and_then(|v| v.as_integer()) {
+        Some(version) => version.max(0) as u32,
+        None if kind == LayerKind::User => 0,
+        None => 1,
+    }
+}
+
+/// Run every migration newer than the file's version; `None` when it is already current
+pub fn migrate(doc: &mut DocumentMut, kind: LayerKind) -> Option<MigrationReport> {
+    let from = file_version(doc, kind);
+    if from >= CURRENT_VERSION {
+        return None;
+    }
+
+    let mut changes = Vec::new();
+    for migration in MIGRATIONS.iter().filter(|m| m.from >= from) {
+        changes.extend((migration.apply)(doc));
+    }
+    stamp_version(doc);
+
+    Some(MigrationReport { from, to: CURRENT_VERSION, changes })
+}
+
+/// Set `version = CURRENT_VERSION`; top-level values are written before any table
+pub fn stamp_version(doc: &mut DocumentMut) {
+    doc.insert("version", toml_edit::value(CURRENT_VERSION as i64));
+}
+
+/// What `upgrade_user_config` did to the user file
+#[derive(Debug)]
+pub enum Upgrade {
+    /// Missing, unparseable or already current
+    Unchanged,
+    /// Migrated, with the old file kept at the path
+    Upgraded(MigrationReport, PathBuf),
+    /// Written by a newer th, whose schema this one can't know - left as it is
+    TooNew(u32),
+}
+
+/// Upgrade the user config in place, keeping a `.bak` of the old file
+pub fn upgrade_user_config() -> Result<Upgrade> {
+    upgrade_file(&Config::get_config_path()?)
+}
+
+fn upgrade_file(path: &Path) -> Result<Upgrade> {
+    if !path.is_file() {
+        return Ok(Upgrade::Unchanged);
+    }
+
+    let content = std::fs::read_to_string(path)?;
+    // A file that doesn't parse is left for `th config validate` to report
+    let Ok(mut doc) = content.parse::<DocumentMut>() else {
+        return Ok(Upgrade::Unchanged);
+    };
+
+    let version = file_version(&doc, LayerKind::User);
+    if version > CURRENT_VERSION {
+        return Ok(Upgrade::TooNew(version));
+    }
+    let Some(report) = migrate(&mut doc, LayerKind::User) else {
+        return Ok(Upgrade::Unchanged);
+    };
+
+    let backup = backup(path)
+        .map_err(|e| ThError::Config(format!("Could not back up {} before migrating: {}", path.display(), e)))?;
+    write_atomic(path, &doc.to_string())?;
+    Ok(Upgrade::Upgraded(report, backup))
+}
+
+/// Tell the user what changed; goes to stderr so `$(th config get ...)` stays clean
+pub fn print_report(report: &MigrationReport, backup: &Path) {
+    eprintln!(
+        "{} Upgraded {} from schema v{} to v{} (backup at {})",
+        "ℹ️ ".blue(),
+        Config::get_config_path().map(|p| p.display().to_string()).unwrap_or_default(),
+        report.from,
+        report.to,
+        backup.display()
+    );
+    for change in &report.changes {
+        eprintln!("   • {}", change);
+    }
+}
+
+/// Warn that the user file is from a newer th; stderr, like `print_report`
+pub fn print_too_new(version: u32) {
+    eprintln!(
+        "{} {} was written by a newer th (schema v{}, this th understands v{}) - run `th update`",
+        "⚠️ ".yellow(),
+        Config::get_config_path().map(|p| p.display().to_string()).unwrap_or_default(),
+        version,
+        CURRENT_VERSION
+    );
+}
+
+/// v0 -> v1: before config layering, the first run of th wrote a full copy of the
+/// built-in defaults to the user file. Those copies would now shadow newer defaults
+/// and the system file, so drop every value still identical to what was written - unless
+/// the user commented on it, which makes it theirs.
+fn drop_v0_defaults(doc: &mut DocumentMut) -> Vec<String> {
+    let mut v0_defaults: Table = toml::from_str(V0_DEFAULTS).unwrap_or_default();
+    insert(&mut v0_defaults, &["paths", "temp_dir"], Value::String(std::env::temp_dir().to_string_lossy().to_string()));
+
+    let mut removed = Vec::new();
+    for (key, default) in flatten(&v0_defaults) {
+        let segments: Vec<&str> = key.split('.').collect();
+        let unchanged = match (value_at(doc.as_table(), &segments), &default) {
+            (Some(item), Value::String(s)) => item.as_str() == Some(s.as_str()),
+            (Some(item), Value::Integer(i)) => item.as_integer() == Some(*i),
+            _ => false,
+        };
+        if unchanged && !commented(doc.as_table(), &segments) && remove_path(doc.as_table_mut(), &segments) {
+            removed.push(key);
+        }
+    }
+
+    if removed.is_empty() {
+        return Vec::new();
+    }
+    vec![format!(
+        "Removed {} value(s) copied from the old built-in defaults: {}",
+        removed.len(),
+        removed.join(", ")
+    )]
+}
+
+/// Whether a comment sits on the line above a value or after it
+fn commented(table: &dyn TableLike, path: &[&str]) -> bool {
+    let Some((last, parents)) = path.split_last() else {
+        return false;
+    };
+    let parent = if parents.is_empty() { Some(table) } else { value_at(table, parents).and_then(|item| item.as_table_like()) };
+    let Some(parent) = parent else {
+        return false;
+    };
+    let has_comment = |raw: Option<&toml_edit::RawString>| raw.and_then(|raw| raw.as_str()).is_some_and(|s| s.contains('#'));
+    let key_comment = parent.key(last).is_some_and(|key| has_comment(key.leaf_decor().prefix()));
+    let value_comment = parent.get(last).and_then(|item| item.as_value()).is_some_and(|value| has_comment(value.decor().suffix()));
+    key_comment || value_comment
+}
+
+fn value_at<'a>(table: &'a dyn TableLike, path: &[&str]) -> Option<&'a Item> {
+    let (first, rest) = path.split_first()?;
+    let item = table.get(first)?;
+    if rest.is_empty() {
+        return Some(item);
+    }
+    value_at(item.as_table_like()?, rest)
+}
+
+/// What `Config::default` wrote to the user file before version 1 (plus the machine's temp dir)
+const V0_DEFAULTS: &str = r#"
+[kube]
+dev = "aslive-dev-eks-blue"
+sandbox = "aslive-sandbox-eks-blue"
+staging = "aslive-staging-eks-blue"
+usstaging = "aslive-usstaging-eks-blue"
+admin = "headquarter-admin-eks-green"
+prod = "live-prod-eks-blue"
+usprod = "live-usprod-eks-blue"
+corepgblue = "platform-corepgblue-eks-blue"
+corepggreen = "platform-corepggreen-eks-green"
+
+[aws]
+dev = "yl-development"
+sandbox = "yl-sandbox"
+staging = "yl-staging"
+usstaging = "yl-usstaging"
+admin = "yl-admin"
+prod = "yl-production"
+usprod = "yl-usproduction"
+corepgblue = "yl-corepgblue"
+corepggreen = "yl-corepggreen"
+corepg = "yl-coreplayground"
+
+[teleport]
+proxy = "youlend.teleport.sh:443"
+auth_type = "ad"
+timeout_seconds = 15
+
+[paths]
+tsh = "tsh"
+kubectl = "kubectl"
+aws_cli = "aws"
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A v0 file: the old default dump, with comments and two values the user changed
+    fn v0_file() -> String {
+        V0_DEFAULTS
+            .replace("[aws]\n", "# my accounts\n[aws]\n")
+            .replace(r#"sandbox = "yl-sandbox""#, "# still used by CI\nsandbox = \"yl-sandbox\"")
+            .replace(r#"staging = "yl-staging""#, r#"staging = "yl-staging-2"  # moved"#)
+            .replace(r#"auth_type = "ad""#, r#"auth_type = "github""#)
+    }
+
+    fn write_config(content: &str) -> (tempfile::TempDir, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(&path, content).unwrap();
+        (dir, path)
+    }
+
+    #[test]
+    fn migrate_upgrades_v0_keeping_edits_and_comments() {
+        let mut doc = v0_file().parse::<DocumentMut>().unwrap();
+        let report = migrate(&mut doc, LayerKind::User).unwrap();
+        assert_eq!((report.from, report.to), (0, CURRENT_VERSION));
+
+        let upgraded = doc.to_string();
+        assert!(upgraded.starts_with(&format!("version = {}", CURRENT_VERSION)), "{}", upgraded);
+        assert!(upgraded.contains("# my accounts"), "{}", upgraded);
+        assert!(upgraded.contains(r#"staging = "yl-staging-2"  # moved"#), "{}", upgraded);
+        assert!(upgraded.contains(r#"auth_type = "github""#), "{}", upgraded);
+        // A default the user commented on is theirs now
+        assert!(upgraded.contains("# still used by CI\nsandbox = \"yl-sandbox\""), "{}", upgraded);
+        // Untouched defaults are gone, and so are the tables they emptied
+        assert!(!upgraded.contains("yl-development"), "{}", upgraded);
+        assert!(!upgraded.contains("[kube]"), "{}", upgraded);
+        assert!(!upgraded.contains("[paths]"), "{}", upgraded);
+        assert!(report.changes[0].contains("aws.dev"));
+    }
+
+    #[test]
+    fn migrate_leaves_a_current_file_alone() {
+        let content = format!("version = {}\n\n[aws]\ndev = \"yl-development\" # kept\n", CURRENT_VERSION);
+        let mut doc = content.parse::<DocumentMut>().unwrap();
+        assert!(migrate(&mut doc, LayerKind::User).is_none());
+        assert_eq!(doc.to_string(), content);
+
+        let (_dir, path) = write_config(&content);
+        assert!(matches!(upgrade_file(&path).unwrap(), Upgrade::Unchanged));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), content);
+    }
+
+    #[test]
+    fn unversioned_system_and_project_files_count_as_v1() {
+        let doc = "[aws]\ndev = \"yl-development\"\n".parse::<DocumentMut>().unwrap();
+        assert_eq!(file_version(&doc, LayerKind::User), 0);
+        assert_eq!(file_version(&doc, LayerKind::System), 1);
+        assert_eq!(file_version(&doc, LayerKind::Project), 1);
+    }
+
+    #[test]
+    fn upgrade_reports_a_newer_file_without_changing_it() {
+        let content = format!("version = {}\n\n[aws]\ndev = \"yl-development\"\n", CURRENT_VERSION + 1);
+        let (_dir, path) = write_config(&content);
+        assert!(matches!(upgrade_file(&path).unwrap(), Upgrade::TooNew(version) if version == CURRENT_VERSION + 1));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), content);
+        assert!(!path.with_file_name("config.toml.bak").exists());
+    }
+
+    #[test]
+    fn upgrade_backs_up_the_old_file() {
+        let (_dir, path) = write_config(&v0_file());
+        let Upgrade::Upgraded(_, backup) = upgrade_file(&path).unwrap() else {
+            panic!("v0 file was not upgraded");
+        };
+        assert_eq!(backup, path.with_file_name("config.toml.bak"));
+        assert_eq!(std::fs::read_to_string(&backup).unwrap(), v0_file());
+        assert_ne!(std::fs::read_to_string(&path).unwrap(), v0_file());
+    }
+
+    #[test]
+    fn upgrade_leaves_the_file_alone_when_the_backup_fails() {
+        let (_dir, path) = write_config(&v0_file());
+        // A directory in the backup's place makes the copy fail
+        std::fs::create_dir(path.with_file_name("config.toml.bak")).unwrap();
+        assert!(upgrade_file(&path).is_err());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), v0_file());
+    }
+}
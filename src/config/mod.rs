@@ -0,0 +1,427 @@
+pub mod discovery;
+pub mod edit;
+pub mod layers;
+pub mod migrate;
+pub mod validate;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct Config {
+    /// Schema version - see `migrate::CURRENT_VERSION`
+    pub version: u32,
+    /// Active `[profiles.<name>]`, if any - see `layers::LayeredConfig::apply_profile`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    pub kube: HashMap<String, String>,
+    pub aws: HashMap<String, String>,
+    /// Environment → database name, for `th d <env>`
+    pub databases: HashMap<String, String>,
+    pub teleport: TeleportConfig,
+    pub paths: PathsConfig,
+    /// Teleport roles per AWS env, kube env and database class
+    pub roles: RolesConfig,
+    /// How `th config sync` maps resource labels to environments
+    pub discovery: DiscoveryConfig,
+    /// Background `tsh proxy` supervision
+    pub proxy: ProxyConfig,
+    /// What th may change in your shell setup
+    pub shell: ShellConfig,
+    /// `th prompt` segment
+    pub prompt: PromptConfig,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct TeleportConfig {
+    pub proxy: String,
+    pub auth_type: String,
+    pub timeout_seconds: u64,
+    /// Warn and offer to log in again when the certificate has less than this left
+    pub renew_before_minutes: u64,
+    /// Log in again without asking when the certificate is about to expire
+    pub auto_renew: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct PathsConfig {
+    pub tsh: String,
+    pub kubectl: String,
+    pub aws_cli: String,
+    pub temp_dir: PathBuf,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct DiscoveryConfig {
+    /// Label holding the environment, e.g. `env=staging`
+    pub env_label: String,
+    /// Label holding the region; other than `home_region` it prefixes the env (`usstaging`)
+    pub region_label: String,
+    pub home_region: String,
+    /// Look up environments missing from the config in Teleport at login time
+    pub auto: bool,
+    /// Set by `th config sync`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub synced_at: Option<String>,
+    /// Env maps `th config sync` has written - their built-in defaults are not used
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub synced_sections: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct ProxyConfig {
+    /// Start the watchdog (`th proxy watch`) whenever th starts a proxy
+    pub watch: bool,
+    /// How often the watchdog checks proxies and the Teleport session
+    pub watch_interval_seconds: u64,
+    /// How long a new proxy gets to open its port or print its credentials
+    pub ready_timeout_seconds: u64,
+    /// Local port per app or database name; others get a stable port derived from the name
+    pub ports: HashMap<String, u16>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct ShellConfig {
+    /// Keep a `# >>> th >>>` block in your rc file that loads the default AWS session in
+    /// new shells; off, th never touches rc files and `th shell-init` is all there is
+    pub manage_rc: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct PromptConfig {
+    /// Template with `{kube}`, `{aws}`, `{expiry}`, `{env}`, `{account}`, `{role}` and `{context}`
+    pub format: String,
+    /// Segments whose env, account or cluster contains this are shown in red
+    pub prod_match: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct RolesConfig {
+    /// Keyed by the same environment names as `[aws]`
+    pub aws: HashMap<String, RoleMapping>,
+    /// Keyed by the same environment names as `[kube]`
+    pub kube: HashMap<String, RoleMapping>,
+    /// Keyed by database class (`rds`, `mongodb`)
+    pub db: HashMap<String, DbRoles>,
+    pub terraform: TerraformRoles,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+#[serde(default)]
+pub struct RoleMapping {
+    /// Role used for a normal login
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default: Option<String>,
+    /// Role used when the sudo flag is given
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sudo: Option<String>,
+    /// Role to raise an access request for when access is missing
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub elevation: Option<String>,
+    /// `--max-duration` for that access request
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_duration: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+#[serde(default)]
+pub struct DbRoles {
+    /// Holding any of these roles grants access to the class
+    pub access_roles: Vec<String>,
+    /// Holding this role allows connecting as `admin_user`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub admin_role: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub elevation: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_duration: Option<String>,
+    /// Database user for a normal connection
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_user: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub admin_user: Option<String>,
+    /// Per-database user overrides
+    pub users: HashMap<String, String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct TerraformRoles {
+    /// AWS app `th terra` logs into
+    pub account: String,
+    pub role: String,
+}
+
+impl Default for RolesConfig {
+    fn default() -> Self {
+        let mut aws = HashMap::new();
+        aws.insert("dev".to_string(), RoleMapping {
+            default: Some("dev".to_string()),
+            sudo: Some("sudo_dev".to_string()),
+            ..RoleMapping::default()
+        });
+        aws.insert("corepg".to_string(), RoleMapping {
+            default: Some("coreplayground".to_string()),
+            sudo: Some("sudo_coreplayground".to_string()),
+            ..RoleMapping::default()
+        });
+        aws.insert("prod".to_string(), RoleMapping {
+            elevation: Some("sudo_prod_role".to_string()),
+            ..RoleMapping::default()
+        });
+        aws.insert("usprod".to_string(), RoleMapping {
+            elevation: Some("sudo_usprod_role".to_string()),
+            ..RoleMapping::default()
+        });
+
+        let mut kube = HashMap::new();
+        kube.insert("prod".to_string(), RoleMapping {
+            elevation: Some("sudo_prod_eks_cluster".to_string()),
+            ..RoleMapping::default()
+        });
+        kube.insert("usprod".to_string(), RoleMapping {
+            elevation: Some("sudo_usprod_eks_cluster".to_string()),
+            ..RoleMapping::default()
+        });
+
+        let mut db = HashMap::new();
+        db.insert("rds".to_string(), DbRoles {
+            admin_role: Some("sudo_teleport_rds_write_role".to_string()),
+            elevation: Some("sudo_teleport_rds_read_role".to_string()),
+            max_duration: Some("6h".to_string()),
+            default_user: Some("tf_teleport_rds_read_user".to_string()),
+            admin_user: Some("tf_sudo_teleport_rds_user".to_string()),
+            ..DbRoles::default()
+        });
+        let mut mongo_users = HashMap::new();
+        mongo_users.insert("mongodb-YLUSProd-Cluster-1".to_string(), "teleport-usprod".to_string());
+        mongo_users.insert("mongodb-YLProd-Cluster-1".to_string(), "teleport-prod".to_string());
+        mongo_users.insert("mongodb-YLSandbox-Cluster-1".to_string(), "teleport-sandbox".to_string());
+        db.insert("mongodb".to_string(), DbRoles {
+            access_roles: vec!["atlas-can-read".to_string(), "atlas-read-only".to_string()],
+            elevation: Some("atlas-read-only".to_string()),
+            max_duration: Some("6h".to_string()),
+            default_user: Some("teleport-default".to_string()),
+            users: mongo_users,
+            ..DbRoles::default()
+        });
+
+        Self {
+            aws,
+            kube,
+            db,
+            terraform: TerraformRoles::default(),
+        }
+    }
+}
+
+impl Default for DiscoveryConfig {
+    fn default() -> Self {
+        Self {
+            env_label: "env".to_string(),
+            region_label: "region".to_string(),
+            home_region: "eu".to_string(),
+            auto: false,
+            synced_at: None,
+            synced_sections: Vec::new(),
+        }
+    }
+}
+
+impl Default for TerraformRoles {
+    fn default() -> Self {
+        Self {
+            account: "yl-admin".to_string(),
+            role: "sudo_admin".to_string(),
+        }
+    }
+}
+
+impl RolesConfig {
+    /// Role for `th a <env>`, or `th a <env> s` - unmapped envs use the `<env>`/`sudo_<env>` convention
+    pub fn aws_role(&self, env: &str, sudo: bool) -> String {
+        let mapping = self.aws.get(env);
+        let role = if sudo {
+            mapping.and_then(|m| m.sudo.clone())
+        } else {
+            mapping.and_then(|m| m.default.clone())
+        };
+
+        role.unwrap_or_else(|| if sudo { format!("sudo_{}", env) } else { env.to_string() })
+    }
+
+    pub fn aws(&self, env: &str) -> RoleMapping {
+        self.aws.get(env).cloned().unwrap_or_default()
+    }
+
+    pub fn kube(&self, env: &str) -> RoleMapping {
+        self.kube.get(env).cloned().unwrap_or_default()
+    }
+
+    pub fn db(&self, class: &str) -> DbRoles {
+        self.db.get(class).cloned().unwrap_or_default()
+    }
+}
+
+impl DbRoles {
+    /// User to connect to `database` as
+    pub fn user_for(&self, database: &str, admin: bool) -> Option<&str> {
+        if admin {
+            if let Some(user) = &self.admin_user {
+                return Some(user);
+            }
+        }
+        // A leaf cluster database (`leaf/name`) can be mapped either way
+        let (_, name) = crate::models::resources::split_target(database);
+        self.users.get(database)
+            .or_else(|| self.users.get(name))
+            .or(self.default_user.as_ref())
+            .map(|u| u.as_str())
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        let mut kube = HashMap::new();
+        kube.insert("dev".to_string(), "aslive-dev-eks-blue".to_string());
+        kube.insert("sandbox".to_string(), "aslive-sandbox-eks-blue".to_string());
+        kube.insert("staging".to_string(), "aslive-staging-eks-blue".to_string());
+        kube.insert("usstaging".to_string(), "aslive-usstaging-eks-blue".to_string());
+        kube.insert("admin".to_string(), "headquarter-admin-eks-green".to_string());
+        kube.insert("prod".to_string(), "live-prod-eks-blue".to_string());
+        kube.insert("usprod".to_string(), "live-usprod-eks-blue".to_string());
+        kube.insert("corepgblue".to_string(), "platform-corepgblue-eks-blue".to_string());
+        kube.insert("corepggreen".to_string(), "platform-corepggreen-eks-green".to_string());
+
+        let mut aws = HashMap::new();
+        aws.insert("dev".to_string(), "yl-development".to_string());
+        aws.insert("sandbox".to_string(), "yl-sandbox".to_string());
+        aws.insert("staging".to_string(), "yl-staging".to_string());
+        aws.insert("usstaging".to_string(), "yl-usstaging".to_string());
+        aws.insert("admin".to_string(), "yl-admin".to_string());
+        aws.insert("prod".to_string(), "yl-production".to_string());
+        aws.insert("usprod".to_string(), "yl-usproduction".to_string());
+        aws.insert("corepgblue".to_string(), "yl-corepgblue".to_string());
+        aws.insert("corepggreen".to_string(), "yl-corepggreen".to_string());
+        aws.insert("corepg".to_string(), "yl-coreplayground".to_string());
+
+        Self {
+            version: migrate::CURRENT_VERSION,
+            profile: None,
+            kube,
+            aws,
+            databases: HashMap::new(),
+            teleport: TeleportConfig::default(),
+            paths: PathsConfig::default(),
+            roles: RolesConfig::default(),
+            discovery: DiscoveryConfig::default(),
+            proxy: ProxyConfig::default(),
+            shell: ShellConfig::default(),
+            prompt: PromptConfig::default(),
+        }
+    }
+}
+
+impl Default for TeleportConfig {
+    fn default() -> Self {
+        Self {
+            proxy: "youlend.teleport.sh:443".to_string(),
+            auth_type: "ad".to_string(),
+            timeout_seconds: 15,
+            renew_before_minutes: 15,
+            auto_renew: false,
+        }
+    }
+}
+
+impl Default for ProxyConfig {
+    fn default() -> Self {
+        Self {
+            watch: false,
+            watch_interval_seconds: 15,
+            ready_timeout_seconds: 15,
+            ports: HashMap::new(),
+        }
+    }
+}
+
+impl Default for ShellConfig {
+    fn default() -> Self {
+        Self { manage_rc: true }
+    }
+}
+
+impl Default for PromptConfig {
+    fn default() -> Self {
+        Self {
+            format: "{kube} {aws} {expiry}".to_string(),
+            prod_match: "prod".to_string(),
+        }
+    }
+}
+
+impl Default for PathsConfig {
+    fn default() -> Self {
+        Self {
+            tsh: "tsh".to_string(),
+            kubectl: "kubectl".to_string(),
+            aws_cli: "aws".to_string(),
+            temp_dir: std::env::temp_dir(),
+        }
+    }
+}
+
+impl Config {
+    /// Load configuration from every layer - see `layers::LayeredConfig::discover`
+    pub async fn load() -> Result<Self> {
+        layers::LayeredConfig::discover()?.resolve()
+    }
+
+    /// Get the path to the configuration file
+    pub fn get_config_path() -> Result<PathBuf> {
+        let home = dirs::home_dir()
+            .ok_or_else(|| anyhow::anyhow!("Could not determine home directory"))?;
+        
+        Ok(home.join(".config").join("th").join("config.toml"))
+    }
+
+    /// Get Kubernetes cluster name for environment
+    pub fn get_kube_cluster(&self, env: &str) -> Option<&String> {
+        self.kube.get(env)
+    }
+
+    /// Get AWS account name for environment
+    pub fn get_aws_account(&self, env: &str) -> Option<&String> {
+        self.aws.get(env)
+    }
+
+    /// Environment whose `[aws]` entry is `account`
+    pub fn aws_env_for_account(&self, account: &str) -> Option<&String> {
+        self.aws.iter().find(|(_, name)| *name == account).map(|(env, _)| env)
+    }
+
+    /// Environment whose `[kube]` entry is `cluster`
+    pub fn kube_env_for_cluster(&self, cluster: &str) -> Option<&String> {
+        self.kube.iter().find(|(_, name)| *name == cluster).map(|(env, _)| env)
+    }
+
+    /// List all available Kubernetes environments
+    pub fn list_kube_envs(&self) -> Vec<&String> {
+        self.kube.keys().collect()
+    }
+
+    /// List all available AWS environments
+    pub fn list_aws_envs(&self) -> Vec<&String> {
+        self.aws.keys().collect()
+    }
+}
\ No newline at end of file
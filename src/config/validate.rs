@@ -0,0 +1,327 @@
+use super::layers::{self, flatten, insert, merge, ConfigLayer, LayerKind, LayeredConfig};
+use super::migrate::{self, CURRENT_VERSION};
+use super::Config;
+use crate::tools::{self, Tool};
+use anyhow::Result;
+use std::fmt;
+use std::path::PathBuf;
+use toml::{Table, Value};
+use toml_edit::DocumentMut;
+
+/// Every key th understands; `*` stands for any map key
+const SCHEMA: &[&str] = &[
+    "version",
+    "profile",
+    "kube.*",
+    "aws.*",
+    "databases.*",
+    "teleport.proxy",
+    "teleport.auth_type",
+    "teleport.timeout_seconds",
+    "teleport.renew_before_minutes",
+    "teleport.auto_renew",
+    "paths.tsh",
+    "paths.kubectl",
+    "paths.aws_cli",
+    "paths.temp_dir",
+    "roles.aws.*.default",
+    "roles.aws.*.sudo",
+    "roles.aws.*.elevation",
+    "roles.aws.*.max_duration",
+    "roles.kube.*.default",
+    "roles.kube.*.sudo",
+    "roles.kube.*.elevation",
+    "roles.kube.*.max_duration",
+    "roles.db.*.access_roles",
+    "roles.db.*.admin_role",
+    "roles.db.*.elevation",
+    "roles.db.*.max_duration",
+    "roles.db.*.default_user",
+    "roles.db.*.admin_user",
+    "roles.db.*.users.*",
+    "roles.terraform.account",
+    "roles.terraform.role",
+    "discovery.env_label",
+    "discovery.region_label",
+    "discovery.home_region",
+    "discovery.auto",
+    "discovery.synced_at",
+    "discovery.synced_sections",
+    "proxy.watch",
+    "proxy.watch_interval_seconds",
+    "proxy.ready_timeout_seconds",
+    "proxy.ports.*",
+    "shell.manage_rc",
+    "prompt.format",
+    "prompt.prod_match",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// `file:line:column`, or the env var a value came from
+#[derive(Debug, Clone)]
+pub enum Location {
+    File { path: PathBuf, line: usize, column: usize },
+    Env(String),
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Location::File { path, line, column } => write!(f, "{}:{}:{}", path.display(), line, column),
+            Location::Env(var) => write!(f, "${}", var),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Issue {
+    pub severity: Severity,
+    pub location: Option<Location>,
+    pub message: String,
+}
+
+impl Issue {
+    fn error(location: Option<Location>, message: String) -> Self {
+        Self { severity: Severity::Error, location, message }
+    }
+
+    fn warning(location: Option<Location>, message: String) -> Self {
+        Self { severity: Severity::Warning, location, message }
+    }
+}
+
+/// A config file's text, kept so issues can point at a line
+struct SourceFile {
+    path: PathBuf,
+    text: String,
+}
+
+impl SourceFile {
+    /// Location of `key` in this file, falling back to the top of the file
+    fn locate(&self, key: &str) -> Location {
+        let offset = span_of(&self.text, key).unwrap_or(0);
+        let (line, column) = line_column(&self.text, offset);
+        Location::File { path: self.path.clone(), line, column }
+    }
+}
+
+/// Check every layer: syntax, unknown keys, value types, then the merged result
+pub fn validate() -> Result<Vec<Issue>> {
+    let mut issues = Vec::new();
+    let mut sources = Vec::new();
+    let mut layered = LayeredConfig { layers: vec![layers::default_layer()?] };
+
+    for (kind, path) in layers::layer_files()? {
+        if !path.is_file() {
+            continue;
+        }
+        let text = std::fs::read_to_string(&path)?;
+        let source = SourceFile { path, text };
+
+        match check_source(&source, kind, &mut issues) {
+            Some(values) => {
+                layered.layers.push(ConfigLayer { kind, path: Some(source.path.clone()), values });
+                sources.push(source);
+            }
+            // Nothing more can be said about a file that doesn't parse
+            None => continue,
+        }
+    }
+
+    if let Some(env) = layers::env_layer() {
+        check_layer(&env.values, &mut issues, |key| Some(Location::Env(env_var_name(key))));
+        layered.layers.push(env);
+    }
+
+    if issues.iter().any(|issue| issue.severity == Severity::Error) {
+        return Ok(issues);
+    }
+    layered.drop_synced_defaults();
+    if let Err(e) = layered.apply_profile() {
+        let location = match std::env::var(layers::PROFILE_ENV) {
+            Ok(_) => Some(Location::Env(layers::PROFILE_ENV.to_string())),
+            Err(_) => locate_in_files(&layered, &sources, "profile"),
+        };
+        issues.push(Issue::error(location, e.to_string()));
+        return Ok(issues);
+    }
+
+    let profile = layered.selected_profile();
+    let locate = |key: &str| match layered.origin(key)?.kind {
+        LayerKind::Env => Some(Location::Env(env_var_name(key))),
+        LayerKind::Profile => locate_in_files(&layered, &sources, &format!("profiles.{}.{}", profile.as_deref()?, key)),
+        _ => locate_in_files(&layered, &sources, key),
+    };
+
+    match layered.resolve() {
+        Ok(config) => check_config(&config, &mut issues, locate),
+        Err(e) => issues.push(Issue::error(None, e.to_string())),
+    }
+
+    Ok(issues)
+}
+
+/// Where in the config files `key` is set
+fn locate_in_files(layered: &LayeredConfig, sources: &[SourceFile], key: &str) -> Option<Location> {
+    let origin = layered.origin(key)?;
+    sources.iter()
+        .find(|source| Some(&source.path) == origin.path.as_ref())
+        .map(|source| source.locate(key))
+}
+
+/// Syntax-check one file and check its keys; returns its values if it parsed
+fn check_source(source: &SourceFile, kind: LayerKind, issues: &mut Vec<Issue>) -> Option<Table> {
+    let doc = match source.text.parse::<DocumentMut>() {
+        Ok(doc) => doc,
+        Err(e) => {
+            let (line, column) = line_column(&source.text, e.span().map(|s| s.start).unwrap_or(0));
+            let location = Location::File { path: source.path.clone(), line, column };
+            issues.push(Issue::error(Some(location), e.message().trim().replace('\n', ", ")));
+            return None;
+        }
+    };
+
+    let version = migrate::file_version(&doc, kind);
+    if version > CURRENT_VERSION {
+        issues.push(Issue::warning(
+            Some(source.locate("version")),
+            format!("version: written by a newer th (schema {}, this th understands {}) - run `th update`", version, CURRENT_VERSION),
+        ));
+    }
+
+    let values = layers::parse_table(&source.text, kind).ok()?;
+    check_layer(&values, issues, |key| Some(source.locate(key)));
+    Some(values)
+}
+
+/// Unknown keys and values of the wrong type within one layer
+fn check_layer(values: &Table, issues: &mut Vec<Issue>, locate: impl Fn(&str) -> Option<Location>) {
+    for (key, value) in flatten(values) {
+        if !is_known_key(&key) {
+            issues.push(Issue::warning(locate(&key), format!("Unknown key '{}'", key)));
+            continue;
+        }
+        if let Some(message) = type_error(&key, value) {
+            issues.push(Issue::error(locate(&key), format!("{}: {}", key, message)));
+        }
+    }
+}
+
+/// Checks that need the fully merged config: env names, paths and tools
+fn check_config(config: &Config, issues: &mut Vec<Issue>, locate: impl Fn(&str) -> Option<Location>) {
+    for env in config.roles.aws.keys() {
+        if !config.aws.contains_key(env) {
+            let key = format!("roles.aws.{}", env);
+            issues.push(Issue::warning(locate(&key), format!("{}: '{}' is not an environment in [aws]", key, env)));
+        }
+    }
+    for env in config.roles.kube.keys() {
+        if !config.kube.contains_key(env) {
+            let key = format!("roles.kube.{}", env);
+            issues.push(Issue::warning(locate(&key), format!("{}: '{}' is not an environment in [kube]", key, env)));
+        }
+    }
+    let terraform = &config.roles.terraform;
+    if !config.aws.values().any(|account| *account == terraform.account) {
+        issues.push(Issue::warning(
+            locate("roles.terraform.account"),
+            format!("roles.terraform.account: '{}' is not an account in [aws]", terraform.account),
+        ));
+    }
+
+    if config.teleport.proxy.trim().is_empty() {
+        issues.push(Issue::error(locate("teleport.proxy"), "teleport.proxy: must not be empty".to_string()));
+    }
+    if config.teleport.timeout_seconds == 0 {
+        issues.push(Issue::warning(locate("teleport.timeout_seconds"), "teleport.timeout_seconds: 0 disables waiting for login".to_string()));
+    }
+
+    if !config.paths.temp_dir.is_dir() {
+        issues.push(Issue::error(
+            locate("paths.temp_dir"),
+            format!("paths.temp_dir: {} is not a directory", config.paths.temp_dir.display()),
+        ));
+    }
+
+    // tsh is required; kubectl and aws are only needed by some commands
+    let tools = [
+        (Tool::Tsh, Severity::Error),
+        (Tool::Kubectl, Severity::Warning),
+        (Tool::AwsCli, Severity::Warning),
+    ];
+    for (tool, severity) in tools {
+        if let Err(e) = tools::require(config, tool) {
+            let key = tool.config_key().unwrap_or_default();
+            issues.push(Issue { severity, location: locate(key), message: format!("{}: {}", key, e) });
+        }
+    }
+}
+
+/// Whether `key` matches an entry in `SCHEMA`, directly or inside `[profiles.<name>]`
+pub fn is_known_key(key: &str) -> bool {
+    if let Some(rest) = profile_key(key) {
+        return rest != "profile" && !rest.starts_with("profiles.") && is_known_key(rest);
+    }
+    let segments: Vec<&str> = key.split('.').collect();
+    SCHEMA.iter().any(|pattern| {
+        let pattern: Vec<&str> = pattern.split('.').collect();
+        pattern.len() == segments.len()
+            && pattern.iter().zip(&segments).all(|(p, s)| *p == "*" || p == s)
+    })
+}
+
+/// `teleport.proxy` for `profiles.staging.teleport.proxy`
+fn profile_key(key: &str) -> Option<&str> {
+    let rest = key.strip_prefix("profiles.")?;
+    rest.split_once('.').map(|(_, rest)| rest)
+}
+
+/// Why `value` can't be used for `key`, found by overlaying it alone on the defaults
+pub fn type_error(key: &str, value: Value) -> Option<String> {
+    let key = profile_key(key).unwrap_or(key);
+    let mut single = Table::new();
+    let path: Vec<&str> = key.split('.').collect();
+    insert(&mut single, &path, value);
+
+    let mut table = Table::try_from(Config::default()).ok()?;
+    merge(&mut table, &single);
+    Value::Table(table)
+        .try_into::<Config>()
+        .err()
+        .map(|e| e.message().trim().to_string())
+}
+
+fn env_var_name(key: &str) -> String {
+    format!("{}{}", layers::ENV_PREFIX, key.replace('.', layers::ENV_SEPARATOR).to_uppercase())
+}
+
+/// Byte offset of `key` (dotted path) in a TOML document
+fn span_of(text: &str, key: &str) -> Option<usize> {
+    let doc = toml_edit::ImDocument::parse(text).ok()?;
+    let mut table: &dyn toml_edit::TableLike = doc.as_table();
+    let mut offset = None;
+
+    for segment in key.split('.').filter(|s| !s.is_empty()) {
+        let (k, item) = table.get_key_value(segment)?;
+        offset = k.span().or_else(|| item.span()).map(|span| span.start).or(offset);
+        match item.as_table_like() {
+            Some(child) => table = child,
+            None => break,
+        }
+    }
+
+    offset
+}
+
+/// 1-based line and column of a byte offset
+fn line_column(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset.min(text.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.rsplit('\n').next().map(|l| l.chars().count()).unwrap_or(0) + 1;
+    (line, column)
+}
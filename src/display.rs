@@ -6,7 +6,6 @@ use std::io::{self, Write};
 use std::time::Duration;
 use tokio::time::sleep;
 use tokio::task;
-use rand::Rng;
 
 /// Clear the terminal screen
 pub fn clear_screen() -> io::Result<()> {
@@ -161,6 +160,16 @@ pub fn display_status_list(items: &[(String, bool)]) {
     }
 }
 
+/// `42s`, `5m`, `3h12m`, `2d4h`
+pub fn format_duration(seconds: i64) -> String {
+    match seconds.max(0) {
+        s if s < 60 => format!("{}s", s),
+        s if s < 3600 => format!("{}m", s / 60),
+        s if s < 86400 => format!("{}h{}m", s / 3600, s % 3600 / 60),
+        s => format!("{}d{}h", s / 86400, s % 86400 / 3600),
+    }
+}
+
 /// Create a code block display
 pub fn display_code(code: &str) -> String {
     format!("{}", code.on_bright_black().white())
@@ -342,8 +351,15 @@ pub fn print_help(version: &str) {
     println!("{}     ╚═ \x1b[1mth terra          | t\x1b[0m   : Quick log-in to Terragrunt.", center_spaces);
     println!("{}     ╚═ \x1b[1mth logout         | l\x1b[0m   : Clean up Teleport session.", center_spaces);
     println!("{}     ╚═ \x1b[1mth login          | li\x1b[0m  : Simple log in to Teleport\x1b[0m", center_spaces);
+    println!("{}     ╚═ \x1b[1mth config [cmd]       \x1b[0m  : Show and change settings.", center_spaces);
+    println!("{}     ╚═ \x1b[1mth proxy [cmd]        \x1b[0m  : List, stop and restart proxies.", center_spaces);
+    println!("{}     ╚═ \x1b[1mth status         | s\x1b[0m   : Show logins, credentials and proxies.", center_spaces);
+    println!("{}     ╚═ \x1b[1mth exec <env> -- cmd  \x1b[0m  : Run one command with credentials.", center_spaces);
+    println!("{}     ╚═ \x1b[1mth shell-init <shell> \x1b[0m  : Shell function for your rc file.", center_spaces);
+    println!("{}     ╚═ \x1b[1mth prompt [options]   \x1b[0m  : Kube/AWS/expiry segment for PS1.", center_spaces);
     println!("{}     \x1b[0m\x1b[38;5;245m━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━\x1b[0m\x1b[1;34m\x1b[0m", center_spaces);
     println!("{}     For help, and \x1b[1m[options]\x1b[0m info, run \x1b[1mth a/k/d etc.. -h\x1b[0m", center_spaces);
+    println!("{}     Add \x1b[1m--profile <name>\x1b[0m to use another Teleport cluster.", center_spaces);
     println!();
     create_header_full("Docs", &center_spaces, true);
     println!("{}     Run the following commands to access the documentation pages: ", center_spaces);
@@ -364,22 +380,6 @@ pub fn print_help(version: &str) {
 //                                                   Exact Bash Functions 
 // ========================================================================================================================
 
-/// Find available port - exactly like bash version
-pub fn find_available_port() -> u16 {
-    let mut rng = rand::thread_rng();
-    for _ in 0..100 {
-        let port = rng.gen_range(40000..60000);
-        if !is_port_in_use(port) {
-            return port;
-        }
-    }
-    50000
-}
-
-fn is_port_in_use(port: u16) -> bool {
-    std::net::TcpListener::bind(("127.0.0.1", port)).is_err()
-}
-
 /// Create a note - exactly like bash version
 pub fn create_note(note_text: &str) {
     println!("\n\n\x1b[0m\x1b[38;5;245m▄██▀ {}\x1b[0m\x1b[1;34m\x1b[0m\n\n", note_text);
@@ -499,66 +499,55 @@ pub async fn animate_youlend() {
 // ========================================================================================================================
 
 /// Login to Teleport - exactly like bash th_login function
-pub async fn th_login() -> anyhow::Result<()> {
-    use std::process::Command;
-    
+pub async fn th_login(client: &crate::teleport::TeleportClient) -> anyhow::Result<()> {
     clear_screen()?;
     create_header("Login");
     println!("Checking login status...");
     
-    // Check if already logged in - exactly like bash: tsh status 2>/dev/null | grep -q 'Logged in as:'
-    let status_check = Command::new("tsh")
-        .args(["status"])
-        .stdout(std::process::Stdio::piped())
-        .stderr(std::process::Stdio::null())
-        .output();
-        
-    if let Ok(output) = status_check {
-        let output_str = String::from_utf8_lossy(&output.stdout);
-        if output_str.contains("Logged in as:") {
-            cprintf("\n✅ \x1b[1mAlready logged in to Teleport!\x1b[0m\n");
-            std::thread::sleep(std::time::Duration::from_secs(1));
-            return Ok(());
-        }
+    // Check if already logged in, with enough time left on the certificate
+    let status = client.get_status().await.unwrap_or_default();
+    if status.valid_for(client.renew_before()) {
+        cprintf("\n✅ \x1b[1mAlready logged in to Teleport!\x1b[0m\n");
+        std::thread::sleep(std::time::Duration::from_secs(1));
+        return Ok(());
     }
     
-    println!("\nLogging you into Teleport...");
-    
-    // Run tsh login - exactly like bash: tsh login --auth=ad --proxy=youlend.teleport.sh:443 > /dev/null 2>&1
-    let _login_result = Command::new("tsh")
-        .args(["login", "--auth=ad", "--proxy=youlend.teleport.sh:443"])
-        .stdout(std::process::Stdio::null())
-        .stderr(std::process::Stdio::null())
-        .status();
-    
-    // Wait until login completes (max 15 seconds) - exactly like bash: for i in {1..30}
-    for _ in 1..=30 {
-        let status_check = Command::new("tsh")
-            .args(["status"])
-            .stdout(std::process::Stdio::piped())
-            .stderr(std::process::Stdio::null())
-            .output();
-            
-        if let Ok(output) = status_check {
-            let output_str = String::from_utf8_lossy(&output.stdout);
-            if output_str.contains("Logged in as:") {
-                println!("\n\x1b[1;32mLogged in successfully!\x1b[0m");
-                std::thread::sleep(std::time::Duration::from_secs(1));
+    if status.logged_in {
+        // About to expire - a long terraform apply or proxy would die part-way
+        let left = status.remaining().map(|left| format_duration(left.num_seconds())).unwrap_or_default();
+        println!();
+        print_warning(&format!("Your Teleport certificate expires in {}", left));
+        if !client.config().teleport.auto_renew {
+            print!("Log in again now? (y/n): ");
+            io::stdout().flush()?;
+            let mut answer = String::new();
+            io::stdin().read_line(&mut answer)?;
+            if !answer.trim().to_lowercase().starts_with('y') {
                 return Ok(());
             }
         }
-        std::thread::sleep(std::time::Duration::from_millis(500));
+        println!("\nRenewing your Teleport login...");
+    } else {
+        match client.profile() {
+            Some(profile) => println!("\nLogging you into Teleport ({} - {})...", profile, client.config().teleport.proxy),
+            None => println!("\nLogging you into Teleport..."),
+        }
     }
     
-    println!("\n❌ \x1b[1;31mTimed out waiting for Teleport login.\x1b[0m");
-    Err(anyhow::anyhow!("Login timeout"))
+    // Run tsh login against the configured proxy/auth type and wait for the new certificate
+    if let Err(e) = client.renew(&status).await {
+        println!("\n❌ \x1b[1;31mTeleport login failed.\x1b[0m");
+        return Err(e);
+    }
+    println!("\n\x1b[1;32mLogged in successfully!\x1b[0m");
+    std::thread::sleep(std::time::Duration::from_secs(1));
+    Ok(())
 }
 
 /// Kill/cleanup Teleport sessions - exactly like bash th_kill function
-pub async fn th_kill() -> anyhow::Result<()> {
+pub async fn th_kill(client: &crate::teleport::TeleportClient) -> anyhow::Result<()> {
     use std::process::Command;
     use std::fs;
-    use std::path::Path;
     
     clear_screen()?;
     create_header("Cleanup");
@@ -576,37 +565,13 @@ pub async fn th_kill() -> anyhow::Result<()> {
         }
     }
     
-    // Determine which shell profile to clean - exactly like bash
-    let shell = std::env::var("SHELL").unwrap_or_default();
-    let shell_name = Path::new(&shell).file_name()
-        .and_then(|s| s.to_str())
-        .unwrap_or("");
-    
-    let shell_profile = match shell_name {
-        "zsh" => Some(format!("{}/.zshrc", std::env::var("HOME").unwrap_or_default())),
-        "bash" => Some(format!("{}/.bash_profile", std::env::var("HOME").unwrap_or_default())),
-        _ => {
-            println!("Unsupported shell: {}. Skipping profile cleanup.", shell_name);
-            None
-        }
-    };
-    
-    // Remove any lines sourcing proxy envs from the profile - exactly like bash sed
-    if let Some(profile_path) = &shell_profile {
-        if Path::new(profile_path).exists() {
-            if let Ok(content) = fs::read_to_string(profile_path) {
-                // Filter out lines that match the sed pattern: '/[[:space:]]*source \/tmp\/tsh_proxy_/d'
-                let cleaned_lines: Vec<&str> = content
-                    .lines()
-                    .filter(|line| {
-                        let trimmed = line.trim_start();
-                        !trimmed.starts_with("source /tmp/tsh_proxy_")
-                    })
-                    .collect();
-                
-                let _ = fs::write(profile_path, cleaned_lines.join("\n"));
-                println!("\n\n✏️ \x1b[0mRemoving source lines from {}...\x1b[0m", profile_path);
-            }
+    // Drop th's block from the rc file, unless th is told to keep out of it
+    if client.config().shell.manage_rc {
+        let shell = crate::shell::ShellIntegration::new()?;
+        let profile_path = shell.profile_path().display().to_string();
+        match shell.cleanup_profile(client.config()) {
+            Ok(()) => println!("\n\n✏️ \x1b[0mRemoving th's block from {}...\x1b[0m", profile_path),
+            Err(e) => print_warning(&format!("Could not clean up {}: {}", profile_path, e)),
         }
     }
     
@@ -620,9 +585,17 @@ pub async fn th_kill() -> anyhow::Result<()> {
     std::env::remove_var("ACCOUNT");
     std::env::remove_var("ROLE");
     std::env::remove_var("AWS_DEFAULT_REGION");
+    let _ = crate::shell::request_env(&[], crate::commands::aws::SESSION_VARS);
     
     println!("\n💀 \x1b[0mKilling all running tsh proxies...\x1b[0m\n");
     
+    // Proxies th registered first, then anything left over from before the registry
+    if let Ok(entries) = crate::proxies::list(client.config()) {
+        for entry in &entries {
+            let _ = crate::proxies::stop(client.config(), entry);
+        }
+    }
+    
     // Kill all tsh proxy processes - exactly like bash: ps aux | grep '[t]sh proxy aws' | awk '{print $2}' | xargs kill
     let ps_output = Command::new("ps")
         .args(["aux"])
@@ -659,17 +632,8 @@ pub async fn th_kill() -> anyhow::Result<()> {
     }
     
     // Logout from teleport - exactly like bash
-    let _ = Command::new("tsh")
-        .args(["logout"])
-        .stdout(std::process::Stdio::null())
-        .stderr(std::process::Stdio::null())
-        .output();
-        
-    let _ = Command::new("tsh")
-        .args(["apps", "logout"])
-        .stdout(std::process::Stdio::null())
-        .stderr(std::process::Stdio::null())
-        .output();
+    let _ = client.logout().await;
+    let _ = client.aws_logout(None).await;
     
     println!("\n✅ \x1b[1;32mLogged out of all apps, clusters & proxies\x1b[0m\n");
     
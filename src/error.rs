@@ -6,7 +6,7 @@ pub enum ThError {
     AuthFailed(String),
     
     #[error("Configuration error: {0}")]
-    Config(#[from] config::ConfigError),
+    Config(String),
     
     #[error("Process execution failed: {0}")]
     Process(String),
@@ -34,4 +34,10 @@ pub enum ThError {
     
     #[error("Teleport proxy error: {0}")]
     Proxy(String),
+    
+    #[error("Proxy exited before it was ready: {0}")]
+    ProxyExited(String),
+    
+    #[error("Proxy never opened port {0}")]
+    PortNeverOpened(u16),
 }
\ No newline at end of file
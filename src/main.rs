@@ -1,21 +1,61 @@
 use anyhow::Result;
 use clap::Parser;
 
+mod aws_config;
+mod backend;
 mod cli;
 mod commands;
 mod config;
 mod display;
 mod error;
+mod models;
 mod process;
+mod proxies;
+mod readiness;
 mod shell;
+mod subshell;
 mod teleport;
+mod tools;
 mod update;
+mod watchdog;
 
-use cli::{Cli, Commands};
+use cli::{AwsCommand, Cli, Commands};
 use config::Config;
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    let cli = Cli::parse();
+    
+    // Through the environment so `th config` and any th we spawn see the same profile
+    if let Some(profile) = &cli.profile {
+        std::env::set_var(config::layers::PROFILE_ENV, profile);
+    }
+    
+    // Runs with every prompt: no migration, update check or tool validation
+    if let Some(Commands::Prompt(prompt_args)) = cli.command {
+        return commands::prompt::execute(prompt_args).await;
+    }
+    
+    // Upgrade a config file written by an older th before anything reads it
+    match config::migrate::upgrade_user_config() {
+        Ok(config::migrate::Upgrade::Upgraded(report, backup)) => config::migrate::print_report(&report, &backup),
+        Ok(config::migrate::Upgrade::TooNew(version)) => config::migrate::print_too_new(version),
+        Ok(config::migrate::Upgrade::Unchanged) => {}
+        Err(e) => eprintln!("Warning: could not migrate config: {}", e),
+    }
+    
+    // th config has to work even when the config itself is broken
+    let command = match cli.command {
+        Some(Commands::Config(config_args)) => return commands::config::execute(config_args).await,
+        command => command,
+    };
+    
+    // The AWS CLI and shells parse our stdout as credentials, th exec's stdout is the command's,
+    // and scripts parse `th status --json` - nothing else may go there
+    let machine_output = matches!(&command, Some(Commands::Exec(_)) | Some(Commands::ShellInit(_)))
+        || matches!(&command, Some(Commands::Status(args)) if args.json)
+        || matches!(&command, Some(Commands::Aws(args)) if matches!(args.command, Some(AwsCommand::CredentialProcess { .. }) | Some(AwsCommand::Env { .. })));
+    
     // Initialize configuration
     let config = Config::load().await?;
     
@@ -24,16 +64,12 @@ async fn main() -> Result<()> {
         let _ = update_checker.check_for_updates_background().await;
     }
     
-    // Validate that required tools are available (for commands that need them)
-    let cli = Cli::parse();
-    
     // Only validate teleport tools for commands that need them
-    match &cli.command {
-        Some(Commands::Kube(_)) | Some(Commands::Aws(_)) | Some(Commands::Database(_)) | Some(Commands::Login(_)) | Some(Commands::Logout(_)) => {
-            let client = crate::teleport::TeleportClient::new(config.clone());
+    match &command {
+        Some(Commands::Kube(_)) | Some(Commands::Aws(_)) | Some(Commands::Database(_)) | Some(Commands::Login(_)) | Some(Commands::Logout(_)) | Some(Commands::Exec(_)) => {
+            let client = crate::teleport::TeleportClient::new(config.clone())?;
             if let Err(e) = client.validate_tools().await {
                 eprintln!("Error: {}", e);
-                eprintln!("Make sure Teleport (tsh) is installed and in your PATH");
                 std::process::exit(1);
             }
         }
@@ -49,7 +85,7 @@ async fn main() -> Result<()> {
     }
     
     // Execute command  
-    let result = match cli.command {
+    let result = match command {
         None => {
             // No command provided, show original help screen (like bash version)
             display::print_help("1.5.0");
@@ -73,6 +109,20 @@ async fn main() -> Result<()> {
         Some(Commands::Logout(logout_args)) => {
             commands::logout::execute(logout_args, &config).await
         }
+        Some(Commands::Proxy(proxy_args)) => {
+            commands::proxy::execute(proxy_args, &config).await
+        }
+        Some(Commands::Status(status_args)) => {
+            commands::status::execute(status_args, &config).await
+        }
+        Some(Commands::Exec(exec_args)) => {
+            commands::exec::execute(exec_args, &config).await
+        }
+        Some(Commands::ShellInit(shell_init_args)) => {
+            commands::shell_init::execute(shell_init_args).await
+        }
+        Some(Commands::Config(_)) => unreachable!("th config runs before the config is loaded"),
+        Some(Commands::Prompt(_)) => unreachable!("th prompt runs before anything else"),
         Some(Commands::Version) => {
             commands::version::execute().await
         }
@@ -98,9 +148,15 @@ async fn main() -> Result<()> {
         Some(Commands::ClearUpdateCache) => {
             commands::update::clear_cache().await
         }
+        Some(Commands::FakeProxy(fake_args)) => {
+            backend::fake::run_fake_proxy(&fake_args.kind, &fake_args.target, fake_args.port, fake_args.endpoint_url).await
+        }
     };
     
     // Show update notification after command completion (end-of-flow)
+    if machine_output {
+        return result;
+    }
     if let Ok(update_checker) = crate::update::UpdateChecker::new() {
         if let Ok(Some(_notification)) = update_checker.should_show_update_notification().await {
             // Extract version numbers from the cache
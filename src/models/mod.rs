@@ -0,0 +1,13 @@
+pub mod resources;
+pub mod status;
+
+use serde::{Deserialize, Deserializer};
+
+/// Deserialize a field that tsh sometimes prints as `null` into its default value
+pub(crate) fn nullable<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Default + Deserialize<'de>,
+{
+    Ok(Option::<T>::deserialize(deserializer)?.unwrap_or_default())
+}
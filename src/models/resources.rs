@@ -0,0 +1,382 @@
+use super::nullable;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// `metadata` block shared by Teleport resources
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Metadata {
+    #[serde(deserialize_with = "nullable")]
+    pub name: String,
+    #[serde(deserialize_with = "nullable")]
+    pub description: String,
+    #[serde(deserialize_with = "nullable")]
+    pub labels: BTreeMap<String, String>,
+}
+
+/// An item from `tsh apps ls --format=json`
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct App {
+    pub metadata: Metadata,
+    pub spec: AppSpec,
+    /// Only present in tsh versions that list roles alongside apps
+    #[serde(deserialize_with = "nullable")]
+    pub aws_roles: Vec<AwsRole>,
+    /// Leaf cluster the app was listed from; `None` for the root cluster
+    #[serde(skip)]
+    pub cluster: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct AppSpec {
+    #[serde(deserialize_with = "nullable")]
+    pub uri: String,
+    #[serde(deserialize_with = "nullable")]
+    pub public_addr: String,
+}
+
+/// An AWS IAM role offered by an app
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct AwsRole {
+    #[serde(deserialize_with = "nullable")]
+    pub name: String,
+    #[serde(deserialize_with = "nullable")]
+    pub arn: String,
+    #[serde(deserialize_with = "nullable")]
+    pub account_id: String,
+}
+
+/// An item from `tsh kube ls -f json`
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct KubeCluster {
+    pub name: String,
+    pub labels: BTreeMap<String, String>,
+    pub selected: bool,
+    /// Leaf cluster it was listed from; `None` for the root cluster
+    pub cluster: Option<String>,
+}
+
+/// An item from `tsh db ls --format=json`
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Database {
+    pub metadata: Metadata,
+    pub spec: DatabaseSpec,
+    pub users: AllowList,
+    pub database_names: AllowList,
+    /// Leaf cluster it was listed from; `None` for the root cluster
+    #[serde(skip)]
+    pub cluster: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct DatabaseSpec {
+    #[serde(deserialize_with = "nullable")]
+    pub protocol: String,
+    #[serde(deserialize_with = "nullable")]
+    pub uri: String,
+    pub aws: DatabaseAws,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct DatabaseAws {
+    #[serde(deserialize_with = "nullable")]
+    pub region: String,
+    #[serde(deserialize_with = "nullable")]
+    pub account_id: String,
+}
+
+/// An item from `tsh clusters --format=json`
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct TeleportCluster {
+    #[serde(deserialize_with = "nullable")]
+    pub cluster_name: String,
+    #[serde(deserialize_with = "nullable")]
+    pub status: String,
+    /// `root` or `leaf`
+    #[serde(deserialize_with = "nullable")]
+    pub cluster_type: String,
+    #[serde(deserialize_with = "nullable")]
+    pub selected: bool,
+}
+
+/// Allowed/denied values (db users, db names) computed by tsh from the user's roles
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct AllowList {
+    #[serde(deserialize_with = "nullable")]
+    pub allowed: Vec<String>,
+    #[serde(deserialize_with = "nullable")]
+    pub denied: Vec<String>,
+}
+
+impl App {
+    pub fn name(&self) -> &str {
+        &self.metadata.name
+    }
+
+    /// `leaf/name` for leaf cluster apps - what the rest of th passes around
+    pub fn qualified_name(&self) -> String {
+        qualify(self.cluster.as_deref(), self.name())
+    }
+}
+
+impl KubeCluster {
+    pub fn qualified_name(&self) -> String {
+        qualify(self.cluster.as_deref(), &self.name)
+    }
+}
+
+impl Database {
+    pub fn name(&self) -> &str {
+        &self.metadata.name
+    }
+
+    pub fn qualified_name(&self) -> String {
+        qualify(self.cluster.as_deref(), self.name())
+    }
+
+    pub fn label(&self, key: &str) -> Option<&str> {
+        self.metadata.labels.get(key).map(|v| v.as_str())
+    }
+
+    /// RDS databases are tagged `db_type=rds` - exactly like bash check_rds_login
+    pub fn is_rds(&self) -> bool {
+        self.label("db_type") == Some("rds")
+    }
+}
+
+/// Older tsh prints `kube_cluster_name` + `labels`, newer tsh a full resource with `metadata`
+impl<'de> Deserialize<'de> for KubeCluster {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize, Default)]
+        #[serde(default)]
+        struct Raw {
+            kube_cluster_name: Option<String>,
+            metadata: Option<Metadata>,
+            #[serde(deserialize_with = "nullable")]
+            labels: BTreeMap<String, String>,
+            #[serde(deserialize_with = "nullable")]
+            selected: bool,
+        }
+
+        let raw = Raw::deserialize(deserializer)?;
+        let metadata = raw.metadata.unwrap_or_default();
+        let name = raw.kube_cluster_name
+            .filter(|n| !n.is_empty())
+            .unwrap_or(metadata.name);
+        let mut labels = metadata.labels;
+        labels.extend(raw.labels);
+
+        Ok(Self {
+            name,
+            labels,
+            selected: raw.selected,
+            cluster: None,
+        })
+    }
+}
+
+impl TeleportCluster {
+    pub fn is_leaf(&self) -> bool {
+        self.cluster_type == "leaf"
+    }
+
+    pub fn is_online(&self) -> bool {
+        self.status.is_empty() || self.status == "online"
+    }
+}
+
+/// Resource names in a leaf cluster are written `leaf/name` in config and between
+/// commands; Teleport resource names never contain `/`
+pub fn split_target(target: &str) -> (Option<&str>, &str) {
+    match target.split_once('/') {
+        Some((cluster, name)) if !cluster.is_empty() => (Some(cluster), name),
+        _ => (None, target),
+    }
+}
+
+pub fn qualify(cluster: Option<&str>, name: &str) -> String {
+    match cluster {
+        Some(cluster) => format!("{}/{}", cluster, name),
+        None => name.to_string(),
+    }
+}
+
+/// Parse a tsh JSON list, skipping entries this version of th can't read
+pub fn parse_list<T: DeserializeOwned>(json: Value) -> Vec<T> {
+    match json {
+        Value::Array(items) => items
+            .into_iter()
+            .filter_map(|item| serde_json::from_value(item).ok())
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Parse the "Available AWS roles" table `tsh apps login <app>` prints when no role is given
+pub fn parse_aws_roles_table(output: &str) -> Vec<AwsRole> {
+    let mut in_roles_section = false;
+    let mut roles = Vec::new();
+
+    for line in output.lines() {
+        if line.contains("Available AWS roles:") {
+            in_roles_section = true;
+            continue;
+        }
+        if !in_roles_section {
+            continue;
+        }
+        if line.contains("ERROR:") {
+            break;
+        }
+
+        let mut columns = line.split_whitespace();
+        let name = match columns.next() {
+            Some(name) => name,
+            None => continue,
+        };
+        // Skip the header and separator rows
+        if name == "Role" || name.starts_with("---") {
+            continue;
+        }
+
+        let arn = line.split_whitespace().find(|c| c.starts_with("arn:")).unwrap_or("").to_string();
+        let account_id = arn.split(':').nth(4).unwrap_or("").to_string();
+        roles.push(AwsRole {
+            name: name.to_string(),
+            arn,
+            account_id,
+        });
+    }
+
+    roles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn kube_cluster_reads_the_old_flat_shape() {
+        let cluster: KubeCluster = serde_json::from_value(json!({
+            "kube_cluster_name": "aslive-dev-eks-blue",
+            "labels": {"env": "dev"},
+            "selected": true
+        })).unwrap();
+        assert_eq!(cluster.name, "aslive-dev-eks-blue");
+        assert_eq!(cluster.labels.get("env").map(String::as_str), Some("dev"));
+        assert!(cluster.selected);
+        assert_eq!(cluster.cluster, None);
+    }
+
+    #[test]
+    fn kube_cluster_reads_the_resource_shape() {
+        let cluster: KubeCluster = serde_json::from_value(json!({
+            "kind": "kube_cluster",
+            "metadata": {"name": "live-prod-eks-blue", "labels": {"env": "prod", "region": "eu"}},
+            "spec": {}
+        })).unwrap();
+        assert_eq!(cluster.name, "live-prod-eks-blue");
+        assert_eq!(cluster.labels.len(), 2);
+        assert!(!cluster.selected);
+    }
+
+    #[test]
+    fn kube_cluster_prefers_the_flat_name_and_merges_labels() {
+        let cluster: KubeCluster = serde_json::from_value(json!({
+            "kube_cluster_name": "flat-name",
+            "metadata": {"name": "metadata-name", "labels": {"env": "dev", "team": "core"}},
+            "labels": {"env": "staging"}
+        })).unwrap();
+        assert_eq!(cluster.name, "flat-name");
+        assert_eq!(cluster.labels.get("env").map(String::as_str), Some("staging"));
+        assert_eq!(cluster.labels.get("team").map(String::as_str), Some("core"));
+    }
+
+    #[test]
+    fn nulls_read_as_defaults() {
+        let cluster: KubeCluster = serde_json::from_value(json!({
+            "kube_cluster_name": "eks", "labels": null, "selected": null
+        })).unwrap();
+        assert!(cluster.labels.is_empty());
+        assert!(!cluster.selected);
+
+        let app: App = serde_json::from_value(json!({
+            "metadata": {"name": "yl-development", "description": null, "labels": null},
+            "spec": {"uri": null},
+            "aws_roles": null
+        })).unwrap();
+        assert_eq!(app.name(), "yl-development");
+        assert_eq!(app.metadata.description, "");
+        assert!(app.metadata.labels.is_empty());
+        assert!(app.aws_roles.is_empty());
+
+        let db: Database = serde_json::from_value(json!({
+            "metadata": {"name": "dev-postgres", "labels": {"db_type": "rds"}},
+            "users": {"allowed": null, "denied": null}
+        })).unwrap();
+        assert!(db.is_rds());
+        assert!(db.users.allowed.is_empty());
+    }
+
+    #[test]
+    fn parse_list_skips_unreadable_items() {
+        let clusters: Vec<TeleportCluster> = parse_list(json!([
+            {"cluster_name": "youlend", "cluster_type": "root"},
+            "not a cluster",
+            {"cluster_name": "partner", "cluster_type": "leaf", "status": "offline"}
+        ]));
+        assert_eq!(clusters.len(), 2);
+        assert!(clusters[1].is_leaf() && !clusters[1].is_online());
+        assert!(parse_list::<TeleportCluster>(json!({"error": "not a list"})).is_empty());
+    }
+
+    #[test]
+    fn parse_aws_roles_table_reads_names_and_arns() {
+        let output = "\
+Available AWS roles:
+Role Name Role ARN
+--------- ---------------------------------------
+dev       arn:aws:iam::123456789012:role/dev
+sudo_dev  arn:aws:iam::123456789012:role/sudo_dev
+
+ERROR: --aws-role flag is required
+ignored   arn:aws:iam::999999999999:role/ignored
+";
+        let roles = parse_aws_roles_table(output);
+        let names: Vec<&str> = roles.iter().map(|role| role.name.as_str()).collect();
+        assert_eq!(names, ["dev", "sudo_dev"]);
+        assert_eq!(roles[1].arn, "arn:aws:iam::123456789012:role/sudo_dev");
+        assert_eq!(roles[1].account_id, "123456789012");
+    }
+
+    #[test]
+    fn parse_aws_roles_table_without_a_table_is_empty() {
+        assert!(parse_aws_roles_table("Logged into app yl-development\n").is_empty());
+    }
+
+    #[test]
+    fn split_target_and_qualify_round_trip_leaf_names() {
+        assert_eq!(split_target("partner/partner-eks"), (Some("partner"), "partner-eks"));
+        assert_eq!(split_target("aslive-dev-eks-blue"), (None, "aslive-dev-eks-blue"));
+        // No cluster before the slash isn't a leaf
+        assert_eq!(split_target("/odd"), (None, "/odd"));
+        assert_eq!(qualify(Some("partner"), "partner-eks"), "partner/partner-eks");
+        assert_eq!(qualify(None, "yl-development"), "yl-development");
+        let (cluster, name) = split_target("partner/partner-postgres");
+        assert_eq!(qualify(cluster, name), "partner/partner-postgres");
+    }
+}
@@ -0,0 +1,135 @@
+use super::nullable;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// `tsh status --format=json`
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct TshStatus {
+    /// Profile tsh is currently using
+    pub active: Option<ProfileStatus>,
+    /// Every other profile tsh holds certificates for
+    #[serde(deserialize_with = "nullable")]
+    pub profiles: Vec<ProfileStatus>,
+}
+
+/// One Teleport profile (proxy + user) as reported by tsh
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ProfileStatus {
+    #[serde(deserialize_with = "nullable")]
+    pub profile_url: String,
+    #[serde(deserialize_with = "nullable")]
+    pub username: String,
+    #[serde(deserialize_with = "nullable")]
+    pub cluster: String,
+    #[serde(deserialize_with = "nullable")]
+    pub roles: Vec<String>,
+    #[serde(deserialize_with = "nullable")]
+    pub traits: BTreeMap<String, Value>,
+    #[serde(deserialize_with = "nullable")]
+    pub logins: Vec<String>,
+    #[serde(deserialize_with = "request_ids")]
+    pub active_requests: Vec<String>,
+    #[serde(deserialize_with = "timestamp")]
+    pub valid_until: Option<DateTime<Utc>>,
+    #[serde(deserialize_with = "nullable")]
+    pub kubernetes_enabled: bool,
+    #[serde(deserialize_with = "nullable")]
+    pub kubernetes_cluster: String,
+    #[serde(deserialize_with = "nullable")]
+    pub kubernetes_users: Vec<String>,
+    #[serde(deserialize_with = "nullable")]
+    pub kubernetes_groups: Vec<String>,
+    #[serde(deserialize_with = "nullable")]
+    pub databases: Vec<DatabaseCert>,
+    #[serde(deserialize_with = "nullable")]
+    pub apps: Vec<AppCert>,
+    #[serde(deserialize_with = "nullable")]
+    pub extensions: Vec<String>,
+}
+
+/// A database certificate held by the profile
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct DatabaseCert {
+    #[serde(deserialize_with = "nullable")]
+    pub service_name: String,
+    #[serde(deserialize_with = "nullable")]
+    pub protocol: String,
+    #[serde(deserialize_with = "nullable")]
+    pub username: String,
+    #[serde(deserialize_with = "nullable")]
+    pub database: String,
+}
+
+/// An app certificate held by the profile
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct AppCert {
+    #[serde(deserialize_with = "nullable")]
+    pub name: String,
+    #[serde(deserialize_with = "nullable")]
+    pub public_addr: String,
+    #[serde(deserialize_with = "nullable")]
+    pub aws_role_arn: String,
+}
+
+impl TshStatus {
+    pub fn from_value(value: Value) -> Result<Self, serde_json::Error> {
+        serde_json::from_value(value)
+    }
+}
+
+impl ProfileStatus {
+    /// Whether this profile belongs to `proxy` (`host[:port]`); tsh versions
+    /// that don't report a profile URL are assumed to match
+    pub fn is_for_proxy(&self, proxy: &str) -> bool {
+        self.profile_url.is_empty() || proxy_host(&self.profile_url) == proxy_host(proxy)
+    }
+
+    /// Certificate still valid (tsh keeps expired profiles around)
+    pub fn is_valid(&self) -> bool {
+        match self.valid_until {
+            Some(valid_until) => valid_until > Utc::now(),
+            None => !self.username.is_empty(),
+        }
+    }
+}
+
+/// `youlend.teleport.sh` from `https://youlend.teleport.sh:443/` or `youlend.teleport.sh:443`
+fn proxy_host(url: &str) -> String {
+    let without_scheme = url.split_once("://").map(|(_, rest)| rest).unwrap_or(url);
+    let host_port = without_scheme.split('/').next().unwrap_or_default();
+    host_port.split(':').next().unwrap_or_default().to_lowercase()
+}
+
+/// `active_requests` is a list of IDs in current tsh and a list of objects in older ones
+fn request_ids<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let items: Vec<Value> = nullable(deserializer)?;
+    Ok(items
+        .into_iter()
+        .filter_map(|item| match item {
+            Value::String(id) => Some(id),
+            Value::Object(map) => map.get("id").and_then(|id| id.as_str()).map(|id| id.to_string()),
+            _ => None,
+        })
+        .collect())
+}
+
+/// RFC 3339 timestamp; anything unparseable (or Go's zero time) becomes `None`
+fn timestamp<'de, D>(deserializer: D) -> Result<Option<DateTime<Utc>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let raw: Option<String> = Option::deserialize(deserializer)?;
+    Ok(raw
+        .and_then(|s| DateTime::parse_from_rfc3339(&s).ok())
+        .map(|t| t.with_timezone(&Utc))
+        .filter(|t| t.timestamp() > 0))
+}
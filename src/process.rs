@@ -101,6 +101,30 @@ pub async fn execute_command_interactive(program: &str, args: &[&str]) -> Result
     Ok(())
 }
 
+/// Wait for an interactive child. Ctrl-C reaches it as well as us; rather than dying
+/// first we let it decide, so callers get to clean up after it.
+pub async fn wait_in_foreground(child: &mut tokio::process::Child) -> Result<std::process::ExitStatus> {
+    loop {
+        tokio::select! {
+            status = child.wait() => return Ok(status?),
+            _ = tokio::signal::ctrl_c() => {}
+        }
+    }
+}
+
+/// Exit code to pass on for a child's status: its own, or 128 plus the signal that
+/// killed it, as shells report it
+pub fn exit_code(status: std::process::ExitStatus) -> i32 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        if let Some(signal) = status.signal() {
+            return 128 + signal;
+        }
+    }
+    status.code().unwrap_or(1)
+}
+
 /// Kill processes by pattern
 pub async fn kill_processes_by_pattern(pattern: &str) -> Result<()> {
     let output = execute_command("pgrep", &["-f", pattern]).await;
@@ -167,25 +191,47 @@ pub async fn execute_background(program: &str, args: &[&str]) -> Result<tokio::p
     Ok(child)
 }
 
-/// Wait for a condition to be true with timeout
-pub async fn wait_for_condition<F>(
-    condition: F,
-    timeout_secs: u64,
-    check_interval_ms: u64,
-) -> Result<()>
-where
-    F: Fn() -> bool,
-{
-    let timeout_duration = Duration::from_secs(timeout_secs);
-    let check_interval = Duration::from_millis(check_interval_ms);
-    let start_time = tokio::time::Instant::now();
-    
-    while start_time.elapsed() < timeout_duration {
-        if condition() {
-            return Ok(());
-        }
-        tokio::time::sleep(check_interval).await;
+/// Command line of a running process, or `None` if it has exited.
+/// Windows only reports the image name.
+pub fn process_command_line(pid: u32) -> Option<String> {
+    let output = if cfg!(windows) {
+        std::process::Command::new("tasklist")
+            .args(["/FI", &format!("PID eq {}", pid), "/FO", "CSV", "/NH"])
+            .output()
+            .ok()?
+    } else {
+        std::process::Command::new("ps")
+            .args(["-p", &pid.to_string(), "-o", "command="])
+            .output()
+            .ok()?
+    };
+
+    let stdout = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    // tasklist prints an INFO line instead of failing when nothing matches
+    if !output.status.success() || stdout.is_empty() || stdout.starts_with("INFO:") || stdout.contains("<defunct>") {
+        return None;
     }
-    
-    Err(ThError::Timeout("Condition not met within timeout".to_string()).into())
-}
\ No newline at end of file
+    Some(stdout)
+}
+
+/// Stop a background process th started
+pub fn terminate_process(pid: u32) -> Result<()> {
+    let status = if cfg!(windows) {
+        std::process::Command::new("taskkill")
+            .args(["/PID", &pid.to_string(), "/F"])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()?
+    } else {
+        std::process::Command::new("kill")
+            .arg(pid.to_string())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()?
+    };
+
+    if !status.success() {
+        return Err(ThError::Process(format!("Could not stop process {}", pid)).into());
+    }
+    Ok(())
+}
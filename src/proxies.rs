@@ -0,0 +1,491 @@
+use crate::backend::ProxyRequest;
+use crate::config::edit::write_atomic;
+use crate::config::Config;
+use crate::error::ThError;
+use crate::process::{process_command_line, terminate_process};
+use crate::readiness;
+use crate::teleport::TeleportClient;
+use crate::watchdog;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+
+/// Registry file inside `runtime_dir`
+const REGISTRY_FILE: &str = "proxies.json";
+/// Assigned ports, next to the config file
+const PORTS_FILE: &str = "ports.json";
+/// Held while the registry or the assigned ports change
+const LOCK_FILE: &str = "proxies.lock";
+/// Local ports proxies are assigned from, end exclusive
+const PORT_RANGE: (u16, u16) = (40000, 60000);
+
+/// A background `tsh proxy` started by th
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProxyEntry {
+    pub id: u32,
+    pub pid: u32,
+    #[serde(flatten)]
+    pub request: ProxyRequest,
+    /// AWS role an app proxy was started for
+    #[serde(default)]
+    pub role: Option<String>,
+    pub started_at: DateTime<Utc>,
+    /// Everything the proxy printed
+    pub log: PathBuf,
+    /// Times `restart` has brought it back
+    #[serde(default)]
+    pub restarts: u32,
+}
+
+impl ProxyEntry {
+    pub fn kind(&self) -> &'static str {
+        kind_and_target(&self.request).0
+    }
+
+    /// App or database being proxied
+    pub fn target(&self) -> &str {
+        kind_and_target(&self.request).1
+    }
+
+    /// Local port. Entries from before ports were assigned get the AWS proxy's from its log.
+    pub fn port(&self) -> Option<u16> {
+        match &self.request {
+            ProxyRequest::Db { port, .. } | ProxyRequest::Aws { port, .. } if *port != 0 => Some(*port),
+            ProxyRequest::Db { .. } => None,
+            ProxyRequest::Aws { .. } => {
+                let log = std::fs::read_to_string(&self.log).ok()?;
+                let re = Regex::new(r"HTTPS_PROXY=https?://[^:\s]+:(\d+)").ok()?;
+                re.captures(&log)?.get(1)?.as_str().parse().ok()
+            }
+        }
+    }
+
+    /// Database user, or the AWS role
+    pub fn user(&self) -> Option<&str> {
+        match &self.request {
+            ProxyRequest::Db { db_user, .. } => db_user.as_deref(),
+            ProxyRequest::Aws { .. } => self.role.as_deref(),
+        }
+    }
+
+    /// Whether the process is still a running proxy - PIDs get reused
+    pub fn is_running(&self) -> bool {
+        match process_command_line(self.pid) {
+            Some(command) => cfg!(windows) || command.contains("proxy"),
+            None => false,
+        }
+    }
+}
+
+/// `proxy.ready_timeout_seconds`
+pub fn ready_timeout(config: &Config) -> std::time::Duration {
+    std::time::Duration::from_secs(config.proxy.ready_timeout_seconds)
+}
+
+/// Wait until a freshly started proxy accepts connections
+pub async fn wait_ready(config: &Config, entry: &ProxyEntry) -> Result<()> {
+    match entry.port() {
+        Some(port) => readiness::wait_for_port(port, ready_timeout(config), || entry.is_running()).await,
+        None => Ok(()),
+    }
+}
+
+/// Per-user directory for the registry and proxy logs
+pub fn runtime_dir(config: &Config) -> PathBuf {
+    dirs::runtime_dir()
+        .map(|dir| dir.join("th"))
+        .unwrap_or_else(|| {
+            let user = std::env::var("USER")
+                .or_else(|_| std::env::var("USERNAME"))
+                .unwrap_or_else(|_| "user".to_string());
+            config.paths.temp_dir.join(format!("th-{}", user))
+        })
+}
+
+pub fn ensure_runtime_dir(config: &Config) -> Result<PathBuf> {
+    let dir = runtime_dir(config);
+    std::fs::create_dir_all(dir.join("logs"))?;
+    // Logs hold AWS credentials
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o700))?;
+    }
+    Ok(dir)
+}
+
+/// Exclusive lock on the registry, released when dropped. The AWS SDKs run
+/// `credential_process` several times at once, and each run may start a proxy.
+fn lock(config: &Config) -> Result<File> {
+    let dir = ensure_runtime_dir(config)?;
+    let file = std::fs::OpenOptions::new().create(true).truncate(false).write(true).open(dir.join(LOCK_FILE))?;
+    file.lock()?;
+    Ok(file)
+}
+
+/// Every registered proxy, including ones that have since died
+pub fn load(config: &Config) -> Result<Vec<ProxyEntry>> {
+    let path = runtime_dir(config).join(REGISTRY_FILE);
+    if !path.is_file() {
+        return Ok(Vec::new());
+    }
+    let content = std::fs::read_to_string(&path)?;
+    // A registry from an incompatible th is treated as empty rather than blocking every proxy
+    Ok(serde_json::from_str(&content).unwrap_or_default())
+}
+
+fn save(config: &Config, entries: &[ProxyEntry]) -> Result<()> {
+    let dir = ensure_runtime_dir(config)?;
+    write_atomic(&dir.join(REGISTRY_FILE), &serde_json::to_string_pretty(entries)?)
+}
+
+/// Registered proxies. Entries whose process has gone are dropped from the
+/// registry - unless the watchdog is running, which will bring them back.
+pub fn list(config: &Config) -> Result<Vec<ProxyEntry>> {
+    if watchdog::running_pid(config).is_some() {
+        return load(config);
+    }
+    let _lock = lock(config)?;
+    let entries = load(config)?;
+    let (running, dead): (Vec<_>, Vec<_>) = entries.into_iter().partition(|entry| entry.is_running());
+    if !dead.is_empty() {
+        for entry in &dead {
+            let _ = std::fs::remove_file(&entry.log);
+        }
+        save(config, &running)?;
+    }
+    Ok(running)
+}
+
+/// A registered proxy, running or not - one that died can still be restarted or have
+/// its log read. Only `list` prunes dead entries.
+pub fn find(config: &Config, id: u32) -> Result<ProxyEntry> {
+    load(config)?
+        .into_iter()
+        .find(|entry| entry.id == id)
+        .ok_or_else(|| ThError::NotFound(format!("No proxy with id {} (see `th proxy ls`)", id)).into())
+}
+
+/// Start a proxy in the background and register it. An earlier proxy for the same
+/// resource and role is replaced, so the new one gets its port.
+pub fn start(client: &TeleportClient, request: ProxyRequest, role: Option<&str>) -> Result<ProxyEntry> {
+    let _lock = lock(client.config())?;
+    start_locked(client, request, role)
+}
+
+/// The running proxy for the same resource and role, or a new one; true when it was started
+pub fn ensure(client: &TeleportClient, request: ProxyRequest, role: Option<&str>) -> Result<(ProxyEntry, bool)> {
+    let _lock = lock(client.config())?;
+    let running = load(client.config())?.into_iter().find(|entry| {
+        same_resource(&entry.request, &request) && entry.role.as_deref() == role && entry.is_running()
+    });
+    match running {
+        Some(entry) => Ok((entry, false)),
+        None => Ok((start_locked(client, request, role)?, true)),
+    }
+}
+
+fn start_locked(client: &TeleportClient, mut request: ProxyRequest, role: Option<&str>) -> Result<ProxyEntry> {
+    let config = client.config();
+    // A proxy for another role keeps running, e.g. a subshell's or `th exec`'s
+    let replaced = load(config)?.into_iter()
+        .filter(|entry| same_resource(&entry.request, &request) && entry.role.as_deref() == role);
+    for existing in replaced {
+        stop_locked(config, &existing)?;
+    }
+
+    let entries = load(config)?;
+    let key = port_key(&request, role);
+    let endpoint = matches!(request, ProxyRequest::Aws { endpoint_url: true, .. });
+    let (ProxyRequest::Aws { port, .. } | ProxyRequest::Db { port, .. }) = &mut request;
+    if *port == 0 {
+        *port = if endpoint { endpoint_port(config, &key, &entries)? } else { port_for(config, &key, &entries)? };
+    }
+
+    let id = entries.iter().map(|entry| entry.id).max().unwrap_or(0) + 1;
+    let entry = launch(client, id, request, role.map(|r| r.to_string()), 0)?;
+
+    if config.proxy.watch {
+        if let Err(e) = watchdog::spawn_detached(config) {
+            eprintln!("Warning: could not start the proxy watchdog: {}", e);
+        }
+    }
+    Ok(entry)
+}
+
+/// Stop a registered proxy and forget it
+pub fn stop(config: &Config, entry: &ProxyEntry) -> Result<()> {
+    let _lock = lock(config)?;
+    stop_locked(config, entry)
+}
+
+fn stop_locked(config: &Config, entry: &ProxyEntry) -> Result<()> {
+    if entry.is_running() {
+        terminate_process(entry.pid)?;
+    }
+    forget_locked(config, entry.id)?;
+    let _ = std::fs::remove_file(&entry.log);
+    if let Some(port) = entry.port() {
+        wait_for_port_release(port);
+    }
+    Ok(())
+}
+
+/// Stop a proxy, if it is still running, and start it again with the same id, target and port
+pub fn restart(client: &TeleportClient, id: u32) -> Result<ProxyEntry> {
+    // Locked before the lookup, so a concurrent start can't replace the entry in between
+    let _lock = lock(client.config())?;
+    let entry = find(client.config(), id)?;
+    if entry.is_running() {
+        terminate_process(entry.pid)?;
+    }
+
+    // The old tunnel has to let go of its port first
+    if let Some(port) = entry.port() {
+        wait_for_port_release(port);
+    }
+
+    launch(client, entry.id, entry.request, entry.role, entry.restarts + 1)
+}
+
+fn launch(client: &TeleportClient, id: u32, request: ProxyRequest, role: Option<String>, restarts: u32) -> Result<ProxyEntry> {
+    let config = client.config();
+    let dir = ensure_runtime_dir(config)?;
+    let log = log_path(&dir, id, &request);
+    let file = File::create(&log)?;
+
+    let child = client.proxy_command(&request)?
+        .stdin(Stdio::null())
+        .stdout(file.try_clone()?)
+        .stderr(file)
+        .spawn()?;
+
+    let entry = ProxyEntry {
+        id,
+        pid: child.id(),
+        request,
+        role,
+        started_at: Utc::now(),
+        log,
+        restarts,
+    };
+
+    let mut entries = load(config)?;
+    entries.retain(|existing| existing.id != id);
+    entries.push(entry.clone());
+    entries.sort_by_key(|entry| entry.id);
+    save(config, &entries)?;
+    Ok(entry)
+}
+
+fn forget_locked(config: &Config, id: u32) -> Result<()> {
+    let mut entries = load(config)?;
+    entries.retain(|entry| entry.id != id);
+    save(config, &entries)
+}
+
+/// The port a resource's proxy should use: its pin from `proxy.ports`, the port it was
+/// given before, or else a port derived from its name, which is then kept for it. A
+/// resource whose port is busy gets the next free one for this run only.
+pub fn port_for(config: &Config, target: &str, entries: &[ProxyEntry]) -> Result<u16> {
+    let busy = |port: u16| entries.iter().any(|entry| entry.port() == Some(port)) || port_in_use(port);
+
+    if let Some(port) = config.proxy.ports.get(target) {
+        if busy(*port) {
+            return Err(ThError::Proxy(format!(
+                "Port {} pinned for {} in proxy.ports is already in use (see `th proxy ls`)",
+                port, target
+            )).into());
+        }
+        return Ok(*port);
+    }
+
+    let port = recorded_port(config, target, &busy)?;
+    if !busy(port) {
+        return Ok(port);
+    }
+    // ports.json can be edited by hand, so the recorded port may be outside the range
+    let preferred = if (PORT_RANGE.0..PORT_RANGE.1).contains(&port) { port } else { stable_port(target) };
+    free_port(config, &load_ports(), preferred, &busy)
+}
+
+/// The port for an endpoint proxy. It is written into an `~/.aws/config` profile, so
+/// there is no other port to fall back on.
+fn endpoint_port(config: &Config, target: &str, entries: &[ProxyEntry]) -> Result<u16> {
+    let busy = |port: u16| entries.iter().any(|entry| entry.port() == Some(port)) || port_in_use(port);
+    let port = match config.proxy.ports.get(target) {
+        Some(port) => *port,
+        None => recorded_port(config, target, &busy)?,
+    };
+    if busy(port) {
+        return Err(ThError::Proxy(format!(
+            "Port {} of the AWS profile for {} is already in use (see `th proxy ls`)",
+            port, target
+        )).into());
+    }
+    Ok(port)
+}
+
+/// The port `target` is pinned to or was given, giving it one now if it has neither.
+/// `th aws sync-profiles` writes these into profiles before any proxy runs.
+pub fn reserve_port(config: &Config, target: &str) -> Result<u16> {
+    let _lock = lock(config)?;
+    match config.proxy.ports.get(target) {
+        Some(port) => Ok(*port),
+        None => recorded_port(config, target, &port_in_use),
+    }
+}
+
+/// The port recorded for `target`, or else a free one derived from its name, recorded
+/// for it from now on
+fn recorded_port(config: &Config, target: &str, busy: &dyn Fn(u16) -> bool) -> Result<u16> {
+    let mut assigned = load_ports();
+    if let Some(port) = assigned.get(target) {
+        return Ok(*port);
+    }
+    let port = free_port(config, &assigned, stable_port(target), busy)?;
+    assigned.insert(target.to_string(), port);
+    save_ports(&assigned)?;
+    Ok(port)
+}
+
+/// The first port from `preferred` on that is free. Ports pinned or given to other
+/// resources stay theirs, even while they're not running.
+fn free_port(config: &Config, assigned: &BTreeMap<String, u16>, preferred: u16, busy: &dyn Fn(u16) -> bool) -> Result<u16> {
+    let reserved = |port: u16| assigned.values().any(|other| *other == port) || config.proxy.ports.values().any(|pinned| *pinned == port);
+    let start = preferred.clamp(PORT_RANGE.0, PORT_RANGE.1 - 1) - PORT_RANGE.0;
+    (0..PORT_RANGE.1 - PORT_RANGE.0)
+        .map(|offset| PORT_RANGE.0 + (start + offset) % (PORT_RANGE.1 - PORT_RANGE.0))
+        .find(|port| !busy(*port) && !reserved(*port))
+        .ok_or_else(|| ThError::Proxy("No free local port for the proxy".to_string()).into())
+}
+
+/// Name a proxy's port is pinned and recorded under: the app or database, or
+/// `<app>:<role>` for an endpoint proxy, as each role has its own profile
+pub fn port_key(request: &ProxyRequest, role: Option<&str>) -> String {
+    match request {
+        ProxyRequest::Aws { app, endpoint_url: true, .. } => format!("{}:{}", app, role.unwrap_or_default()),
+        _ => kind_and_target(request).1.to_string(),
+    }
+}
+
+/// Where assigned ports are kept: next to the config file, as the runtime dir doesn't
+/// survive a reboot
+fn ports_path() -> Result<PathBuf> {
+    Ok(Config::get_config_path()?.with_file_name(PORTS_FILE))
+}
+
+/// Port each resource was first given, by name
+fn load_ports() -> BTreeMap<String, u16> {
+    ports_path().ok()
+        .and_then(|path| std::fs::read_to_string(path).ok())
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+fn save_ports(ports: &BTreeMap<String, u16>) -> Result<()> {
+    write_atomic(&ports_path()?, &serde_json::to_string_pretty(ports)?)
+}
+
+/// FNV-1a of the name, so the port doesn't change between th versions or machines
+fn stable_port(target: &str) -> u16 {
+    let hash = target.bytes().fold(0x811c9dc5u32, |hash, byte| (hash ^ byte as u32).wrapping_mul(0x01000193));
+    PORT_RANGE.0 + (hash % (PORT_RANGE.1 - PORT_RANGE.0) as u32) as u16
+}
+
+fn port_in_use(port: u16) -> bool {
+    std::net::TcpListener::bind(("127.0.0.1", port)).is_err()
+}
+
+/// A killed tunnel takes a moment to let go of its port
+fn wait_for_port_release(port: u16) {
+    for _ in 0..20 {
+        if !port_in_use(port) {
+            return;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(250));
+    }
+}
+
+/// Proxies for the same resource, as the same user, replace each other - AWS proxies
+/// only when they are also for the same role
+fn same_resource(a: &ProxyRequest, b: &ProxyRequest) -> bool {
+    match (a, b) {
+        // A profile's endpoint proxy and a shell's HTTPS proxy run side by side
+        (
+            ProxyRequest::Aws { app, endpoint_url, .. },
+            ProxyRequest::Aws { app: other_app, endpoint_url: other_endpoint, .. },
+        ) => app == other_app && endpoint_url == other_endpoint,
+        (
+            ProxyRequest::Db { db, db_user, db_name, .. },
+            ProxyRequest::Db { db: other_db, db_user: other_user, db_name: other_name, .. },
+        ) => db == other_db && db_user == other_user && db_name == other_name,
+        _ => false,
+    }
+}
+
+fn kind_and_target(request: &ProxyRequest) -> (&'static str, &str) {
+    match request {
+        ProxyRequest::Aws { app, .. } => ("aws", app),
+        ProxyRequest::Db { db, .. } => ("db", db),
+    }
+}
+
+fn log_path(dir: &Path, id: u32, request: &ProxyRequest) -> PathBuf {
+    let (kind, target) = kind_and_target(request);
+    let target: String = target.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect();
+    dir.join("logs").join(format!("{}-{}-{}.log", id, kind, target))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stable_port_is_deterministic_and_in_range() {
+        for target in ["yl-development", "dev-postgres", "partner/partner-postgres", ""] {
+            let port = stable_port(target);
+            assert_eq!(port, stable_port(target));
+            assert!((PORT_RANGE.0..PORT_RANGE.1).contains(&port), "{} got {}", target, port);
+        }
+        assert_ne!(stable_port("yl-development"), stable_port("yl-production"));
+    }
+
+    #[test]
+    fn free_port_takes_the_preferred_port_when_free() {
+        assert_eq!(free_port(&Config::default(), &BTreeMap::new(), 45000, &|_| false).unwrap(), 45000);
+    }
+
+    #[test]
+    fn free_port_skips_busy_assigned_and_pinned_ports() {
+        let mut config = Config::default();
+        config.proxy.ports.insert("pinned-db".to_string(), 45002);
+        let assigned = BTreeMap::from([("other-app".to_string(), 45001)]);
+        let port = free_port(&config, &assigned, 45000, &|port| port == 45000 || port == 45003).unwrap();
+        assert_eq!(port, 45004);
+    }
+
+    #[test]
+    fn free_port_wraps_around_the_range() {
+        let last = PORT_RANGE.1 - 1;
+        assert_eq!(free_port(&Config::default(), &BTreeMap::new(), last, &|port| port == last).unwrap(), PORT_RANGE.0);
+    }
+
+    #[test]
+    fn free_port_clamps_a_preferred_port_outside_the_range() {
+        assert_eq!(free_port(&Config::default(), &BTreeMap::new(), 5432, &|_| false).unwrap(), PORT_RANGE.0);
+        assert_eq!(free_port(&Config::default(), &BTreeMap::new(), 65000, &|_| false).unwrap(), PORT_RANGE.1 - 1);
+    }
+
+    #[test]
+    fn free_port_fails_when_every_port_is_taken() {
+        assert!(free_port(&Config::default(), &BTreeMap::new(), 45000, &|_| true).is_err());
+    }
+}
@@ -0,0 +1,121 @@
+use crate::error::ThError;
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio::time::{Duration, Instant};
+
+/// First retry delay; doubles up to `MAX_DELAY`
+const FIRST_DELAY: Duration = Duration::from_millis(50);
+const MAX_DELAY: Duration = Duration::from_secs(1);
+
+/// Exponential backoff that never sleeps past a deadline
+pub struct Backoff {
+    delay: Duration,
+    deadline: Instant,
+}
+
+impl Backoff {
+    pub fn new(timeout: Duration) -> Self {
+        Self { delay: FIRST_DELAY, deadline: Instant::now() + timeout }
+    }
+
+    /// Sleep before the next attempt; `false` once the deadline has passed
+    pub async fn wait(&mut self) -> bool {
+        let now = Instant::now();
+        if now >= self.deadline {
+            return false;
+        }
+        tokio::time::sleep(self.delay.min(self.deadline - now)).await;
+        self.delay = (self.delay * 2).min(MAX_DELAY);
+        true
+    }
+
+    pub fn remaining(&self) -> Duration {
+        self.deadline.saturating_duration_since(Instant::now())
+    }
+}
+
+/// Whether something accepts connections on a local port
+pub async fn probe(port: u16, timeout: Duration) -> bool {
+    let connect = tokio::net::TcpStream::connect(("127.0.0.1", port));
+    matches!(tokio::time::timeout(timeout, connect).await, Ok(Ok(_)))
+}
+
+/// Wait until `port` accepts connections. `running` is asked between attempts so a
+/// proxy that dies fails fast instead of running out the clock.
+pub async fn wait_for_port(port: u16, timeout: Duration, running: impl Fn() -> bool) -> Result<()> {
+    let mut backoff = Backoff::new(timeout);
+    loop {
+        if probe(port, backoff.remaining().min(MAX_DELAY)).await {
+            return Ok(());
+        }
+        if !running() {
+            return Err(ThError::ProxyExited(format!("nothing is listening on port {}", port)).into());
+        }
+        if !backoff.wait().await {
+            return Err(ThError::PortNeverOpened(port).into());
+        }
+    }
+}
+
+/// A proxy's output, read line by line as it is written to its log
+pub struct OutputStream {
+    path: PathBuf,
+    offset: u64,
+    partial: String,
+    lines: Vec<String>,
+}
+
+impl OutputStream {
+    pub fn new(path: &Path) -> Self {
+        Self { path: path.to_path_buf(), offset: 0, partial: String::new(), lines: Vec::new() }
+    }
+
+    /// Every complete line read so far
+    pub fn lines(&self) -> &[String] {
+        &self.lines
+    }
+
+    /// Read whatever has been written since the last call
+    async fn read_new(&mut self) -> Result<()> {
+        let Ok(mut file) = tokio::fs::File::open(&self.path).await else {
+            return Ok(());
+        };
+        file.seek(std::io::SeekFrom::Start(self.offset)).await?;
+        let mut bytes = Vec::new();
+        self.offset += file.read_to_end(&mut bytes).await? as u64;
+
+        self.partial.push_str(&String::from_utf8_lossy(&bytes));
+        while let Some(end) = self.partial.find('\n') {
+            let line: String = self.partial.drain(..=end).collect();
+            self.lines.push(line.trim_end_matches(['\r', '\n']).to_string());
+        }
+        Ok(())
+    }
+
+    /// Wait until `ready` accepts the lines read so far
+    pub async fn wait_until(
+        &mut self,
+        what: &str,
+        timeout: Duration,
+        running: impl Fn() -> bool,
+        ready: impl Fn(&[String]) -> bool,
+    ) -> Result<()> {
+        let mut backoff = Backoff::new(timeout);
+        loop {
+            self.read_new().await?;
+            if ready(&self.lines) {
+                return Ok(());
+            }
+            if !running() {
+                // Whatever it managed to print usually says why
+                self.read_new().await?;
+                let output = self.lines.iter().map(|l| l.trim()).filter(|l| !l.is_empty()).collect::<Vec<_>>().join(" ");
+                return Err(ThError::ProxyExited(if output.is_empty() { "no output".to_string() } else { output }).into());
+            }
+            if !backoff.wait().await {
+                return Err(ThError::Timeout(format!("{} after {}s", what, timeout.as_secs())).into());
+            }
+        }
+    }
+}
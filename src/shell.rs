@@ -1,8 +1,106 @@
+use crate::config::edit::write_atomic;
+use crate::config::Config;
 use crate::error::ThError;
 use anyhow::Result;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use tokio::fs;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+/// Markers around the one part of an rc file th writes
+const BLOCK_START: &str = "# >>> th >>>";
+const BLOCK_END: &str = "# <<< th <<<";
+
+/// Syntax for commands that change a shell's environment, as printed by `th aws env`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+    Powershell,
+    /// Windows `cmd.exe`, for `th.bat`
+    Cmd,
+    Json,
+}
+
+impl Shell {
+    /// From `$SHELL`; PowerShell on Windows
+    pub fn detect() -> Self {
+        let shell = std::env::var("SHELL").unwrap_or_default();
+        if shell.contains("zsh") {
+            Shell::Zsh
+        } else if shell.contains("fish") {
+            Shell::Fish
+        } else if shell.is_empty() && cfg!(windows) {
+            Shell::Powershell
+        } else {
+            Shell::Bash
+        }
+    }
+
+    /// Commands setting `vars`, one per line
+    pub fn set_vars(self, vars: &[(String, String)]) -> String {
+        if self == Shell::Json {
+            let set: serde_json::Map<String, serde_json::Value> = vars.iter()
+                .map(|(key, value)| (key.clone(), value.clone().into()))
+                .collect();
+            return serde_json::json!({ "set": set, "unset": [] }).to_string();
+        }
+        vars.iter()
+            .map(|(key, value)| match self {
+                Shell::Bash | Shell::Zsh => format!("export {}='{}'", key, value.replace('\'', "'\\''")),
+                Shell::Fish => format!("set -gx {} '{}'", key, value.replace('\\', "\\\\").replace('\'', "\\'")),
+                Shell::Powershell => format!("$Env:{} = '{}'", key, value.replace('\'', "''")),
+                Shell::Cmd => format!("set \"{}={}\"", key, value.replace('%', "%%")),
+                Shell::Json => unreachable!(),
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Commands removing `keys`, one per line
+    pub fn unset_vars(self, keys: &[&str]) -> String {
+        if self == Shell::Json {
+            return serde_json::json!({ "set": {}, "unset": keys }).to_string();
+        }
+        keys.iter()
+            .map(|key| match self {
+                Shell::Bash | Shell::Zsh => format!("unset {}", key),
+                Shell::Fish => format!("set -e {}", key),
+                Shell::Powershell => format!("Remove-Item Env:{} -ErrorAction SilentlyContinue", key),
+                Shell::Cmd => format!("set \"{}=\"", key),
+                Shell::Json => unreachable!(),
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// Set by the `th shell-init` wrapper: a file it evaluates in the calling shell once th exits
+pub const ENV_FILE_VAR: &str = "TH_ENV_FILE";
+/// The wrapper's shell, which decides the syntax written to `TH_ENV_FILE`
+pub const ENV_SHELL_VAR: &str = "TH_ENV_SHELL";
+
+/// Ask the `th shell-init` wrapper to unset and then set variables in the shell th was
+/// run from. Returns false when th wasn't run through the wrapper.
+pub fn request_env(set: &[(String, String)], unset: &[&str]) -> Result<bool> {
+    let Some(path) = std::env::var_os(ENV_FILE_VAR).filter(|path| !path.is_empty()) else {
+        return Ok(false);
+    };
+    let shell = std::env::var(ENV_SHELL_VAR).ok()
+        .and_then(|name| <Shell as clap::ValueEnum>::from_str(&name, true).ok())
+        .unwrap_or_else(Shell::detect);
+
+    // The wrapper creates the file; one that's gone belongs to a shell that has moved on
+    let Ok(mut file) = std::fs::OpenOptions::new().append(true).open(&path) else {
+        return Ok(false);
+    };
+    use std::io::Write;
+    for lines in [shell.unset_vars(unset), shell.set_vars(set)] {
+        if !lines.is_empty() {
+            writeln!(file, "{}", lines)?;
+        }
+    }
+    Ok(true)
+}
 
 /// Shell integration for managing environment variables and profiles
 pub struct ShellIntegration {
@@ -19,7 +117,7 @@ enum ShellType {
 }
 
 impl ShellIntegration {
-    pub async fn new() -> Result<Self> {
+    pub fn new() -> Result<Self> {
         let shell_type = Self::detect_shell();
         let profile_path = Self::get_profile_path(&shell_type)?;
         
@@ -58,75 +156,56 @@ impl ShellIntegration {
         Ok(home.join(profile_name))
     }
 
-    /// Source AWS credentials in shell profile
-    pub async fn source_aws_credentials(&self, credentials_file: &str) -> Result<()> {
-        let source_line = match self.shell_type {
-            ShellType::Fish => format!("source {}", credentials_file),
-            _ => format!("source {}", credentials_file),
-        };
-
-        self.add_to_profile(&source_line).await
+    /// The rc file new shells read
+    pub fn profile_path(&self) -> &Path {
+        &self.profile_path
     }
 
-    /// Add content to shell profile
-    async fn add_to_profile(&self, content: &str) -> Result<()> {
-        // Read existing profile
-        let mut existing_content = String::new();
-        if self.profile_path.exists() {
-            let mut file = fs::File::open(&self.profile_path).await?;
-            file.read_to_string(&mut existing_content).await?;
-        }
-
-        // Check if content already exists
-        if existing_content.contains(content) {
-            return Ok(());
-        }
-
-        // Append new content
-        let mut file = fs::OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(&self.profile_path)
-            .await?;
+    /// What th last wrote between the markers in the profile
+    pub fn managed_block(&self) -> Option<String> {
+        let content = std::fs::read_to_string(&self.profile_path).ok()?;
+        let block: Vec<&str> = content.lines()
+            .skip_while(|line| line.trim() != BLOCK_START)
+            .skip(1)
+            .take_while(|line| line.trim() != BLOCK_END)
+            .collect();
+        Some(block.join("\n")).filter(|block| !block.is_empty())
+    }
 
-        file.write_all(format!("\n# Added by th\n{}\n", content).as_bytes()).await?;
-        file.sync_all().await?;
+    /// Have new shells source an AWS credentials file, through th's block in the profile
+    pub fn source_aws_credentials(&self, config: &Config, credentials_file: &str) -> Result<()> {
+        self.write_managed_block(config, Some(&format!("source {}", credentials_file)))
+    }
 
-        Ok(())
+    /// Remove th's block from the profile
+    pub fn cleanup_profile(&self, config: &Config) -> Result<()> {
+        self.write_managed_block(config, None)
     }
 
-    /// Remove th-related content from shell profile
-    pub async fn cleanup_profile(&self) -> Result<()> {
-        if !self.profile_path.exists() {
+    /// Replace th's block with `content`, or drop it. The rest of the file is left as it
+    /// was, bar the bare `source /tmp/tsh_proxy_` lines older versions wrote. The previous
+    /// file is kept as `<profile>.th.bak`. Profiles that don't exist aren't created.
+    fn write_managed_block(&self, config: &Config, content: Option<&str>) -> Result<()> {
+        if !config.shell.manage_rc {
             return Ok(());
         }
+        // Write through a symlink, e.g. into a dotfiles repo, rather than replacing it
+        let path = std::fs::canonicalize(&self.profile_path).unwrap_or_else(|_| self.profile_path.clone());
+        let Ok(existing) = std::fs::read_to_string(&path) else {
+            return Ok(());
+        };
 
-        let mut content = String::new();
-        let mut file = fs::File::open(&self.profile_path).await?;
-        file.read_to_string(&mut content).await?;
-        drop(file);
-
-        // Remove lines related to th
-        let cleaned_content: String = content
-            .lines()
-            .filter(|line| !self.is_th_related_line(line))
-            .map(|line| format!("{}\n", line))
-            .collect();
-
-        // Write cleaned content back
-        let mut file = fs::File::create(&self.profile_path).await?;
-        file.write_all(cleaned_content.as_bytes()).await?;
-        file.sync_all().await?;
-
-        Ok(())
-    }
+        let updated = replace_block(&existing, content).ok_or_else(|| ThError::Shell(format!(
+            "'{}' without a matching '{}' in {} - fix it by hand", BLOCK_START, BLOCK_END, path.display()
+        )))?;
+        if updated == existing {
+            return Ok(());
+        }
 
-    /// Check if a line is related to th
-    fn is_th_related_line(&self, line: &str) -> bool {
-        line.contains("# Added by th") ||
-        line.contains("yl_aws_credentials") ||
-        line.contains("TH_AWS_") ||
-        line.contains("export AWS_") && line.contains("teleport")
+        let mut backup = path.clone().into_os_string();
+        backup.push(".th.bak");
+        std::fs::copy(&path, &backup)?;
+        write_atomic(&path, &updated)
     }
 
     /// Clean up temporary credential files
@@ -182,16 +261,6 @@ impl ShellIntegration {
         Ok(env_vars)
     }
 
-    /// Set environment variable in current shell session
-    pub async fn set_env_var(&self, key: &str, value: &str) -> Result<()> {
-        let export_cmd = match self.shell_type {
-            ShellType::Fish => format!("set -x {} {}", key, value),
-            _ => format!("export {}={}", key, value),
-        };
-
-        self.add_to_profile(&export_cmd).await
-    }
-
     /// Unset environment variable
     pub async fn unset_env_var(&self, key: &str) -> Result<()> {
         let unset_cmd = match self.shell_type {
@@ -212,4 +281,162 @@ impl ShellIntegration {
             _ => false,
         }
     }
-}
\ No newline at end of file
+}
+
+/// `content` with th's block replaced by `block`, or removed, or appended when there was
+/// none; `None` when the block is never closed
+fn replace_block(content: &str, block: Option<&str>) -> Option<String> {
+    let mut lines = Vec::new();
+    let mut position = None;
+    let mut inside = false;
+    for line in content.lines() {
+        match line.trim() {
+            BLOCK_START if !inside => {
+                inside = true;
+                position.get_or_insert(lines.len());
+            }
+            BLOCK_END if inside => inside = false,
+            _ if inside => {}
+            trimmed if trimmed.starts_with("source /tmp/tsh_proxy_") => {}
+            _ => lines.push(line),
+        }
+    }
+    if inside {
+        return None;
+    }
+
+    if let Some(block) = block {
+        let mut new = vec![BLOCK_START];
+        new.extend(block.lines());
+        new.push(BLOCK_END);
+        match position {
+            Some(at) => {
+                lines.splice(at..at, new);
+            }
+            None => {
+                if lines.last().is_some_and(|line| !line.trim().is_empty()) {
+                    lines.push("");
+                }
+                lines.extend(new);
+            }
+        }
+    } else if position == Some(lines.len()) && lines.last().is_some_and(|line| line.trim().is_empty()) {
+        // The blank line th put before a block at the end goes with it
+        lines.pop();
+    }
+
+    let mut updated = lines.join("\n");
+    if content.ends_with('\n') || (block.is_some() && position.is_none()) {
+        updated.push('\n');
+    }
+    Some(updated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replace_block_replaces_in_place() {
+        let content = "export A=1\n# >>> th >>>\nsource /tmp/tsh_proxy_old.log\n# <<< th <<<\nexport B=2\n";
+        assert_eq!(
+            replace_block(content, Some("source /tmp/tsh_proxy_new.log")).unwrap(),
+            "export A=1\n# >>> th >>>\nsource /tmp/tsh_proxy_new.log\n# <<< th <<<\nexport B=2\n"
+        );
+    }
+
+    #[test]
+    fn replace_block_refuses_an_unterminated_block() {
+        let content = "export A=1\n# >>> th >>>\nsource /tmp/tsh_proxy_old.log\nexport B=2\n";
+        assert_eq!(replace_block(content, Some("source /tmp/tsh_proxy_new.log")), None);
+        assert_eq!(replace_block(content, None), None);
+    }
+
+    #[test]
+    fn replace_block_appends_after_a_missing_trailing_newline() {
+        assert_eq!(
+            replace_block("export A=1", Some("source /tmp/tsh_proxy_new.log")).unwrap(),
+            "export A=1\n\n# >>> th >>>\nsource /tmp/tsh_proxy_new.log\n# <<< th <<<\n"
+        );
+        // Nothing is added to a file that had no newline at the end
+        assert_eq!(
+            replace_block("# >>> th >>>\nold\n# <<< th <<<", Some("new")).unwrap(),
+            "# >>> th >>>\nnew\n# <<< th <<<"
+        );
+    }
+
+    #[test]
+    fn replace_block_drops_legacy_source_lines() {
+        let content = "export A=1\nsource /tmp/tsh_proxy_yl-development.log\nexport B=2\n";
+        assert_eq!(
+            replace_block(content, Some("source /tmp/tsh_proxy_new.log")).unwrap(),
+            "export A=1\nexport B=2\n\n# >>> th >>>\nsource /tmp/tsh_proxy_new.log\n# <<< th <<<\n"
+        );
+        assert_eq!(replace_block(content, None).unwrap(), "export A=1\nexport B=2\n");
+    }
+
+    #[test]
+    fn replace_block_removes_the_block_and_its_blank_line() {
+        let content = "export A=1\n\n# >>> th >>>\nsource /tmp/tsh_proxy_old.log\n# <<< th <<<\n";
+        assert_eq!(replace_block(content, None).unwrap(), "export A=1\n");
+    }
+
+    /// A value with every character the shells' quoting has to handle
+    fn tricky() -> Vec<(String, String)> {
+        vec![("SECRET".to_string(), r"it's\50%".to_string())]
+    }
+
+    #[test]
+    fn set_vars_quotes_for_posix_shells() {
+        assert_eq!(Shell::Bash.set_vars(&tricky()), r"export SECRET='it'\''s\50%'");
+        assert_eq!(Shell::Zsh.set_vars(&tricky()), Shell::Bash.set_vars(&tricky()));
+    }
+
+    #[test]
+    fn set_vars_quotes_for_fish() {
+        // Inside fish's single quotes only \' and \\ are escapes
+        assert_eq!(Shell::Fish.set_vars(&tricky()), r"set -gx SECRET 'it\'s\\50%'");
+    }
+
+    #[test]
+    fn set_vars_quotes_for_powershell() {
+        // Backslashes are literal in single quotes; a quote is doubled
+        assert_eq!(Shell::Powershell.set_vars(&tricky()), r"$Env:SECRET = 'it''s\50%'");
+    }
+
+    #[test]
+    fn set_vars_quotes_for_cmd() {
+        // A batch file expands % even inside a quoted `set`
+        assert_eq!(Shell::Cmd.set_vars(&tricky()), r#"set "SECRET=it's\50%%""#);
+    }
+
+    #[test]
+    fn set_vars_as_json_keeps_the_value() {
+        let json: serde_json::Value = serde_json::from_str(&Shell::Json.set_vars(&tricky())).unwrap();
+        assert_eq!(json["set"]["SECRET"], r"it's\50%");
+    }
+
+    #[test]
+    fn unset_vars_per_shell() {
+        let keys = ["AWS_ACCESS_KEY_ID", "ACCOUNT"];
+        assert_eq!(Shell::Bash.unset_vars(&keys), "unset AWS_ACCESS_KEY_ID\nunset ACCOUNT");
+        assert_eq!(Shell::Fish.unset_vars(&keys), "set -e AWS_ACCESS_KEY_ID\nset -e ACCOUNT");
+        assert_eq!(
+            Shell::Powershell.unset_vars(&keys),
+            "Remove-Item Env:AWS_ACCESS_KEY_ID -ErrorAction SilentlyContinue\nRemove-Item Env:ACCOUNT -ErrorAction SilentlyContinue"
+        );
+        assert_eq!(Shell::Cmd.unset_vars(&keys), "set \"AWS_ACCESS_KEY_ID=\"\nset \"ACCOUNT=\"");
+        assert_eq!(Shell::Json.unset_vars(&keys), r#"{"set":{},"unset":["AWS_ACCESS_KEY_ID","ACCOUNT"]}"#);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn set_vars_round_trips_through_bash() {
+        let output = std::process::Command::new("bash")
+            .arg("-c")
+            .arg(format!("{}\nprintf %s \"$SECRET\"", Shell::Bash.set_vars(&tricky())))
+            .output()
+            .unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout), r"it's\50%");
+    }
+}
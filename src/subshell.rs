@@ -0,0 +1,140 @@
+use crate::commands::aws::SESSION_VARS;
+use crate::config::Config;
+use crate::display::print_warning;
+use crate::process;
+use crate::proxies;
+use crate::shell::{Shell, ENV_FILE_VAR, ENV_SHELL_VAR};
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+use std::process::{ExitStatus, Stdio};
+
+/// Set in a subshell to the environment it was started for
+pub const ENV_MARKER: &str = "TH_ENV";
+
+/// Run the user's shell with `vars` and `TH_ENV` set, and `(th:<env>)` in front of its
+/// prompt, until it exits
+pub async fn run(config: &Config, env: &str, vars: Vec<(String, String)>) -> Result<ExitStatus> {
+    if let Ok(outer) = std::env::var(ENV_MARKER) {
+        print_warning(&format!("Already in a th shell for {} - starting another one inside it", outer));
+    }
+
+    let program = std::env::var("SHELL").ok()
+        .filter(|shell| !shell.is_empty())
+        .unwrap_or_else(|| if cfg!(windows) { "powershell".to_string() } else { "/bin/sh".to_string() });
+    let name = Path::new(&program).file_stem().and_then(|name| name.to_str()).unwrap_or("").to_lowercase();
+
+    // Startup files that run the user's own and then add the prompt prefix
+    let dir = tempfile::Builder::new().prefix("shell-").tempdir_in(proxies::ensure_runtime_dir(config)?)?;
+    let mut command = tokio::process::Command::new(&program);
+    match name.as_str() {
+        "bash" => {
+            let rc = dir.path().join("bashrc");
+            let script = startup_script(Shell::Bash, "[ -f ~/.bashrc ] && . ~/.bashrc", &vars, "PS1=\"(th:$TH_ENV) $PS1\"");
+            std::fs::write(&rc, script)?;
+            command.arg("--rcfile").arg(&rc).arg("-i");
+        }
+        "zsh" => {
+            let original = std::env::var_os("ZDOTDIR");
+            let home = original.clone().map(PathBuf::from).or_else(dirs::home_dir).unwrap_or_default();
+            let source = |file: &str| {
+                let path = home.join(file).display().to_string().replace('\'', r"'\''");
+                format!("[ -f '{0}' ] && . '{0}'", path)
+            };
+            std::fs::write(dir.path().join(".zshenv"), source(".zshenv") + "\n")?;
+            let restore = match &original {
+                Some(zdotdir) => format!("ZDOTDIR='{}'", zdotdir.to_string_lossy().replace('\'', r"'\''")),
+                None => "unset ZDOTDIR".to_string(),
+            };
+            let user_rc = format!("{}\n{}", source(".zshrc"), restore);
+            let script = startup_script(Shell::Zsh, &user_rc, &vars, "PROMPT=\"(th:$TH_ENV) $PROMPT\"");
+            std::fs::write(dir.path().join(".zshrc"), script)?;
+            command.env("ZDOTDIR", dir.path());
+        }
+        // Both read their own startup files before running this; a file keeps the
+        // credentials off the command line
+        "fish" => {
+            let init = dir.path().join("init.fish");
+            let prompt = "functions -c fish_prompt __th_fish_prompt; function fish_prompt; printf '(th:%s) ' $TH_ENV; __th_fish_prompt; end";
+            std::fs::write(&init, startup_script(Shell::Fish, "", &vars, prompt))?;
+            command.arg("--init-command").arg(format!("source '{}'", init.display().to_string().replace('\\', r"\\").replace('\'', r"\'")));
+        }
+        "pwsh" | "powershell" => {
+            let init = dir.path().join("init.ps1");
+            let prompt = "$__thPrompt = $function:prompt; function global:prompt { \"(th:$Env:TH_ENV) \" + (& $__thPrompt) }";
+            std::fs::write(&init, startup_script(Shell::Powershell, "", &vars, prompt))?;
+            // Read rather than run, which the execution policy may not allow
+            let load = format!("Get-Content -Raw '{}' | Invoke-Expression", init.display().to_string().replace('\'', "''"));
+            command.args(["-NoExit", "-Command"]).arg(load);
+        }
+        // Anything else still gets TH_ENV for its own prompt
+        _ => {}
+    }
+
+    // Nothing from the shell th was run from leaks in: not its credentials, and not its
+    // shell-init side channel
+    for key in SESSION_VARS.iter().chain(&[ENV_FILE_VAR, ENV_SHELL_VAR]) {
+        command.env_remove(key);
+    }
+    let mut child = command
+        .envs(vars)
+        .env(ENV_MARKER, env)
+        .stdin(Stdio::inherit())
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .spawn()?;
+    let status = process::wait_in_foreground(&mut child).await?;
+
+    drop(dir);
+    Ok(status)
+}
+
+/// Startup commands that run `user_rc`, then put `vars` back over whatever it set, then
+/// `prompt`. An rc with th's block sources the default account's credentials, which must
+/// not win over the ones the subshell is for.
+fn startup_script(shell: Shell, user_rc: &str, vars: &[(String, String)], prompt: &str) -> String {
+    let lines = [user_rc.to_string(), shell.unset_vars(SESSION_VARS), shell.set_vars(vars), prompt.to_string()];
+    let mut script = lines.iter().filter(|line| !line.is_empty()).cloned().collect::<Vec<_>>().join("\n");
+    script.push('\n');
+    script
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars() -> Vec<(String, String)> {
+        vec![
+            ("AWS_ACCESS_KEY_ID".to_string(), "prod-key".to_string()),
+            ("ACCOUNT".to_string(), "yl-production".to_string()),
+        ]
+    }
+
+    #[test]
+    fn startup_script_sets_vars_after_the_user_rc() {
+        let script = startup_script(Shell::Bash, ". ~/.bashrc", &vars(), "PS1=x");
+        let position = |needle: &str| script.find(needle).unwrap_or_else(|| panic!("{} missing from {}", needle, script));
+        assert!(position(". ~/.bashrc") < position("unset AWS_SESSION_TOKEN"));
+        assert!(position("unset AWS_SESSION_TOKEN") < position("export AWS_ACCESS_KEY_ID='prod-key'"));
+        assert!(position("export ACCOUNT='yl-production'") < position("PS1=x"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn startup_script_wins_over_th_block_in_user_rc() {
+        let dir = tempfile::tempdir().unwrap();
+        // What th's managed block sources for the default account
+        let credentials = dir.path().join("tsh_proxy_default.log");
+        std::fs::write(&credentials, "export AWS_ACCESS_KEY_ID=default-key\nexport AWS_SESSION_TOKEN=default-token\nexport ACCOUNT=yl-default\n").unwrap();
+        let user_rc = dir.path().join("bashrc");
+        std::fs::write(&user_rc, format!("# >>> th >>>\nsource {}\n# <<< th <<<\n", credentials.display())).unwrap();
+
+        let rc = dir.path().join("th-bashrc");
+        std::fs::write(&rc, startup_script(Shell::Bash, &format!(". '{}'", user_rc.display()), &vars(), "PS1=x")).unwrap();
+        let output = std::process::Command::new("bash")
+            .arg("-c")
+            .arg(format!(". '{}'; echo \"$AWS_ACCESS_KEY_ID ${{AWS_SESSION_TOKEN:-none}} $ACCOUNT\"", rc.display()))
+            .output()
+            .unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "prod-key none yl-production");
+    }
+}
@@ -1,29 +1,107 @@
+use crate::backend::{self, ProxyRequest, TeleportBackend};
+use crate::config::edit::write_atomic;
 use crate::config::Config;
+use crate::models::resources::{self, split_target, App, Database, KubeCluster, TeleportCluster};
+use crate::models::status::TshStatus;
+use crate::display::format_duration;
 use crate::error::ThError;
-use crate::process::{execute_command, execute_command_silent, execute_command_json, wait_for_condition};
+use crate::process::ProcessOutput;
+use crate::proxies;
+use crate::tools::{self, Tool};
 use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Arc;
 
-#[derive(Debug, Deserialize, Serialize)]
+/// Certificate expiry per proxy as of th's last `tsh status`
+fn expiry_cache(config: &Config) -> PathBuf {
+    proxies::runtime_dir(config).join("expiry.json")
+}
+
+/// When the configured proxy's certificate expires, going by the last time th asked
+/// tsh - for `th prompt`, which must not run tsh itself
+pub fn cached_expiry(config: &Config) -> Option<DateTime<Utc>> {
+    let content = std::fs::read_to_string(expiry_cache(config)).ok()?;
+    let cache: BTreeMap<String, DateTime<Utc>> = serde_json::from_str(&content).ok()?;
+    cache.get(&config.teleport.proxy).copied()
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
 pub struct TeleportStatus {
     pub logged_in: bool,
     pub user: Option<String>,
     pub cluster: Option<String>,
-    pub expires: Option<String>,
+    pub expires: Option<DateTime<Utc>>,
+    pub roles: Vec<String>,
+    pub active_requests: Vec<String>,
+    pub logins: Vec<String>,
+    /// Everything tsh reported, including other profiles
+    pub tsh: TshStatus,
+}
+
+impl From<TshStatus> for TeleportStatus {
+    fn from(tsh: TshStatus) -> Self {
+        match tsh.active.clone() {
+            Some(active) => Self {
+                logged_in: active.is_valid(),
+                user: Some(active.username).filter(|u| !u.is_empty()),
+                cluster: Some(active.cluster).filter(|c| !c.is_empty()),
+                expires: active.valid_until,
+                roles: active.roles,
+                active_requests: active.active_requests,
+                logins: active.logins,
+                tsh,
+            },
+            None => Self {
+                tsh,
+                ..Self::default()
+            },
+        }
+    }
+}
+
+impl TeleportStatus {
+    /// Status for the tsh profile of `proxy`. If tsh's active profile is another
+    /// cluster we count as logged out, so the next `tsh login` switches to ours.
+    pub fn for_proxy(tsh: TshStatus, proxy: &str) -> Self {
+        let ours = tsh.active.as_ref().map(|active| active.is_for_proxy(proxy)).unwrap_or(false);
+        if ours {
+            Self::from(tsh)
+        } else {
+            Self { tsh, ..Self::default() }
+        }
+    }
+
+    pub fn has_role(&self, role: &str) -> bool {
+        self.roles.iter().any(|r| r == role)
+    }
+
+    /// Time left on the certificate; `None` when logged out or tsh didn't say
+    pub fn remaining(&self) -> Option<Duration> {
+        self.expires.filter(|_| self.logged_in).map(|expires| expires - Utc::now())
+    }
+
+    /// Logged in with a certificate that lasts at least `min` longer
+    pub fn valid_for(&self, min: Duration) -> bool {
+        self.logged_in && self.remaining().map(|left| left >= min).unwrap_or(true)
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct KubernetesCluster {
     pub name: String,
+    pub labels: BTreeMap<String, String>,
     pub accessible: bool,
+    /// Leaf cluster it lives in; `None` for the root cluster
+    pub cluster: Option<String>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
-pub struct AwsApp {
-    pub name: String,
-    pub description: Option<String>,
-    pub uri: String,
+impl KubernetesCluster {
+    pub fn qualified_name(&self) -> String {
+        resources::qualify(self.cluster.as_deref(), &self.name)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -35,170 +113,166 @@ pub struct DatabaseInfo {
 #[derive(Clone)]
 pub struct TeleportClient {
     config: Config,
+    backend: Arc<dyn TeleportBackend>,
 }
 
 impl TeleportClient {
-    pub fn new(config: Config) -> Self {
-        Self { config }
+    /// Create a client on the backend selected for this run (see `backend::from_env`)
+    pub fn new(config: Config) -> Result<Self> {
+        let backend = backend::from_env(&config)?;
+        Ok(Self::with_backend(config, backend))
+    }
+
+    /// Create a client on an explicit backend
+    pub fn with_backend(config: Config, backend: Arc<dyn TeleportBackend>) -> Self {
+        Self { config, backend }
+    }
+
+    /// Configuration this client was built with
+    pub fn config(&self) -> &Config {
+        &self.config
     }
 
-    /// Validate that required tools are available
+    /// Name of the `[profiles.<name>]` in use, if any
+    pub fn profile(&self) -> Option<&str> {
+        self.config.profile.as_deref()
+    }
+
+    /// Validate that tsh is available and new enough
     pub async fn validate_tools(&self) -> Result<()> {
-        // Check if tsh is available
-        let tsh_check = tokio::process::Command::new(&self.config.paths.tsh)
-            .arg("version")
-            .output()
-            .await;
-
-        match tsh_check {
-            Ok(output) if output.status.success() => {
-                // tsh is available
-            }
-            _ => {
-                return Err(crate::error::ThError::NotFound(
-                    format!("tsh command not found at: {}", self.config.paths.tsh)
-                ).into());
+        let output = match self.backend.version().await {
+            Ok(output) => output,
+            Err(e) => {
+                // Prefer the "not found, set paths.tsh" explanation when that's the cause
+                tools::resolve(&self.config, Tool::Tsh)?;
+                return Err(e);
             }
-        }
+        };
+        tools::check_version(Tool::Tsh, &output)?;
 
         Ok(())
     }
 
-    /// Check if user is logged into Teleport
-    pub async fn is_logged_in(&self) -> Result<bool> {
-        let status = self.get_status().await?;
-        Ok(status.logged_in)
-    }
-
     /// Get current Teleport status
     pub async fn get_status(&self) -> Result<TeleportStatus> {
-        let output = execute_command(&self.config.paths.tsh, &["status"]).await;
-        
-        match output {
-            Ok(status_text) => {
-                // Check for different login indicators that tsh status might show
-                if status_text.contains("Logged in as:") || 
-                   status_text.contains("logged in as") ||
-                   status_text.contains("User:") {
-                    
-                    // Try multiple field names that tsh might use
-                    let user = self.extract_field(&status_text, "Logged in as:")
-                        .or_else(|| self.extract_field(&status_text, "User:"));
-                    let cluster = self.extract_field(&status_text, "Cluster:")
-                        .or_else(|| self.extract_field(&status_text, "Proxy:"));
-                    let expires = self.extract_field(&status_text, "Valid until:")
-                        .or_else(|| self.extract_field(&status_text, "Expires:"));
-                    
-                    Ok(TeleportStatus {
-                        logged_in: true,
-                        user,
-                        cluster,
-                        expires,
-                    })
-                } else if status_text.contains("Not logged in") || status_text.is_empty() {
-                    Ok(TeleportStatus {
-                        logged_in: false,
-                        user: None,
-                        cluster: None,
-                        expires: None,
-                    })
-                } else {
-                    // If we can't determine status clearly, assume not logged in
-                    Ok(TeleportStatus {
-                        logged_in: false,
-                        user: None,
-                        cluster: None,
-                        expires: None,
-                    })
-                }
-            }
-            Err(e) => {
-                // Log the error but don't fail - might just mean tsh not found or not logged in
-                eprintln!("Warning: Could not check Teleport status: {}", e);
-                Ok(TeleportStatus {
-                    logged_in: false,
-                    user: None,
-                    cluster: None,
-                    expires: None,
-                })
+        let status = TeleportStatus::for_proxy(self.tsh_status().await?, &self.config.teleport.proxy);
+        self.cache_expiry(&status);
+        Ok(status)
+    }
+
+    /// Remember the certificate expiry for `th prompt`; failing to is harmless
+    fn cache_expiry(&self, status: &TeleportStatus) {
+        // No expiry may just be a `tsh status` that failed, e.g. offline - only
+        // `logout` clears the cache. An expired certificate stays, so the prompt can say so.
+        let Some(expires) = status.expires else {
+            return;
+        };
+        let path = expiry_cache(&self.config);
+        let mut cache: BTreeMap<String, DateTime<Utc>> = std::fs::read_to_string(&path).ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+        if cache.insert(self.config.teleport.proxy.clone(), expires) != Some(expires) {
+            if let Ok(content) = serde_json::to_string(&cache) {
+                let _ = proxies::ensure_runtime_dir(&self.config).and_then(|_| write_atomic(&path, &content));
             }
         }
     }
 
-    /// Login to Teleport
-    pub async fn login(&self) -> Result<()> {
-        // Check if already logged in
-        if self.is_logged_in().await? {
-            return Ok(());
+    /// Full `tsh status --format=json`, including inactive profiles
+    pub async fn tsh_status(&self) -> Result<TshStatus> {
+        match self.backend.status().await {
+            Ok(json) => Ok(TshStatus::from_value(json)?),
+            // tsh status exits non-zero when logged out - treat any failure as not logged in
+            Err(_) => Ok(TshStatus::default()),
         }
+    }
 
-        // Start login process (this will be interactive)
-        let args = vec![
-            "login",
-            "--auth",
-            &self.config.teleport.auth_type,
-            "--proxy",
-            &self.config.teleport.proxy,
-        ];
-
-        // Use background execution for non-blocking login
-        let mut child = tokio::process::Command::new(&self.config.paths.tsh)
-            .args(&args)
-            .stdin(std::process::Stdio::inherit())
-            .stdout(std::process::Stdio::inherit())
-            .stderr(std::process::Stdio::inherit())
-            .spawn()?;
-
-        // Wait for the login process to complete
-        let status = child.wait().await?;
-        if !status.success() {
-            return Err(ThError::AuthFailed("Teleport login failed".to_string()).into());
+    /// `teleport.renew_before_minutes`
+    pub fn renew_before(&self) -> Duration {
+        Duration::minutes(self.config.teleport.renew_before_minutes as i64)
+    }
+
+    /// Login to Teleport unless the certificate lasts at least `min` longer
+    pub async fn login(&self, min: Duration) -> Result<TeleportStatus> {
+        let status = self.get_status().await?;
+        if status.valid_for(min) {
+            return Ok(status);
         }
 
-        // Wait for login to be fully established
-        wait_for_condition(
-            || {
-                // Use a blocking call in the closure
-                tokio::task::block_in_place(|| {
-                    let rt = tokio::runtime::Handle::current();
-                    rt.block_on(async {
-                        self.is_logged_in().await.unwrap_or(false)
-                    })
-                })
-            },
-            self.config.teleport.timeout_seconds,
-            500,
-        ).await?;
+        let status = self.renew(&status).await?;
+        if !status.valid_for(min) {
+            let left = status.remaining().map(|left| format_duration(left.num_seconds())).unwrap_or_default();
+            return Err(ThError::AuthFailed(format!(
+                "Teleport issued a certificate valid for {}, less than the {} asked for - your roles cap the session length",
+                left, format_duration(min.num_seconds())
+            )).into());
+        }
+        Ok(status)
+    }
 
-        Ok(())
+    /// Run `tsh login` and wait for a certificate newer than the one in `current`
+    pub async fn renew(&self, current: &TeleportStatus) -> Result<TeleportStatus> {
+        self.login_with_request(None).await?;
+
+        let previous = current.expires.filter(|_| current.logged_in);
+        let deadline = tokio::time::Instant::now() + tokio::time::Duration::from_secs(self.config.teleport.timeout_seconds);
+        loop {
+            let status = self.get_status().await?;
+            let renewed = match (previous, status.expires) {
+                (Some(previous), Some(expires)) => expires > previous,
+                _ => true,
+            };
+            if status.logged_in && renewed {
+                return Ok(status);
+            }
+            if tokio::time::Instant::now() >= deadline {
+                return Err(ThError::Timeout("Teleport login".to_string()).into());
+            }
+            tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+        }
+    }
+
+    /// Run `tsh login` against the configured proxy, optionally assuming an access request
+    pub async fn login_with_request(&self, request_id: Option<&str>) -> Result<()> {
+        self.backend.login(
+            &self.config.teleport.auth_type,
+            &self.config.teleport.proxy,
+            request_id,
+        ).await
     }
 
     /// Logout from Teleport
     pub async fn logout(&self) -> Result<()> {
-        execute_command_silent(&self.config.paths.tsh, &["logout"]).await?;
+        self.backend.logout().await?;
+        // tsh logout ends every profile's session
+        let _ = std::fs::remove_file(expiry_cache(&self.config));
         Ok(())
     }
 
+    /// The root cluster and its leaf (trusted) clusters. Older tsh or a missing
+    /// permission just means no leaves.
+    pub async fn list_clusters(&self) -> Result<Vec<TeleportCluster>> {
+        Ok(resources::parse_list(self.backend.list_clusters().await.unwrap_or_default()))
+    }
+
+    /// Online leaf cluster names
+    async fn leaf_clusters(&self) -> Vec<String> {
+        self.list_clusters().await
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|cluster| cluster.is_leaf() && cluster.is_online())
+            .map(|cluster| cluster.cluster_name)
+            .collect()
+    }
+
     /// List available Kubernetes clusters - exactly like bash version
     pub async fn list_kube_clusters(&self) -> Result<Vec<KubernetesCluster>> {
-        let json = execute_command_json(&self.config.paths.tsh, &["kube", "ls", "-f", "json"]).await?;
+        let clusters = self.list_kube_resources().await?;
         
-        let mut clusters = Vec::new();
-        let mut test_cluster: Option<String> = None;
-        
-        // First pass: collect all cluster names and find first prod cluster
-        if let Value::Array(items) = json {
-            for item in items {
-                if let Some(name) = item.get("kube_cluster_name").and_then(|n| n.as_str()) {
-                    clusters.push(name.to_string());
-                    
-                    // Find first prod cluster to test with
-                    if test_cluster.is_none() && name.contains("prod") {
-                        test_cluster = Some(name.to_string());
-                    }
-                }
-            }
-        }
+        // Find first prod cluster to test with
+        let test_cluster = clusters.iter()
+            .find(|cluster| cluster.name.contains("prod"))
+            .map(|cluster| cluster.qualified_name());
         
         // Test access with one prod cluster if we found one
         let prod_access_status = if let Some(ref test_cluster) = test_cluster {
@@ -207,205 +281,202 @@ impl TeleportClient {
             false
         };
         
-        // Second pass: apply status based on cluster type
-        let result: Vec<KubernetesCluster> = clusters.into_iter().map(|name| {
-            let accessible = if name.contains("prod") {
+        // Apply status based on cluster type
+        let result: Vec<KubernetesCluster> = clusters.into_iter().map(|cluster| {
+            let accessible = if cluster.name.contains("prod") {
                 prod_access_status
             } else {
                 true // Non-prod clusters are always accessible (n/a case)
             };
             
             KubernetesCluster {
-                name,
+                name: cluster.name,
+                labels: cluster.labels,
                 accessible,
+                cluster: cluster.cluster,
             }
         }).collect();
         
         Ok(result)
     }
 
+    /// List kube clusters with their labels, without checking access
+    pub async fn list_kube_resources(&self) -> Result<Vec<KubeCluster>> {
+        let mut clusters: Vec<KubeCluster> = resources::parse_list(self.backend.list_kube_clusters(None).await?);
+        for leaf in self.leaf_clusters().await {
+            // An unreachable leaf shouldn't hide everything else
+            let Ok(json) = self.backend.list_kube_clusters(Some(&leaf)).await else { continue };
+            clusters.extend(resources::parse_list::<KubeCluster>(json).into_iter().map(|mut cluster| {
+                cluster.cluster = Some(leaf.clone());
+                cluster
+            }));
+        }
+        Ok(clusters)
+    }
+
     /// Check prod cluster access - exactly like bash version
     pub async fn check_prod_cluster_access(&self, cluster_name: &str) -> Result<bool> {
         // Try to login to the prod cluster
-        let login_result = execute_command_silent(
-            &self.config.paths.tsh,
-            &["kube", "login", cluster_name],
-        ).await;
-        
-        if login_result.is_err() {
+        if self.kube_login(cluster_name).await.is_err() {
             return Ok(false);
         }
         
         // Test if we can create pods (write access)
-        let kubectl_result = execute_command_silent(
-            "kubectl",
-            &["auth", "can-i", "create", "pod"],
-        ).await;
-        
-        Ok(kubectl_result.unwrap_or(false))
+        Ok(self.backend.kube_can_i("create", "pod").await.unwrap_or(false))
     }
 
-    /// Login to a Kubernetes cluster
+    /// Context kubectl is pointed at, if any
+    pub async fn kube_current_context(&self) -> Option<String> {
+        self.backend.kube_current_context().await.ok().flatten()
+    }
+
+    /// Login to a Kubernetes cluster; `leaf/name` logs in through a leaf cluster
     pub async fn kube_login(&self, cluster_name: &str) -> Result<()> {
-        execute_command(&self.config.paths.tsh, &["kube", "login", cluster_name]).await?;
-        Ok(())
+        let (leaf, name) = split_target(cluster_name);
+        self.backend.kube_login(name, leaf).await
     }
 
     /// List available AWS applications
-    pub async fn list_aws_apps(&self) -> Result<Vec<AwsApp>> {
-        let json = execute_command_json(&self.config.paths.tsh, &["apps", "ls", "--format=json"]).await?;
-        
-        let mut apps = Vec::new();
-        
-        if let Value::Array(items) = json {
-            for item in items {
-                if let Some(name) = item.get("metadata").and_then(|m| m.get("name")).and_then(|n| n.as_str()) {
-                    let description = item.get("metadata")
-                        .and_then(|m| m.get("description"))
-                        .and_then(|d| d.as_str())
-                        .map(|s| s.to_string());
-                    
-                    let uri = item.get("spec")
-                        .and_then(|s| s.get("uri"))
-                        .and_then(|u| u.as_str())
-                        .unwrap_or("")
-                        .to_string();
-                    
-                    apps.push(AwsApp {
-                        name: name.to_string(),
-                        description,
-                        uri,
-                    });
-                }
-            }
+    pub async fn list_aws_apps(&self) -> Result<Vec<App>> {
+        let mut apps: Vec<App> = resources::parse_list(self.backend.list_apps(None).await?);
+        for leaf in self.leaf_clusters().await {
+            let Ok(json) = self.backend.list_apps(Some(&leaf)).await else { continue };
+            apps.extend(resources::parse_list::<App>(json).into_iter().map(|mut app| {
+                app.cluster = Some(leaf.clone());
+                app
+            }));
         }
-        
         Ok(apps)
     }
 
     /// Login to an AWS application
     /// Login to AWS app with specific role 
     pub async fn aws_login(&self, app_name: &str, role_name: &str) -> Result<()> {
-        execute_command(&self.config.paths.tsh, &["apps", "login", app_name, "--aws-role", role_name]).await?;
+        let (leaf, app_name) = split_target(app_name);
+        let output = self.backend.app_login(app_name, Some(role_name), leaf).await?;
+        if !output.success {
+            return Err(crate::error::ThError::Process(format!(
+                "Command 'tsh' failed: {}",
+                output.stderr
+            )).into());
+        }
         Ok(())
     }
     
     /// Login to AWS app without role (to discover available roles)
     pub async fn aws_login_discover_roles(&self, app_name: &str) -> Result<String> {
-        // This will fail but return the available roles in the error output
-        let result = crate::process::execute_command_with_output(&self.config.paths.tsh, &["apps", "login", app_name]).await;
-        
-        match result {
-            Ok(output) => Ok(output.stderr), // Should not happen, but return stderr if it does
-            Err(e) => {
-                // Extract the error message which contains available roles
-                Ok(e.to_string())
-            }
-        }
+        // This will fail but print the available roles - combine both streams like bash 2>&1
+        let (leaf, app_name) = split_target(app_name);
+        let output = self.backend.app_login(app_name, None, leaf).await?;
+        Ok(format!("{}{}", output.stdout, output.stderr))
     }
 
-    /// Logout from AWS applications
-    pub async fn aws_logout(&self) -> Result<()> {
-        execute_command_silent(&self.config.paths.tsh, &["apps", "logout"]).await?;
-        Ok(())
+    /// Logout from one AWS application, or all of them
+    pub async fn aws_logout(&self, app_name: Option<&str>) -> Result<()> {
+        match app_name.map(split_target) {
+            Some((leaf, app_name)) => self.backend.app_logout(Some(app_name), leaf).await,
+            None => self.backend.app_logout(None, None).await,
+        }
     }
 
     /// List available databases
-    pub async fn list_databases(&self) -> Result<Vec<Value>> {
-        let json = execute_command_json(&self.config.paths.tsh, &["db", "ls", "--format=json"]).await?;
-        
-        if let Value::Array(items) = json {
-            Ok(items)
-        } else {
-            Ok(Vec::new())
+    pub async fn list_databases(&self) -> Result<Vec<Database>> {
+        let mut databases: Vec<Database> = resources::parse_list(self.backend.list_databases(None).await?);
+        for leaf in self.leaf_clusters().await {
+            let Ok(json) = self.backend.list_databases(Some(&leaf)).await else { continue };
+            databases.extend(resources::parse_list::<Database>(json).into_iter().map(|mut db| {
+                db.cluster = Some(leaf.clone());
+                db
+            }));
         }
+        Ok(databases)
     }
 
     /// Login to a database
     pub async fn db_login(&self, db_name: &str) -> Result<()> {
-        execute_command(&self.config.paths.tsh, &["db", "login", db_name]).await?;
-        Ok(())
+        let (leaf, db_name) = split_target(db_name);
+        self.backend.db_login(db_name, None, None, leaf).await
     }
 
-    /// Get proxy information for a database
-    pub async fn get_db_proxy(&self, db_name: &str) -> Result<String> {
-        execute_command(&self.config.paths.tsh, &["proxy", "db", db_name]).await
+    /// Login to a database as a specific user
+    pub async fn db_login_as(&self, db_name: &str, db_user: &str, database: &str) -> Result<()> {
+        let (leaf, db_name) = split_target(db_name);
+        self.backend.db_login(db_name, Some(db_user), Some(database), leaf).await
     }
 
-    /// Helper to extract field from status text
-    fn extract_field(&self, text: &str, field: &str) -> Option<String> {
-        text.lines()
-            .find(|line| line.contains(field))
-            .and_then(|line| line.split(':').nth(1))
-            .map(|value| value.trim().to_string())
+    /// Open an interactive client session to a database
+    pub async fn db_connect(&self, db_name: &str, db_user: Option<&str>, database: Option<&str>) -> Result<()> {
+        let (leaf, db_name) = split_target(db_name);
+        self.backend.db_connect(db_name, db_user, database, leaf).await
     }
 
-    /// List RDS databases with access checking - exactly like bash check_rds_login
-    pub async fn list_rds_databases(&self) -> Result<Vec<DatabaseInfo>> {
-        let json = execute_command_json(&self.config.paths.tsh, &["db", "ls", "--format=json"]).await?;
-        
-        let mut databases = Vec::new();
-        
-        if let Value::Array(items) = json {
-            for item in items {
-                if let Some(name) = item.get("metadata").and_then(|m| m.get("name")).and_then(|n| n.as_str()) {
-                    // Check if it's an RDS database (has rds label or postgres/mysql protocol)
-                    let is_rds = item.get("metadata")
-                        .and_then(|m| m.get("labels"))
-                        .and_then(|l| l.get("db_type"))
-                        .and_then(|t| t.as_str())
-                        .map(|t| t == "rds")
-                        .unwrap_or(false);
-                    
-                    if is_rds {
-                        // For now, assume all RDS databases are accessible
-                        // This would need to be implemented based on actual access checking logic
-                        databases.push(DatabaseInfo {
-                            name: name.to_string(),
-                            accessible: true,
-                        });
-                    }
+    /// Raise an access request for `roles`
+    pub async fn request_create(&self, roles: &str, reason: &str, max_duration: Option<&str>) -> Result<ProcessOutput> {
+        self.backend.request_create(roles, reason, max_duration).await
+    }
+
+    /// Build the command that starts a `tsh proxy`
+    pub fn proxy_command(&self, request: &ProxyRequest) -> Result<std::process::Command> {
+        // A `leaf/name` app or database is proxied through its leaf cluster
+        match request {
+            ProxyRequest::Aws { app, port, leaf: None, endpoint_url } => {
+                if let (Some(leaf), name) = split_target(app) {
+                    return self.backend.proxy_command(&ProxyRequest::Aws {
+                        app: name.to_string(),
+                        port: *port,
+                        leaf: Some(leaf.to_string()),
+                        endpoint_url: *endpoint_url,
+                    });
+                }
+            }
+            ProxyRequest::Db { db, db_user, db_name, port, leaf: None } => {
+                if let (Some(leaf), name) = split_target(db) {
+                    return self.backend.proxy_command(&ProxyRequest::Db {
+                        db: name.to_string(),
+                        db_user: db_user.clone(),
+                        db_name: db_name.clone(),
+                        port: *port,
+                        leaf: Some(leaf.to_string()),
+                    });
                 }
             }
+            _ => {}
         }
+        self.backend.proxy_command(request)
+    }
+
+    /// List RDS databases with access checking - exactly like bash check_rds_login
+    pub async fn list_rds_databases(&self) -> Result<Vec<DatabaseInfo>> {
+        let databases = self.list_databases().await?
+            .into_iter()
+            .filter(|db| db.is_rds())
+            // For now, assume all RDS databases are accessible
+            // This would need to be implemented based on actual access checking logic
+            .map(|db| DatabaseInfo {
+                name: db.qualified_name(),
+                accessible: true,
+            })
+            .collect();
         
         Ok(databases)
     }
 
     /// List MongoDB databases with access checking - exactly like bash check_atlas_access
     pub async fn list_mongodb_databases(&self) -> Result<(Vec<String>, bool)> {
-        // Check if user has atlas access
-        let status_output = execute_command_json(&self.config.paths.tsh, &["status", "--format=json"]).await?;
+        // Check if user has atlas access - either an assumed request or a granted role
+        let status = self.get_status().await?;
+        let access_roles = self.config.roles.db("mongodb").access_roles;
         
-        let has_atlas_access = status_output.get("active_requests")
-            .and_then(|requests| requests.as_array())
-            .map(|arr| arr.iter().any(|req| 
-                req.get("id").and_then(|id| id.as_str()).map(|s| s.contains("atlas-can-read")).unwrap_or(false)
-            ))
-            .unwrap_or(false);
+        let has_atlas_access = access_roles.iter().any(|role| {
+            status.has_role(role) || status.active_requests.iter().any(|id| id.contains(role.as_str()))
+        });
         
         // Get MongoDB databases (filter out RDS)
-        let json = execute_command_json(&self.config.paths.tsh, &["db", "ls", "--format=json"]).await?;
-        
-        let mut databases = Vec::new();
-        
-        if let Value::Array(items) = json {
-            for item in items {
-                if let Some(name) = item.get("metadata").and_then(|m| m.get("name")).and_then(|n| n.as_str()) {
-                    // Check if it's NOT an RDS database
-                    let is_rds = item.get("metadata")
-                        .and_then(|m| m.get("labels"))
-                        .and_then(|l| l.get("db_type"))
-                        .and_then(|t| t.as_str())
-                        .map(|t| t == "rds")
-                        .unwrap_or(false);
-                    
-                    if !is_rds {
-                        databases.push(name.to_string());
-                    }
-                }
-            }
-        }
+        let databases = self.list_databases().await?
+            .into_iter()
+            .filter(|db| !db.is_rds())
+            .map(|db| db.qualified_name())
+            .collect();
         
         Ok((databases, has_atlas_access))
     }
@@ -0,0 +1,113 @@
+use crate::config::Config;
+use crate::error::ThError;
+use anyhow::Result;
+use regex::Regex;
+use semver::Version;
+use std::path::PathBuf;
+
+/// Oldest tsh whose JSON output (`status`, `apps ls`, `kube ls`, `db ls`) and
+/// `proxy db --tunnel` th understands
+pub const MIN_TSH_VERSION: &str = "12.0.0";
+
+/// External programs th runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tool {
+    Tsh,
+    Kubectl,
+    AwsCli,
+    /// Started by `tsh db connect`, so only ever looked up on PATH
+    Psql,
+    Mongosh,
+}
+
+impl Tool {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Tool::Tsh => "tsh",
+            Tool::Kubectl => "kubectl",
+            Tool::AwsCli => "aws",
+            Tool::Psql => "psql",
+            Tool::Mongosh => "mongosh",
+        }
+    }
+
+    /// Config key that overrides where the tool lives
+    pub fn config_key(&self) -> Option<&'static str> {
+        match self {
+            Tool::Tsh => Some("paths.tsh"),
+            Tool::Kubectl => Some("paths.kubectl"),
+            Tool::AwsCli => Some("paths.aws_cli"),
+            Tool::Psql | Tool::Mongosh => None,
+        }
+    }
+
+    fn version_args(&self) -> &'static [&'static str] {
+        match self {
+            Tool::Tsh => &["version"],
+            Tool::Kubectl => &["version", "--client"],
+            Tool::AwsCli | Tool::Psql | Tool::Mongosh => &["--version"],
+        }
+    }
+
+    fn min_version(&self) -> Option<Version> {
+        match self {
+            Tool::Tsh => Version::parse(MIN_TSH_VERSION).ok(),
+            _ => None,
+        }
+    }
+}
+
+/// The program th will run for `tool`, as configured - a bare name or a path
+pub fn program(config: &Config, tool: Tool) -> &str {
+    match tool {
+        Tool::Tsh => &config.paths.tsh,
+        Tool::Kubectl => &config.paths.kubectl,
+        Tool::AwsCli => &config.paths.aws_cli,
+        Tool::Psql | Tool::Mongosh => tool.name(),
+    }
+}
+
+/// Find the configured binary, or explain how to point th at it
+pub fn resolve(config: &Config, tool: Tool) -> Result<PathBuf> {
+    let program = program(config, tool);
+    which::which(program).map_err(|_| {
+        let hint = match tool.config_key() {
+            Some(key) => format!(" - install it or run `th config set {} /path/to/{}`", key, tool.name()),
+            None => " - install it and make sure it is on your PATH".to_string(),
+        };
+        ThError::NotFound(format!("{} not found at '{}'{}", tool.name(), program, hint)).into()
+    })
+}
+
+/// Resolve `tool` and make sure it is new enough, returning its path and version
+pub fn require(config: &Config, tool: Tool) -> Result<(PathBuf, Option<Version>)> {
+    let path = resolve(config, tool)?;
+    let output = std::process::Command::new(&path)
+        .args(tool.version_args())
+        .output()?;
+    let banner = format!("{}{}", String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr));
+    let version = check_version(tool, &banner)?;
+    Ok((path, version))
+}
+
+/// Parse the version out of `<tool> --version` output and compare it with the minimum
+pub fn check_version(tool: Tool, output: &str) -> Result<Option<Version>> {
+    let version = parse_version(output);
+    if let (Some(found), Some(min)) = (&version, tool.min_version()) {
+        if *found < min {
+            return Err(ThError::Config(format!(
+                "{} {} is too old - th needs {} {} or newer",
+                tool.name(), found, tool.name(), min
+            )).into());
+        }
+    }
+    Ok(version)
+}
+
+/// First `x.y[.z]` in a version banner, e.g. `Teleport v15.4.3 git:...` or `aws-cli/2.15.0 Python/...`
+pub fn parse_version(output: &str) -> Option<Version> {
+    let re = Regex::new(r"(\d+)\.(\d+)(?:\.(\d+))?").ok()?;
+    let caps = re.captures(output)?;
+    let part = |i: usize| caps.get(i).and_then(|m| m.as_str().parse::<u64>().ok()).unwrap_or(0);
+    Some(Version::new(part(1), part(2), part(3)))
+}
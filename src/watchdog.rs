@@ -0,0 +1,211 @@
+use crate::backend::ProxyRequest;
+use crate::commands::aws;
+use crate::config::Config;
+use crate::process::process_command_line;
+use crate::proxies::{self, ProxyEntry};
+use crate::readiness;
+use crate::shell;
+use crate::teleport::TeleportClient;
+use anyhow::Result;
+use chrono::{DateTime, Duration, Local, Utc};
+use std::collections::HashMap;
+use std::fs::File;
+use std::process::Stdio;
+
+/// Holds the PID of the running watchdog, inside `proxies::runtime_dir`
+const PID_FILE: &str = "watchdog.pid";
+/// A proxy gets this long to open its port before it is health-checked
+const STARTUP_GRACE_SECONDS: i64 = 20;
+/// Restarts in a row that may fail before the watchdog stops and forgets a proxy
+const MAX_FAILURES: u32 = 5;
+/// Longest wait before checking a failing proxy again
+const MAX_BACKOFF_SECONDS: u64 = 300;
+
+/// A proxy that has needed restarting since it last passed a check
+struct Failures {
+    count: u32,
+    /// It isn't checked again before this
+    retry_at: DateTime<Utc>,
+}
+
+/// PID of the running watchdog, if there is one
+pub fn running_pid(config: &Config) -> Option<u32> {
+    let pid_file = proxies::runtime_dir(config).join(PID_FILE);
+    let pid: u32 = std::fs::read_to_string(pid_file).ok()?.trim().parse().ok()?;
+    if pid == std::process::id() {
+        return Some(pid);
+    }
+    match process_command_line(pid) {
+        Some(command) if cfg!(windows) || command.contains("watch") => Some(pid),
+        _ => None,
+    }
+}
+
+/// Start `th proxy watch` in the background unless one is already running
+pub fn spawn_detached(config: &Config) -> Result<()> {
+    if running_pid(config).is_some() {
+        return Ok(());
+    }
+
+    let dir = proxies::ensure_runtime_dir(config)?;
+    let log = File::options().create(true).append(true).open(dir.join("logs").join("watchdog.log"))?;
+
+    let mut command = std::process::Command::new(std::env::current_exe()?);
+    command.args(["proxy", "watch"])
+        // It outlives the shell that started it, so it must not write to its shell-init side channel
+        .env_remove(shell::ENV_FILE_VAR)
+        .env_remove(shell::ENV_SHELL_VAR)
+        .stdin(Stdio::null())
+        .stdout(log.try_clone()?)
+        .stderr(log);
+    // Its own process group, so Ctrl-C in the terminal that started it doesn't stop it
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        command.process_group(0);
+    }
+    command.spawn()?;
+    Ok(())
+}
+
+/// Watch registered proxies until none are left: restart any whose process died or
+/// whose port stopped answering, and log in again when the Teleport session expires
+pub async fn run(client: &TeleportClient) -> Result<()> {
+    let config = client.config();
+    if running_pid(config).is_some() {
+        log("Another watchdog is already running");
+        return Ok(());
+    }
+    let dir = proxies::ensure_runtime_dir(config)?;
+    let pid_file = dir.join(PID_FILE);
+    std::fs::write(&pid_file, std::process::id().to_string())?;
+
+    let interval = config.proxy.watch_interval_seconds.max(1);
+    log(&format!("Watching proxies every {}s", interval));
+
+    let mut failing = HashMap::new();
+    let result = loop {
+        let entries = match proxies::load(config) {
+            Ok(entries) => entries,
+            Err(e) => break Err(e),
+        };
+        if entries.is_empty() {
+            log("No proxies left to watch");
+            break Ok(());
+        }
+
+        failing.retain(|id, _| entries.iter().any(|entry| entry.id == *id));
+        check(client, &entries, interval, &mut failing).await;
+        tokio::time::sleep(tokio::time::Duration::from_secs(interval)).await;
+    };
+
+    let _ = std::fs::remove_file(&pid_file);
+    result
+}
+
+async fn check(client: &TeleportClient, entries: &[ProxyEntry], interval: u64, failing: &mut HashMap<u32, Failures>) {
+    // Renew a session that has expired or will before the next check; every
+    // proxy then needs fresh app/database certificates
+    let status = client.get_status().await.unwrap_or_default();
+    let renew_by = Utc::now() + Duration::seconds(interval as i64 * 2);
+    let expiring = !status.logged_in || status.expires.map(|expires| expires <= renew_by).unwrap_or(false);
+    if expiring {
+        log("Teleport session expired or expiring - logging in again");
+        if let Err(e) = client.renew(&status).await {
+            log(&format!("Login failed, retrying next check: {}", e));
+            return;
+        }
+        for entry in entries {
+            if let Err(e) = reissue_certificates(client, entry).await {
+                log(&format!("Could not renew certificates for {} {}: {}", entry.kind(), entry.target(), e));
+            }
+            restart(client, entry, "certificates renewed").await;
+        }
+        return;
+    }
+
+    for entry in entries {
+        if failing.get(&entry.id).is_some_and(|failures| Utc::now() < failures.retry_at) {
+            continue;
+        }
+        if !entry.is_running() {
+            fail(client, entry, "process exited", interval, failing).await;
+            continue;
+        }
+        // Only a proxy that has stayed up past its grace period counts as recovered
+        if Utc::now() - entry.started_at <= Duration::seconds(STARTUP_GRACE_SECONDS) {
+            continue;
+        }
+        match entry.port() {
+            Some(port) if !readiness::probe(port, tokio::time::Duration::from_secs(2)).await => {
+                fail(client, entry, &format!("port {} not answering", port), interval, failing).await;
+            }
+            _ => {
+                failing.remove(&entry.id);
+            }
+        }
+    }
+}
+
+/// Restart a proxy that failed a check, waiting twice as long before each further
+/// check, and give up on it after `MAX_FAILURES` restarts in a row
+async fn fail(client: &TeleportClient, entry: &ProxyEntry, reason: &str, interval: u64, failing: &mut HashMap<u32, Failures>) {
+    let failures = failing.entry(entry.id).or_insert(Failures { count: 0, retry_at: Utc::now() });
+    failures.count += 1;
+    if failures.count > MAX_FAILURES {
+        failing.remove(&entry.id);
+        log(&format!(
+            "Giving up on proxy {} ({} {}) - {} after {} restarts in a row",
+            entry.id, entry.kind(), entry.target(), reason, MAX_FAILURES
+        ));
+        if let Err(e) = proxies::stop(client.config(), entry) {
+            log(&format!("Could not stop proxy {}: {}", entry.id, e));
+        }
+        return;
+    }
+
+    let backoff = interval.saturating_mul(1 << (failures.count - 1)).min(MAX_BACKOFF_SECONDS.max(interval));
+    failures.retry_at = Utc::now() + Duration::seconds(backoff as i64);
+    restart(client, entry, reason).await;
+    if failures.count > 1 {
+        log(&format!("Checking proxy {} again in {}s", entry.id, backoff));
+    }
+}
+
+/// App and database certificates are issued by `tsh apps login`/`tsh db login`
+async fn reissue_certificates(client: &TeleportClient, entry: &ProxyEntry) -> Result<()> {
+    match &entry.request {
+        ProxyRequest::Aws { app, .. } => match &entry.role {
+            Some(role) => client.aws_login(app, role).await,
+            None => Ok(()),
+        },
+        ProxyRequest::Db { db, db_user: Some(user), db_name: Some(name), .. } => client.db_login_as(db, user, name).await,
+        ProxyRequest::Db { db, .. } => client.db_login(db).await,
+    }
+}
+
+async fn restart(client: &TeleportClient, entry: &ProxyEntry, reason: &str) {
+    let restarted = match proxies::restart(client, entry.id) {
+        Ok(restarted) => restarted,
+        Err(e) => {
+            log(&format!("Could not restart proxy {} ({} {}): {}", entry.id, entry.kind(), entry.target(), e));
+            return;
+        }
+    };
+
+    // The AWS proxy comes back with new credentials; a profile's asks th for its own
+    if let ProxyRequest::Aws { endpoint_url: false, .. } = restarted.request {
+        if let Err(e) = aws::write_credentials_file(client.config(), &restarted).await {
+            log(&format!("Could not write credentials for {}: {}", restarted.target(), e));
+        }
+    }
+
+    log(&format!(
+        "Restarted proxy {} ({} {}) - {}, restart #{}",
+        restarted.id, restarted.kind(), restarted.target(), reason, restarted.restarts
+    ));
+}
+
+fn log(message: &str) {
+    println!("[{}] {}", Local::now().format("%Y-%m-%d %H:%M:%S"), message);
+}
@@ -33,34 +33,30 @@ for %%F in ("%TH_DIR%src\*.rs") do (
 )
 
 :run_binary
-REM Create temporary files for credential handling (Windows equivalent)
-set "TEMP_FILE=%TEMP%\th_aws_creds_%RANDOM%.bat"
-
-REM Run the Rust binary and capture AWS credential output
-"%TH_DIR%target\release\th.exe" %* > "%TEMP_FILE%" 2>&1
-
-REM Check if the output contains AWS credential exports
-findstr /C:"export AWS_" "%TEMP_FILE%" >nul 2>&1
-if %errorlevel% equ 0 (
-    REM Convert Unix export commands to Windows set commands
-    for /f "tokens=*" %%i in ('findstr /C:"export AWS_" "%TEMP_FILE%"') do (
-        set "line=%%i"
-        REM Convert "export VAR=value" to "set VAR=value"
-        set "line=!line:export =set !"
-        REM Execute the set command
-        !line!
-    )
-    echo AWS credentials have been set in the current session.
-) else (
-    REM No AWS credentials, just display the output
-    type "%TEMP_FILE%"
-)
-
-REM Get the exit code from the Rust binary
+"%TH_DIR%target\release\th.exe" %*
 set "EXIT_CODE=%errorlevel%"
 
-REM Clean up temporary file
-if exist "%TEMP_FILE%" del "%TEMP_FILE%"
+REM After AWS and logout commands, have th write the credential changes as cmd commands
+set "ENV_FILE=%TEMP%\th_env_%RANDOM%.bat"
+set "ENV_ARGS="
+REM --no-login: pick up the session the command left, never log in or start a proxy
+if /i "%~1"=="aws" set "ENV_ARGS=aws env --no-login --shell cmd"
+if /i "%~1"=="a" set "ENV_ARGS=aws env --no-login --shell cmd"
+if /i "%~2"=="-h" set "ENV_ARGS="
+if /i "%~2"=="--help" set "ENV_ARGS="
+if /i "%~2"=="env" set "ENV_ARGS="
+if /i "%~1"=="logout" set "ENV_ARGS=aws env --unset --shell cmd"
+if /i "%~1"=="l" set "ENV_ARGS=aws env --unset --shell cmd"
+if defined ENV_ARGS (
+    "%TH_DIR%target\release\th.exe" %ENV_ARGS% > "%ENV_FILE%" 2>nul
+    if errorlevel 1 "%TH_DIR%target\release\th.exe" aws env --unset --shell cmd > "%ENV_FILE%"
+)
 
-REM Preserve exit code
-exit /b %EXIT_CODE%
\ No newline at end of file
+REM Variables set inside setlocal are lost - apply the file after endlocal
+endlocal & set "TH_EXIT_CODE=%EXIT_CODE%" & set "TH_ENV_FILE=%ENV_FILE%"
+if exist "%TH_ENV_FILE%" (
+    call "%TH_ENV_FILE%"
+    del "%TH_ENV_FILE%"
+)
+set "TH_ENV_FILE="
+set "TH_EXIT_CODE=" & exit /b %TH_EXIT_CODE%
\ No newline at end of file